@@ -25,11 +25,17 @@ impl<C> FederationPeer<C>
 where
     C: JsonRpcClient + 'static,
 {
-    pub fn new(url: SafeUrl, peer_id: PeerId, api_secret: Option<String>) -> Self {
+    pub fn new(
+        url: SafeUrl,
+        peer_id: PeerId,
+        api_secret: Option<String>,
+        reconnect_policy: ReconnectPolicy,
+    ) -> Self {
         let client = RwLock::new(FederationPeerClient::new(
             peer_id,
             url.clone(),
             api_secret.clone(),
+            reconnect_policy,
         ));
 
         Self {
@@ -53,9 +59,14 @@ impl<C> FederationPeerClient<C>
 where
     C: JsonRpcClient + 'static,
 {
-    fn new(peer_id: PeerId, url: SafeUrl, api_secret: Option<String>) -> Self {
+    fn new(
+        peer_id: PeerId,
+        url: SafeUrl,
+        api_secret: Option<String>,
+        reconnect_policy: ReconnectPolicy,
+    ) -> Self {
         let connection_state = Arc::new(tokio::sync::Mutex::new(
-            FederationPeerClientConnectionState::new(),
+            FederationPeerClientConnectionState::new(reconnect_policy),
         ));
 
         Self {
@@ -132,24 +143,26 @@ struct FederationPeerClientConnectionState {
     /// Last time a connection attempt was made, or `None` if no attempt has
     /// been made yet.
     last_connection_attempt_or: Option<SystemTime>,
-    connection_backoff: backon::FibonacciBackoff,
+    reconnect_policy: ReconnectPolicy,
+    connection_backoff: ReconnectBackoff,
 }
 
 impl FederationPeerClientConnectionState {
-    const MIN_BACKOFF: Duration = Duration::from_millis(100);
-    const MAX_BACKOFF: Duration = Duration::from_secs(5);
-
-    fn new() -> Self {
+    fn new(reconnect_policy: ReconnectPolicy) -> Self {
         Self {
             last_connection_attempt_or: None,
-            connection_backoff: Self::new_backoff(),
+            reconnect_policy,
+            connection_backoff: reconnect_policy.backoff(),
         }
     }
 
     /// Wait (if needed) before reconnection attempt based on number of previous
     /// attempts and update reconnection stats.
     async fn wait(&mut self) {
-        let desired_timeout = self.connection_backoff.next().unwrap_or(Self::MAX_BACKOFF);
+        let desired_timeout = self
+            .connection_backoff
+            .next()
+            .unwrap_or(self.reconnect_policy.max);
         let since_last_connect = match self.last_connection_attempt_or {
             Some(last) => now().duration_since(last).unwrap_or_default(),
             None => Duration::ZERO,
@@ -168,13 +181,111 @@ impl FederationPeerClientConnectionState {
     }
 
     fn reset(&mut self) {
-        *self = Self::new();
+        *self = Self::new(self.reconnect_policy);
+    }
+}
+
+/// How long to wait between attempts to reconnect to a peer
+///
+/// Every peer backs off on its own: the first attempt waits `initial`, and
+/// the delay grows with every failed attempt up to `max`. By default it grows
+/// like the Fibonacci sequence, with a `multiplier` every failed attempt
+/// multiplies the delay by it instead. With `jitter` a random fraction of the
+/// delay is added on top, so many clients don't reconnect in lockstep.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReconnectPolicy {
+    pub initial: Duration,
+    pub max: Duration,
+    pub multiplier: Option<f32>,
+    pub jitter: bool,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(5),
+            multiplier: None,
+            jitter: false,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Delays before each consecutive reconnection attempt
+    pub fn backoff(&self) -> ReconnectBackoff {
+        match self.multiplier {
+            None => {
+                let builder = backon::FibonacciBuilder::default()
+                    .with_min_delay(self.initial)
+                    .with_max_delay(self.max);
+                ReconnectBackoff::Fibonacci(if self.jitter {
+                    builder.with_jitter().build()
+                } else {
+                    builder.build()
+                })
+            }
+            Some(multiplier) => {
+                let builder = backon::ExponentialBuilder::default()
+                    .with_min_delay(self.initial)
+                    .with_max_delay(self.max)
+                    .with_factor(multiplier)
+                    .with_max_times(usize::MAX);
+                ReconnectBackoff::Exponential(if self.jitter {
+                    builder.with_jitter().build()
+                } else {
+                    builder.build()
+                })
+            }
+        }
     }
+}
+
+/// Delays between reconnection attempts following a [`ReconnectPolicy`]
+#[derive(Debug)]
+pub enum ReconnectBackoff {
+    Fibonacci(backon::FibonacciBackoff),
+    Exponential(backon::ExponentialBackoff),
+}
+
+impl Iterator for ReconnectBackoff {
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Duration> {
+        match self {
+            ReconnectBackoff::Fibonacci(backoff) => backoff.next(),
+            ReconnectBackoff::Exponential(backoff) => backoff.next(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_reconnect_backoff_is_fibonacci() {
+        assert_eq!(
+            ReconnectPolicy::default()
+                .backoff()
+                .take(3)
+                .collect::<Vec<_>>(),
+            [100, 100, 200].map(Duration::from_millis).to_vec()
+        );
+    }
+
+    #[test]
+    fn reconnect_backoff_follows_policy() {
+        let policy = ReconnectPolicy {
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(10),
+            multiplier: Some(2.0),
+            jitter: false,
+        };
 
-    fn new_backoff() -> backon::FibonacciBackoff {
-        backon::FibonacciBuilder::default()
-            .with_min_delay(Self::MIN_BACKOFF)
-            .with_max_delay(Self::MAX_BACKOFF)
-            .build()
+        assert_eq!(
+            policy.backoff().take(6).collect::<Vec<_>>(),
+            [1, 2, 4, 8, 10, 10].map(Duration::from_secs).to_vec()
+        );
     }
 }
@@ -1,7 +1,8 @@
 use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fmt::{self, Debug, Display};
+use std::ops::Deref;
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 use std::time::Duration;
 use std::{cmp, result};
 
@@ -49,12 +50,13 @@ use serde_json::Value;
 use thiserror::Error;
 use tracing::{debug, error, instrument, trace, warn};
 
-use crate::query::{QueryStep, QueryStrategy, ThresholdConsensus};
+use crate::query::{QueryStep, QueryStrategy, ReportProgress, ThresholdConsensus};
 
 mod federation_peer_client;
 mod global_federation_api_with_cache;
 
 use federation_peer_client::FederationPeer;
+pub use federation_peer_client::{ReconnectBackoff, ReconnectPolicy};
 use global_federation_api_with_cache::GlobalFederationApiWithCache;
 
 pub type PeerResult<T> = Result<T, PeerError>;
@@ -272,7 +274,7 @@ pub trait IRawFederationApi: Debug + MaybeSend + MaybeSync {
 /// Set of api versions for each component (core + modules)
 ///
 /// E.g. result of federated common api versions discovery.
-#[derive(Debug, Clone, Serialize, Deserialize, Encodable, Decodable)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Encodable, Decodable)]
 pub struct ApiVersionSet {
     pub core: ApiVersion,
     pub modules: BTreeMap<ModuleInstanceId, ApiVersion>,
@@ -567,6 +569,15 @@ impl DynGlobalApi {
         .into()
     }
 
+    /// Build a global API on top of a raw API, e.g. one wrapping another
+    /// [`DynGlobalApi`] to intercept its requests
+    pub fn from_raw<T>(raw: T) -> Self
+    where
+        T: IRawFederationApi + MaybeSend + MaybeSync + 'static,
+    {
+        GlobalFederationApiWithCache::new(raw).into()
+    }
+
     pub fn from_invite_code(invite_code: &InviteCode) -> Self {
         GlobalFederationApiWithCache::new(WsFederationApi::new(
             invite_code.peers().into_iter().collect_vec(),
@@ -575,6 +586,32 @@ impl DynGlobalApi {
         .into()
     }
 
+    /// Like [`FederationApiExt::request_current_consensus`], but calling
+    /// `progress` with the number of guardians that agree on a response so
+    /// far and the number of guardians needed for consensus as they reply
+    pub async fn await_with_quorum_progress<Ret>(
+        &self,
+        method: String,
+        params: ApiRequestErased,
+        progress: impl FnMut(usize, usize) + MaybeSend,
+    ) -> FederationResult<Ret>
+    where
+        Ret: serde::de::DeserializeOwned + Eq + Debug + Clone + MaybeSend,
+    {
+        let num_peers = self.all_peers().to_num_peers();
+        self.inner
+            .request_with_strategy(
+                ReportProgress::new(
+                    ThresholdConsensus::new(num_peers),
+                    num_peers.threshold(),
+                    progress,
+                ),
+                method,
+                params,
+            )
+            .await
+    }
+
     pub async fn await_output_outcome<R>(
         &self,
         outpoint: OutPoint,
@@ -586,10 +623,18 @@ impl DynGlobalApi {
     {
         fedimint_core::runtime::timeout(timeout, async {
             let outcome: SerdeOutputOutcome = self
-                .inner
-                .request_current_consensus(
+                .await_with_quorum_progress(
                     AWAIT_OUTPUT_OUTCOME_ENDPOINT.to_owned(),
                     ApiRequestErased::new(outpoint),
+                    |confirmed, threshold| {
+                        trace!(
+                            target: LOG_CLIENT_NET_API,
+                            %outpoint,
+                            confirmed,
+                            threshold,
+                            "Guardian returned output outcome"
+                        );
+                    },
                 )
                 .await
                 .map_err(OutputOutcomeError::Federation)?;
@@ -805,6 +850,167 @@ impl<C: JsonRpcClient + Debug + 'static> IRawFederationApi for WsFederationApi<C
     }
 }
 
+/// Federation API whose underlying API can be replaced while it is in use,
+/// e.g. to authenticate with a new API secret
+///
+/// All clones, including the module APIs created by
+/// [`IRawFederationApi::with_module`], send their requests using the API
+/// passed to the most recent [`SwappableApi::swap`].
+#[derive(Clone)]
+pub struct SwappableApi {
+    current: Arc<RwLock<DynGlobalApi>>,
+    module_id: Option<ModuleInstanceId>,
+    peers: BTreeSet<PeerId>,
+}
+
+impl SwappableApi {
+    pub fn new(api: DynGlobalApi) -> Self {
+        Self {
+            peers: api.all_peers().clone(),
+            current: Arc::new(RwLock::new(api)),
+            module_id: None,
+        }
+    }
+
+    /// Replace the underlying API, which has to talk to the same peers
+    pub fn swap(&self, api: DynGlobalApi) {
+        assert_eq!(
+            api.all_peers(),
+            &self.peers,
+            "Swapped API must use the same peers"
+        );
+        *self.current.write().expect("Lock poisoned") = api;
+    }
+
+    fn current(&self) -> DynGlobalApi {
+        self.current.read().expect("Lock poisoned").clone()
+    }
+}
+
+impl Debug for SwappableApi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SwappableApi")
+            .field("module_id", &self.module_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl IModuleFederationApi for SwappableApi {}
+
+#[apply(async_trait_maybe_send!)]
+impl IRawFederationApi for SwappableApi {
+    fn all_peers(&self) -> &BTreeSet<PeerId> {
+        &self.peers
+    }
+
+    fn self_peer(&self) -> Option<PeerId> {
+        self.current().self_peer()
+    }
+
+    fn with_module(&self, id: ModuleInstanceId) -> DynModuleApi {
+        Self {
+            module_id: Some(id),
+            ..self.clone()
+        }
+        .into()
+    }
+
+    async fn request_raw(
+        &self,
+        peer_id: PeerId,
+        method: &str,
+        params: &[Value],
+    ) -> JsonRpcResult<Value> {
+        let current = self.current();
+        match self.module_id {
+            Some(id) => {
+                current
+                    .with_module(id)
+                    .request_raw(peer_id, method, params)
+                    .await
+            }
+            None => current.request_raw(peer_id, method, params).await,
+        }
+    }
+}
+
+/// Prefix of the names of endpoints that block until an event happens
+pub const LONG_POLL_ENDPOINT_PREFIX: &str = "await_";
+
+/// Federation API failing every request to a single peer that takes longer
+/// than `timeout`
+///
+/// Timed out requests return [`JsonRpcClientError::RequestTimeout`], which
+/// query strategies handle like any other peer error, so one slow guardian
+/// can't stall a call that the remaining peers can answer.
+///
+/// Long-polling endpoints, whose names start with [`LONG_POLL_ENDPOINT_PREFIX`]
+/// like `await_transaction` or `await_output_outcome`, only return once the
+/// awaited event happened and are never timed out.
+#[derive(Clone)]
+pub struct RequestTimeoutApi<T> {
+    inner: T,
+    timeout: Duration,
+}
+
+impl<T> RequestTimeoutApi<T> {
+    pub fn new(inner: T, timeout: Duration) -> Self {
+        Self { inner, timeout }
+    }
+}
+
+impl<T> Debug for RequestTimeoutApi<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RequestTimeoutApi")
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> IModuleFederationApi for RequestTimeoutApi<T>
+where
+    T: Deref + MaybeSend + MaybeSync + 'static,
+    T::Target: IRawFederationApi,
+{
+}
+
+#[apply(async_trait_maybe_send!)]
+impl<T> IRawFederationApi for RequestTimeoutApi<T>
+where
+    T: Deref + MaybeSend + MaybeSync + 'static,
+    T::Target: IRawFederationApi,
+{
+    fn all_peers(&self) -> &BTreeSet<PeerId> {
+        self.inner.all_peers()
+    }
+
+    fn self_peer(&self) -> Option<PeerId> {
+        self.inner.self_peer()
+    }
+
+    fn with_module(&self, id: ModuleInstanceId) -> DynModuleApi {
+        RequestTimeoutApi::new(self.inner.with_module(id), self.timeout).into()
+    }
+
+    async fn request_raw(
+        &self,
+        peer_id: PeerId,
+        method: &str,
+        params: &[Value],
+    ) -> JsonRpcResult<Value> {
+        if method.starts_with(LONG_POLL_ENDPOINT_PREFIX) {
+            return self.inner.request_raw(peer_id, method, params).await;
+        }
+
+        runtime::timeout(
+            self.timeout,
+            self.inner.request_raw(peer_id, method, params),
+        )
+        .await
+        .unwrap_or(Err(JsonRpcClientError::RequestTimeout))
+    }
+}
+
 #[apply(async_trait_maybe_send!)]
 pub trait JsonRpcClient: ClientT + Sized + MaybeSend + MaybeSync {
     async fn connect(
@@ -868,19 +1074,31 @@ impl JsonRpcClient for WsClient {
 impl WsFederationApi<WsClient> {
     /// Creates a new API client
     pub fn new(peers: Vec<(PeerId, SafeUrl)>, api_secret: &Option<String>) -> Self {
-        Self::new_with_client(peers, None, api_secret)
+        Self::new_with_client(peers, None, api_secret, ReconnectPolicy::default())
     }
 
     /// Creates a new API client from a client config
     pub fn from_config(config: &ClientConfig, api_secret: &Option<String>) -> Self {
-        Self::new(
+        Self::from_config_with_reconnect_policy(config, api_secret, ReconnectPolicy::default())
+    }
+
+    /// Creates a new API client from a client config, reconnecting to peers
+    /// according to `reconnect_policy`
+    pub fn from_config_with_reconnect_policy(
+        config: &ClientConfig,
+        api_secret: &Option<String>,
+        reconnect_policy: ReconnectPolicy,
+    ) -> Self {
+        Self::new_with_client(
             config
                 .global
                 .api_endpoints
                 .iter()
                 .map(|(id, peer)| (*id, peer.url.clone()))
                 .collect(),
+            None,
             api_secret,
+            reconnect_policy,
         )
     }
 
@@ -905,6 +1123,7 @@ where
         peers: Vec<(PeerId, SafeUrl)>,
         self_peer_id: Option<PeerId>,
         api_secret: &Option<String>,
+        reconnect_policy: ReconnectPolicy,
     ) -> Self {
         WsFederationApi {
             peer_ids: peers.iter().map(|m| m.0).collect(),
@@ -919,7 +1138,7 @@ where
                         );
                         assert!(url.host().is_some(), "API client requires a target host");
 
-                        FederationPeer::new(url, peer_id, api_secret.clone())
+                        FederationPeer::new(url, peer_id, api_secret.clone(), reconnect_policy)
                     })
                     .collect(),
             ),
@@ -1032,6 +1251,7 @@ mod tests {
     use std::str::FromStr as _;
 
     use fedimint_core::config::FederationId;
+    use fedimint_core::endpoint_constants::{AWAIT_TRANSACTION_ENDPOINT, SESSION_COUNT_ENDPOINT};
     use jsonrpsee_core::client::BatchResponse;
     use jsonrpsee_core::params::BatchRequestBuilder;
     use jsonrpsee_core::traits::ToRpcParams;
@@ -1092,6 +1312,64 @@ mod tests {
         }
     }
 
+    /// Answers every request after `delay`
+    struct SlowApi {
+        peers: BTreeSet<PeerId>,
+        delay: Duration,
+    }
+
+    impl IModuleFederationApi for SlowApi {}
+
+    #[apply(async_trait_maybe_send!)]
+    impl IRawFederationApi for SlowApi {
+        fn all_peers(&self) -> &BTreeSet<PeerId> {
+            &self.peers
+        }
+
+        fn self_peer(&self) -> Option<PeerId> {
+            None
+        }
+
+        fn with_module(&self, _id: ModuleInstanceId) -> DynModuleApi {
+            unimplemented!()
+        }
+
+        async fn request_raw(
+            &self,
+            _peer_id: PeerId,
+            _method: &str,
+            _params: &[Value],
+        ) -> JsonRpcResult<Value> {
+            runtime::sleep(self.delay).await;
+            Ok(Value::Null)
+        }
+    }
+
+    #[tokio::test]
+    async fn request_timeout_exempts_long_polls() {
+        let api = RequestTimeoutApi::new(
+            Arc::new(SlowApi {
+                peers: BTreeSet::from([PeerId::from(0)]),
+                delay: Duration::from_millis(200),
+            }),
+            Duration::from_millis(20),
+        );
+
+        assert!(matches!(
+            api.request_raw(PeerId::from(0), SESSION_COUNT_ENDPOINT, &[])
+                .await,
+            Err(JsonRpcClientError::RequestTimeout)
+        ));
+        for long_poll in [AWAIT_TRANSACTION_ENDPOINT, AWAIT_OUTPUT_OUTCOME_ENDPOINT] {
+            assert_eq!(
+                api.request_raw(PeerId::from(0), long_poll, &[])
+                    .await
+                    .expect("Long polls aren't timed out"),
+                Value::Null
+            );
+        }
+    }
+
     #[test]
     fn converts_invite_code() {
         let connect = InviteCode::new(
@@ -5,17 +5,18 @@
 #![allow(clippy::must_use_candidate)]
 #![allow(clippy::return_self_not_must_use)]
 
+use std::collections::{BTreeMap, BTreeSet};
 use std::time::Duration;
 
 use anyhow::{bail, Context as _};
-use api::{DynGlobalApi, FederationApiExt as _, WsFederationApi};
-use fedimint_core::config::ClientConfig;
+use api::{DynGlobalApi, FederationApiExt as _, IRawFederationApi as _, WsFederationApi};
+use fedimint_core::config::{ClientConfig, FederationId};
 use fedimint_core::encoding::Encodable as _;
 use fedimint_core::endpoint_constants::CLIENT_CONFIG_ENDPOINT;
 use fedimint_core::invite_code::InviteCode;
 use fedimint_core::module::ApiRequestErased;
-use fedimint_core::util::backon;
-use fedimint_core::NumPeers;
+use fedimint_core::util::{backon, SafeUrl};
+use fedimint_core::{NumPeers, PeerId};
 use query::FilterMap;
 use tracing::debug;
 
@@ -44,7 +45,123 @@ pub async fn download_from_invite_code(invite_code: &InviteCode) -> anyhow::Resu
 
 /// Tries to download the client config only once.
 pub async fn try_download_client_config(invite_code: &InviteCode) -> anyhow::Result<ClientConfig> {
-    // we have to download the api endpoints first
+    let federation_id = invite_code.federation_id();
+    let api_endpoints = download_api_endpoints(invite_code).await?;
+
+    let client_config = WsFederationApi::new(api_endpoints, &invite_code.api_secret())
+        .request_current_consensus::<ClientConfig>(
+            CLIENT_CONFIG_ENDPOINT.to_owned(),
+            ApiRequestErased::default(),
+        )
+        .await?;
+
+    if client_config.calculate_federation_id() != federation_id {
+        bail!("Obtained client config has different federation id");
+    }
+
+    Ok(client_config)
+}
+
+/// How many guardians served the same client config, see
+/// [`try_download_client_config_from_all`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConfigAgreement {
+    /// Number of guardians that returned a config
+    pub total: usize,
+    /// Number of guardians that returned a config byte-identical to the
+    /// returned one
+    pub matching: usize,
+}
+
+/// A client config together with a report on which guardians agreed on it
+#[derive(Debug, Clone)]
+pub struct VerifiedClientConfig {
+    pub config: ClientConfig,
+    pub agreement: ConfigAgreement,
+    /// Guardians that returned a config different from [`Self::config`]
+    pub diverging: BTreeSet<PeerId>,
+}
+
+/// How long [`try_download_client_config_from_all`] waits for each guardian
+pub const CLIENT_CONFIG_PEER_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Downloads the client config from every guardian individually and compares
+/// the responses, instead of trusting whichever guardians answer first.
+///
+/// This detects a single malicious guardian serving a bad config. Guardians
+/// that do not respond within [`CLIENT_CONFIG_PEER_TIMEOUT`] are not counted in
+/// [`ConfigAgreement::total`].
+pub async fn try_download_client_config_from_all(
+    invite_code: &InviteCode,
+) -> anyhow::Result<VerifiedClientConfig> {
+    let api_endpoints = download_api_endpoints(invite_code).await?;
+    let api = WsFederationApi::new(api_endpoints, &invite_code.api_secret());
+
+    let responses = futures::future::join_all(api.all_peers().iter().map(|&peer_id| {
+        let api = &api;
+        async move {
+            let response = api
+                .request_single_peer_typed::<ClientConfig>(
+                    Some(CLIENT_CONFIG_PEER_TIMEOUT),
+                    CLIENT_CONFIG_ENDPOINT.to_owned(),
+                    ApiRequestErased::default(),
+                    peer_id,
+                )
+                .await;
+            (peer_id, response)
+        }
+    }))
+    .await
+    .into_iter()
+    .filter_map(|(peer_id, response)| match response {
+        Ok(config) => Some((peer_id, config)),
+        Err(error) => {
+            debug!(%peer_id, %error, "Guardian did not return a client config");
+            None
+        }
+    })
+    .collect();
+
+    verify_config_agreement(invite_code.federation_id(), responses)
+}
+
+/// Picks the config returned by most guardians among those matching
+/// `federation_id` and reports the guardians that disagree with it
+fn verify_config_agreement(
+    federation_id: FederationId,
+    responses: BTreeMap<PeerId, ClientConfig>,
+) -> anyhow::Result<VerifiedClientConfig> {
+    let responded: BTreeSet<PeerId> = responses.keys().copied().collect();
+
+    let mut by_encoding: BTreeMap<Vec<u8>, (ClientConfig, BTreeSet<PeerId>)> = BTreeMap::new();
+    for (peer_id, config) in responses {
+        by_encoding
+            .entry(config.consensus_encode_to_vec())
+            .or_insert_with(|| (config, BTreeSet::new()))
+            .1
+            .insert(peer_id);
+    }
+
+    let (config, peers) = by_encoding
+        .into_values()
+        .filter(|(config, _)| config.calculate_federation_id() == federation_id)
+        .max_by_key(|(_, peers)| peers.len())
+        .context("No guardian returned a client config matching the federation id")?;
+
+    Ok(VerifiedClientConfig {
+        config,
+        agreement: ConfigAgreement {
+            total: responded.len(),
+            matching: peers.len(),
+        },
+        diverging: responded.difference(&peers).copied().collect(),
+    })
+}
+
+/// Downloads the guardian api endpoints using the peers from the invite code
+async fn download_api_endpoints(
+    invite_code: &InviteCode,
+) -> anyhow::Result<Vec<(PeerId, SafeUrl)>> {
     let federation_id = invite_code.federation_id();
 
     let query_strategy = FilterMap::new(
@@ -66,22 +183,91 @@ pub async fn try_download_client_config(invite_code: &InviteCode) -> anyhow::Res
         )
         .await?;
 
-    // now we can build an api for all guardians and download the client config
-    let api_endpoints = api_endpoints
+    Ok(api_endpoints
         .into_iter()
         .map(|(peer, url)| (peer, url.url))
-        .collect();
+        .collect())
+}
 
-    let client_config = WsFederationApi::new(api_endpoints, &invite_code.api_secret())
-        .request_current_consensus::<ClientConfig>(
-            CLIENT_CONFIG_ENDPOINT.to_owned(),
-            ApiRequestErased::default(),
-        )
-        .await?;
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
 
-    if client_config.calculate_federation_id() != federation_id {
-        bail!("Obtained client config has different federation id");
+    use fedimint_core::config::{ClientConfig, GlobalClientConfig, PeerUrl};
+    use fedimint_core::module::CoreConsensusVersion;
+    use fedimint_core::PeerId;
+
+    use super::{verify_config_agreement, ConfigAgreement};
+
+    fn config(meta: &str) -> ClientConfig {
+        let api_endpoints = (0..3)
+            .map(|i| {
+                let url = PeerUrl {
+                    url: format!("ws://guardian{i}").parse().expect("valid url"),
+                    name: format!("guardian{i}"),
+                };
+                (PeerId::from(i), url)
+            })
+            .collect();
+
+        ClientConfig {
+            global: GlobalClientConfig {
+                api_endpoints,
+                consensus_version: CoreConsensusVersion::new(0, 0),
+                meta: BTreeMap::from([("federation_name".to_owned(), meta.to_owned())]),
+            },
+            modules: BTreeMap::new(),
+        }
     }
 
-    Ok(client_config)
+    #[test]
+    fn diverging_guardian_config_is_reported() {
+        let honest = config("honest");
+        let federation_id = honest.calculate_federation_id();
+
+        let responses = BTreeMap::from([
+            (PeerId::from(0), honest.clone()),
+            (PeerId::from(1), config("malicious")),
+            (PeerId::from(2), honest.clone()),
+        ]);
+
+        let verified = verify_config_agreement(federation_id, responses).unwrap();
+        assert_eq!(verified.config, honest);
+        assert_eq!(
+            verified.agreement,
+            ConfigAgreement {
+                total: 3,
+                matching: 2
+            }
+        );
+        assert_eq!(verified.diverging, [PeerId::from(1)].into());
+    }
+
+    #[test]
+    fn config_with_wrong_federation_id_is_never_chosen() {
+        let honest = config("honest");
+        let federation_id = honest.calculate_federation_id();
+
+        let mut malicious = config("malicious");
+        malicious
+            .global
+            .api_endpoints
+            .get_mut(&PeerId::from(0))
+            .unwrap()
+            .name = "evil".to_owned();
+
+        let responses = BTreeMap::from([
+            (PeerId::from(0), malicious.clone()),
+            (PeerId::from(1), malicious),
+            (PeerId::from(2), honest.clone()),
+        ]);
+
+        let verified = verify_config_agreement(federation_id, responses).unwrap();
+        assert_eq!(verified.config, honest);
+        assert_eq!(verified.agreement.matching, 1);
+        assert_eq!(
+            verified.diverging,
+            [PeerId::from(0), PeerId::from(1)].into()
+        );
+    }
 }
@@ -163,6 +163,57 @@ impl<R, T> QueryStrategy<R, BTreeMap<PeerId, T>> for FilterMapThreshold<R, T> {
     }
 }
 
+/// Wraps another strategy, calling `progress` with the number of peers that
+/// agree on the most common response so far and the `threshold` the query
+/// needs, e.g. to show "2 of 3 guardians confirmed" in a UI. Responses that
+/// differ from the result the query converges on don't count towards it.
+pub struct ReportProgress<S, R, F> {
+    inner: S,
+    progress: F,
+    responses: BTreeMap<PeerId, R>,
+    confirmed: usize,
+    threshold: usize,
+}
+
+impl<S, R, F> ReportProgress<S, R, F> {
+    pub fn new(inner: S, threshold: usize, progress: F) -> Self {
+        Self {
+            inner,
+            progress,
+            responses: BTreeMap::new(),
+            confirmed: 0,
+            threshold,
+        }
+    }
+}
+
+impl<IR, OR, S, F> QueryStrategy<IR, OR> for ReportProgress<S, IR, F>
+where
+    IR: Eq + Clone,
+    S: QueryStrategy<IR, OR>,
+    F: FnMut(usize, usize),
+{
+    fn process(&mut self, peer: PeerId, result: PeerResult<IR>) -> QueryStep<OR> {
+        if let Ok(response) = &result {
+            // Retried peers replace their previous response
+            self.responses.insert(peer, response.clone());
+
+            let confirmed = self
+                .responses
+                .values()
+                .map(|response| self.responses.values().filter(|r| *r == response).count())
+                .max()
+                .unwrap_or_default();
+            if confirmed != self.confirmed {
+                self.confirmed = confirmed;
+                (self.progress)(confirmed, self.threshold);
+            }
+        }
+
+        self.inner.process(peer, result)
+    }
+}
+
 /// Returns when we obtain a threshold of identical responses. Responses are not
 /// assumed to be static and may be updated by the peers; on failure to
 /// establish consensus with a threshold of responses, we retry the requests.
@@ -209,3 +260,35 @@ impl<R: Eq> QueryStrategy<R> for ThresholdConsensus<R> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use fedimint_core::{NumPeers, PeerId};
+
+    use super::{QueryStep, QueryStrategy, ReportProgress, ThresholdConsensus};
+
+    #[test]
+    fn report_progress_counts_only_agreeing_responses() {
+        let num_peers = NumPeers::from(4);
+        let mut progress = vec![];
+        let mut strategy = ReportProgress::new(
+            ThresholdConsensus::new(num_peers),
+            num_peers.threshold(),
+            |confirmed, threshold| progress.push((confirmed, threshold)),
+        );
+
+        for (peer, response) in [(0, 1u64), (1, 2), (2, 2)] {
+            assert!(!matches!(
+                strategy.process(PeerId::from(peer), Ok(response)),
+                QueryStep::Success(_)
+            ));
+        }
+        assert!(matches!(
+            strategy.process(PeerId::from(3), Ok(2)),
+            QueryStep::Success(2)
+        ));
+
+        // The diverging response of peer 0 is never counted as a confirmation
+        assert_eq!(progress, vec![(1, 3), (2, 3), (3, 3)]);
+    }
+}
@@ -49,3 +49,72 @@ impl<const WORD_COUNT: usize> RootSecretStrategy for Bip39RootSecretStrategy<WOR
             .expect("Failed to generate mnemonic, bad word count")
     }
 }
+
+impl<const WORD_COUNT: usize> Bip39RootSecretStrategy<WORD_COUNT> {
+    /// Parses a seed phrase entered by the user, e.g. to restore a client from
+    /// it. Surrounding whitespace and the case of the words are ignored.
+    ///
+    /// # Errors
+    /// If the phrase contains unknown words, has a word count other than
+    /// `WORD_COUNT` or an invalid checksum.
+    pub fn parse_mnemonic(phrase: &str) -> Result<bip39::Mnemonic, bip39::Error> {
+        let phrase = phrase
+            .split_whitespace()
+            .map(str::to_lowercase)
+            .collect::<Vec<_>>()
+            .join(" ");
+        let mnemonic = bip39::Mnemonic::parse_in_normalized(bip39::Language::English, &phrase)?;
+        if mnemonic.word_count() != WORD_COUNT {
+            return Err(bip39::Error::BadWordCount(mnemonic.word_count()));
+        }
+        Ok(mnemonic)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fedimint_client::secret::RootSecretStrategy;
+    use rand::rngs::OsRng;
+
+    use super::Bip39RootSecretStrategy;
+
+    #[test]
+    fn generated_mnemonic_roundtrips_to_same_root_secret() {
+        let mnemonic = Bip39RootSecretStrategy::<12>::random(&mut OsRng);
+        let parsed = Bip39RootSecretStrategy::<12>::parse_mnemonic(&mnemonic.to_string())
+            .expect("Generated mnemonic is valid");
+
+        assert_eq!(
+            Bip39RootSecretStrategy::<12>::to_root_secret(&mnemonic).to_random_bytes::<32>(),
+            Bip39RootSecretStrategy::<12>::to_root_secret(&parsed).to_random_bytes::<32>()
+        );
+    }
+
+    #[test]
+    fn invalid_mnemonics_are_rejected() {
+        let valid = format!("{} about", ["abandon"; 11].join(" "));
+        assert!(Bip39RootSecretStrategy::<12>::parse_mnemonic(&valid).is_ok());
+
+        let bad_checksum = ["abandon"; 12].join(" ");
+        assert_eq!(
+            Bip39RootSecretStrategy::<12>::parse_mnemonic(&bad_checksum),
+            Err(bip39::Error::InvalidChecksum)
+        );
+        assert_eq!(
+            Bip39RootSecretStrategy::<24>::parse_mnemonic(&valid),
+            Err(bip39::Error::BadWordCount(12))
+        );
+        assert!(Bip39RootSecretStrategy::<12>::parse_mnemonic("not a mnemonic").is_err());
+    }
+
+    #[test]
+    fn mnemonic_input_is_normalized() {
+        let valid = format!("{} about", ["abandon"; 11].join(" "));
+        let messy = format!("  {}\n", valid.to_uppercase().replace(' ', "  "));
+
+        assert_eq!(
+            Bip39RootSecretStrategy::<12>::parse_mnemonic(&messy),
+            Bip39RootSecretStrategy::<12>::parse_mnemonic(&valid)
+        );
+    }
+}
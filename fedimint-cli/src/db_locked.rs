@@ -78,4 +78,12 @@ where
     fn checkpoint(&self, backup_path: &Path) -> anyhow::Result<()> {
         self.inner.checkpoint(backup_path)
     }
+
+    async fn size_on_disk(&self) -> anyhow::Result<u64> {
+        self.inner.size_on_disk().await
+    }
+
+    async fn key_count(&self) -> anyhow::Result<u64> {
+        self.inner.key_count().await
+    }
 }
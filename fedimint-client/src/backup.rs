@@ -2,7 +2,8 @@ use std::cmp::Reverse;
 use std::collections::{BTreeMap, BTreeSet};
 use std::io::{Cursor, Error, Read, Write};
 
-use anyhow::{bail, Context, Result};
+use anyhow::{bail, ensure, Context, Result};
+use bitcoin::hashes::{sha256, Hash};
 use fedimint_api_client::api::DynGlobalApi;
 use fedimint_core::core::backup::{
     BackupRequest, SignedBackupRequest, BACKUP_REQUEST_MAX_PAYLOAD_SIZE_BYTES,
@@ -11,7 +12,7 @@ use fedimint_core::core::ModuleInstanceId;
 use fedimint_core::db::IDatabaseTransactionOpsCoreTyped;
 use fedimint_core::encoding::{Decodable, DecodeError, Encodable};
 use fedimint_core::module::registry::ModuleDecoderRegistry;
-use fedimint_derive_secret::DerivableSecret;
+use fedimint_derive_secret::{ChildId, DerivableSecret};
 use fedimint_logging::{LOG_CLIENT, LOG_CLIENT_BACKUP, LOG_CLIENT_RECOVERY};
 use secp256k1_zkp::{KeyPair, Secp256k1};
 use serde::{Deserialize, Serialize};
@@ -23,6 +24,25 @@ use crate::get_decoded_client_secret;
 use crate::module::recovery::DynModuleBackup;
 use crate::secret::DeriveableSecretClientExt;
 
+/// Child of the backup secret whose key signs the manifest of a chunked backup
+const CHUNKED_BACKUP_MANIFEST: ChildId = ChildId(0);
+/// Child of the backup secret whose children sign the segments of a chunked
+/// backup
+const CHUNKED_BACKUP_SEGMENTS: ChildId = ChildId(1);
+
+/// Maximum number of segments of a chunked backup
+///
+/// Guardians store every segment as a separate backup record and never delete
+/// backup records, so a client occupies up to this many segment records in
+/// addition to its regular backup and the manifest. Segments of an earlier,
+/// larger backup stay behind when a smaller one is uploaded, the cap bounds
+/// how many can accumulate.
+pub const MAX_CHUNKED_BACKUP_SEGMENTS: usize = 8;
+
+/// Maximum total size of a chunked backup
+pub const MAX_CHUNKED_BACKUP_SIZE_BYTES: usize =
+    MAX_CHUNKED_BACKUP_SEGMENTS * BACKUP_REQUEST_MAX_PAYLOAD_SIZE_BYTES;
+
 /// Backup metadata
 ///
 /// A backup can have a blob of extra data encoded in it. We provide methods to
@@ -242,12 +262,26 @@ impl EncryptedClientBackup {
     }
 }
 
+/// Lists the segments of an [`EncryptedClientBackup`] too large for a single
+/// backup request
+///
+/// The manifest is stored encrypted under its own backup id, and every segment
+/// under an id derived from its index.
+#[derive(Debug, Clone, PartialEq, Eq, Encodable, Decodable)]
+pub struct ChunkedBackupManifest {
+    /// Session count of the backup, used to pick the most recent backup
+    pub session_count: u64,
+    /// Hashes of the segments in order, a segment not matching its hash is
+    /// left over from an interrupted upload
+    pub segment_hashes: Vec<sha256::Hash>,
+}
+
 impl Client {
     /// Create a backup, include provided `metadata`
     pub async fn create_backup(&self, metadata: Metadata) -> anyhow::Result<ClientBackup> {
         let session_count = self.api.session_count().await?;
         let mut modules = BTreeMap::new();
-        for (id, kind, module) in self.modules.iter_modules() {
+        for (id, kind, module) in self.iter_modules() {
             debug!(target: LOG_CLIENT_BACKUP, module_id=id, module_kind=%kind, "Preparing module backup");
             if module.supports_backup() {
                 let backup = module.backup(id).await?;
@@ -286,11 +320,18 @@ impl Client {
 
         let encrypted = new_backup.encrypt_to(&self.get_derived_backup_encryption_key())?;
 
-        self.validate_backup(&encrypted)?;
-
         self.store_last_backup(&new_backup).await;
 
-        self.upload_backup(&encrypted).await?;
+        if self.validate_backup(&encrypted).is_ok() {
+            self.upload_backup(&encrypted).await?;
+        } else {
+            self.upload_backup_chunked(
+                &encrypted,
+                new_backup.session_count,
+                BACKUP_REQUEST_MAX_PAYLOAD_SIZE_BYTES,
+            )
+            .await?;
+        }
 
         Ok(())
     }
@@ -322,6 +363,77 @@ impl Client {
         Ok(())
     }
 
+    /// Upload `backup`, created at `session_count`, in segments of at most
+    /// `segment_size` bytes, for backups too large for a single request
+    ///
+    /// The manifest listing the segments is uploaded last. Segments overwrite
+    /// the ones of previous chunked backups, so if the upload is interrupted
+    /// the previous manifest no longer matches and the backup is skipped on
+    /// download in favor of other valid backups.
+    ///
+    /// Fails for backups larger than [`MAX_CHUNKED_BACKUP_SIZE_BYTES`] or
+    /// needing more than [`MAX_CHUNKED_BACKUP_SEGMENTS`] segments.
+    pub async fn upload_backup_chunked(
+        &self,
+        backup: &EncryptedClientBackup,
+        session_count: u64,
+        segment_size: usize,
+    ) -> Result<()> {
+        ensure!(
+            0 < segment_size && segment_size <= BACKUP_REQUEST_MAX_PAYLOAD_SIZE_BYTES,
+            "Invalid backup segment size {segment_size}"
+        );
+        ensure!(
+            backup.len() <= MAX_CHUNKED_BACKUP_SIZE_BYTES,
+            "Backup of {} bytes exceeds the maximum of {MAX_CHUNKED_BACKUP_SIZE_BYTES} bytes",
+            backup.len()
+        );
+        let root_secret = self.root_secret();
+        let segments: Vec<_> = backup.0.chunks(segment_size).collect();
+        ensure!(
+            segments.len() <= MAX_CHUNKED_BACKUP_SEGMENTS,
+            "Backup needs {} segments, more than the maximum of {MAX_CHUNKED_BACKUP_SEGMENTS}",
+            segments.len()
+        );
+        info!(
+            target: LOG_CLIENT_BACKUP,
+            size = backup.len(),
+            segments = segments.len(),
+            "Uploading chunked backup to federation"
+        );
+
+        for (index, segment) in segments.iter().enumerate() {
+            let segment_request = EncryptedClientBackup(segment.to_vec()).into_backup_request(
+                &Self::get_derived_backup_segment_signing_key_static(&root_secret, index as u64),
+            )?;
+            self.api.upload_backup(&segment_request).await?;
+        }
+
+        let manifest = ChunkedBackupManifest {
+            session_count,
+            segment_hashes: segments
+                .iter()
+                .map(|segment| sha256::Hash::hash(segment))
+                .collect(),
+        };
+        let manifest_request = EncryptedClientBackup(fedimint_aead::encrypt(
+            manifest.consensus_encode_to_vec(),
+            &self.get_derived_backup_encryption_key(),
+        )?)
+        .into_backup_request(&Self::get_derived_backup_manifest_signing_key_static(
+            &root_secret,
+        ))?;
+        self.api.upload_backup(&manifest_request).await?;
+
+        info!(
+            target: LOG_CLIENT_BACKUP,
+            size = backup.len(),
+            segments = segments.len(),
+            "Uploaded chunked backup to federation"
+        );
+        Ok(())
+    }
+
     pub async fn download_backup_from_federation(&self) -> Result<Option<ClientBackup>> {
         Self::download_backup_from_federation_static(&self.api, &self.root_secret(), &self.decoders)
             .await
@@ -355,6 +467,17 @@ impl Client {
             })
             .collect();
 
+        match Self::download_chunked_backup_static(api, root_secret, decoders).await {
+            Ok(Some(backup)) => responses.push(backup),
+            Ok(None) => {}
+            Err(e) => {
+                warn!(
+                    target: LOG_CLIENT_RECOVERY,
+                    "Could not download chunked backup: {e:#}"
+                );
+            }
+        }
+
         debug!(
             target: LOG_CLIENT_RECOVERY,
             "Received {} valid responses",
@@ -366,6 +489,60 @@ impl Client {
         Ok(responses.into_iter().next())
     }
 
+    /// Download the most recent chunked backup, if any, reassembling it from
+    /// segments that match the manifest
+    async fn download_chunked_backup_static(
+        api: &DynGlobalApi,
+        root_secret: &DerivableSecret,
+        decoders: &ModuleDecoderRegistry,
+    ) -> Result<Option<ClientBackup>> {
+        let encryption_key = Self::get_derived_backup_encryption_key_static(root_secret);
+        let manifest_key = Self::get_derived_backup_manifest_signing_key_static(root_secret);
+        let Some(manifest) = api
+            .download_backup(&manifest_key.public_key())
+            .await?
+            .into_values()
+            .filter_map(|backup| {
+                let mut data = backup?.data;
+                let decrypted = fedimint_aead::decrypt(&mut data, &encryption_key).ok()?;
+                ChunkedBackupManifest::consensus_decode_vec(
+                    decrypted.to_vec(),
+                    &ModuleDecoderRegistry::default(),
+                )
+                .ok()
+            })
+            .max_by_key(|manifest| manifest.session_count)
+        else {
+            return Ok(None);
+        };
+        ensure!(
+            manifest.segment_hashes.len() <= MAX_CHUNKED_BACKUP_SEGMENTS,
+            "Chunked backup has {} segments, the maximum is {MAX_CHUNKED_BACKUP_SEGMENTS}",
+            manifest.segment_hashes.len()
+        );
+
+        let mut encrypted = vec![];
+        for (index, segment_hash) in manifest.segment_hashes.iter().enumerate() {
+            let segment_key =
+                Self::get_derived_backup_segment_signing_key_static(root_secret, index as u64);
+            let segment = api
+                .download_backup(&segment_key.public_key())
+                .await?
+                .into_values()
+                .flatten()
+                .map(|backup| backup.data)
+                .find(|data| sha256::Hash::hash(data) == *segment_hash)
+                .with_context(|| {
+                    format!("Backup segment {index} is missing or was partially overwritten")
+                })?;
+            encrypted.extend(segment);
+        }
+
+        EncryptedClientBackup(encrypted)
+            .decrypt_with(&encryption_key, decoders)
+            .map(Some)
+    }
+
     /// Backup id derived from the root secret key (public key used to self-sign
     /// backup requests)
     pub fn get_backup_id(&self) -> bitcoin::secp256k1::PublicKey {
@@ -391,6 +568,24 @@ impl Client {
             .to_secp_key(&Secp256k1::<secp256k1_zkp::SignOnly>::gen_new())
     }
 
+    fn get_derived_backup_manifest_signing_key_static(secret: &DerivableSecret) -> KeyPair {
+        secret
+            .derive_backup_secret()
+            .child_key(CHUNKED_BACKUP_MANIFEST)
+            .to_secp_key(&Secp256k1::<secp256k1_zkp::SignOnly>::gen_new())
+    }
+
+    fn get_derived_backup_segment_signing_key_static(
+        secret: &DerivableSecret,
+        index: u64,
+    ) -> KeyPair {
+        secret
+            .derive_backup_secret()
+            .child_key(CHUNKED_BACKUP_SEGMENTS)
+            .child_key(ChildId(index))
+            .to_secp_key(&Secp256k1::<secp256k1_zkp::SignOnly>::gen_new())
+    }
+
     fn get_derived_backup_encryption_key(&self) -> fedimint_aead::LessSafeKey {
         Self::get_derived_backup_encryption_key_static(&self.root_secret())
     }
@@ -0,0 +1,113 @@
+use std::future::Future;
+use std::time::{Duration, SystemTime};
+
+use fedimint_core::time::now;
+use tokio::sync::Mutex;
+
+/// Consensus block count shared by all modules of a client, see
+/// [`crate::Client::consensus_block_count_cached`]
+///
+/// The lock is held while refreshing, so concurrent callers wait for the one
+/// request in flight instead of sending their own.
+#[derive(Debug, Default)]
+pub(crate) struct BlockCountCache {
+    cached: Mutex<Option<(SystemTime, u64)>>,
+}
+
+impl BlockCountCache {
+    /// Returns the cached block count if it was fetched at most `max_age` ago,
+    /// otherwise refreshes it using `fetch`
+    pub(crate) async fn get_or_refresh<F, Fut>(
+        &self,
+        max_age: Duration,
+        fetch: F,
+    ) -> anyhow::Result<u64>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = anyhow::Result<u64>>,
+    {
+        let mut cached = self.cached.lock().await;
+        if let Some((fetched_at, block_count)) = *cached {
+            if now()
+                .duration_since(fetched_at)
+                .is_ok_and(|age| age <= max_age)
+            {
+                return Ok(block_count);
+            }
+        }
+
+        let block_count = fetch().await?;
+        *cached = Some((now(), block_count));
+        Ok(block_count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use fedimint_core::task::sleep_in_test;
+
+    use super::BlockCountCache;
+
+    #[tokio::test]
+    async fn concurrent_calls_coalesce_into_one_fetch() {
+        let cache = Arc::new(BlockCountCache::default());
+        let fetches = Arc::new(AtomicUsize::new(0));
+
+        let calls = (0..10).map(|_| {
+            let cache = cache.clone();
+            let fetches = fetches.clone();
+            tokio::spawn(async move {
+                cache
+                    .get_or_refresh(Duration::from_secs(60), || async {
+                        fetches.fetch_add(1, Ordering::SeqCst);
+                        sleep_in_test("simulate slow request", Duration::from_millis(50)).await;
+                        Ok(100)
+                    })
+                    .await
+            })
+        });
+
+        for call in futures::future::join_all(calls).await {
+            assert_eq!(call.expect("Task panicked").expect("Fetch failed"), 100);
+        }
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn stale_value_is_refreshed() {
+        let cache = BlockCountCache::default();
+
+        assert_eq!(
+            cache
+                .get_or_refresh(Duration::ZERO, || async { Ok(1) })
+                .await
+                .unwrap(),
+            1
+        );
+        sleep_in_test("let the value become stale", Duration::from_millis(10)).await;
+        assert_eq!(
+            cache
+                .get_or_refresh(Duration::ZERO, || async { Ok(2) })
+                .await
+                .unwrap(),
+            2
+        );
+
+        // Failed refreshes are not cached
+        assert!(cache
+            .get_or_refresh(Duration::ZERO, || async { anyhow::bail!("offline") })
+            .await
+            .is_err());
+        assert_eq!(
+            cache
+                .get_or_refresh(Duration::from_secs(60), || async { Ok(3) })
+                .await
+                .unwrap(),
+            2
+        );
+    }
+}
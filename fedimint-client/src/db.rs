@@ -14,7 +14,7 @@ use fedimint_core::encoding::{Decodable, Encodable};
 use fedimint_core::module::registry::ModuleDecoderRegistry;
 use fedimint_core::module::SupportedApiVersionsSummary;
 use fedimint_core::util::BoxFuture;
-use fedimint_core::{impl_db_lookup, impl_db_record, PeerId};
+use fedimint_core::{impl_db_lookup, impl_db_record, Amount, PeerId};
 use fedimint_logging::LOG_CLIENT_DB;
 use futures::StreamExt;
 use serde::Serialize;
@@ -47,6 +47,7 @@ pub enum DbKeyPrefix {
     ClientMetaServiceInfo = 0x35,
     ApiSecret = 0x36,
     PeerLastApiVersionsSummaryCache = 0x37,
+    OperationFees = 0x38,
 
     /// Arbitrary data of the applications integrating Fedimint client and
     /// wanting to store some Federation-specific data in Fedimint client
@@ -103,6 +104,19 @@ impl_db_record!(
     db_prefix = DbKeyPrefix::OperationLog
 );
 
+/// Fees paid by all transactions of an operation, kept next to its
+/// [`OperationLogEntry`] so the encoding of the latter stays unchanged
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct OperationFeesKey {
+    pub operation_id: OperationId,
+}
+
+impl_db_record!(
+    key = OperationFeesKey,
+    value = Amount,
+    db_prefix = DbKeyPrefix::OperationFees
+);
+
 /// Key used to lookup operation log entries in chronological order
 #[derive(Debug, Clone, Copy, Encodable, Decodable, Serialize)]
 pub struct ChronologicalOperationLogKey {
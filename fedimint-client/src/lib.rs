@@ -80,12 +80,12 @@
 //!
 //! For a hacky instantiation of a complete client see the [`ng` subcommand of `fedimint-cli`](https://github.com/fedimint/fedimint/blob/55f9d88e17d914b92a7018de677d16e57ed42bf6/fedimint-cli/src/ng.rs#L56-L73).
 
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::fmt::{Debug, Formatter};
 use std::ops::{self, Range};
 use std::pin::Pin;
-use std::sync::{Arc, Weak};
-use std::time::Duration;
+use std::sync::{Arc, OnceLock, Weak};
+use std::time::{Duration, SystemTime};
 
 use anyhow::{anyhow, bail, Context};
 use async_stream::stream;
@@ -97,16 +97,19 @@ use db::{
 };
 use fedimint_api_client::api::{
     ApiVersionSet, DynGlobalApi, DynModuleApi, FederationApiExt, IGlobalFederationApi,
+    IRawFederationApi, ReconnectPolicy, RequestTimeoutApi, SwappableApi, WsFederationApi,
 };
 use fedimint_core::config::{ClientConfig, FederationId, JsonClientConfig, ModuleInitRegistry};
 use fedimint_core::core::{
     DynInput, DynOutput, IInput, IOutput, ModuleInstanceId, ModuleKind, OperationId,
 };
 use fedimint_core::db::{
-    AutocommitError, Database, DatabaseTransaction, IDatabaseTransactionOpsCoreTyped,
+    AutocommitError, Database, DatabaseTransaction, DatabaseVersion, DatabaseVersionKey,
+    IDatabaseTransactionOpsCoreTyped,
 };
 use fedimint_core::encoding::{Decodable, Encodable};
 use fedimint_core::endpoint_constants::VERSION_ENDPOINT;
+use fedimint_core::invite_code::InviteCode;
 use fedimint_core::module::registry::ModuleDecoderRegistry;
 use fedimint_core::module::{
     ApiAuth, ApiRequestErased, ApiVersion, MultiApiVersion, SupportedApiVersionsSummary,
@@ -140,6 +143,7 @@ use tracing::{debug, error, info, warn};
 
 use crate::api_version_discovery::discover_common_api_versions_set;
 use crate::backup::Metadata;
+use crate::block_count_cache::BlockCountCache;
 use crate::db::{ClientMetadataKey, ClientModuleRecoveryState, InitState, OperationLogKey};
 use crate::module::init::{
     ClientModuleInit, ClientModuleInitRegistry, DynClientModuleInit, IClientModuleInit,
@@ -153,8 +157,9 @@ use crate::sm::{
     ClientSMDatabaseTransaction, DynState, Executor, IState, Notifier, OperationState, State,
 };
 use crate::transaction::{
-    tx_submission_sm_decoder, ClientInput, ClientOutput, TransactionBuilder, TxSubmissionContext,
-    TxSubmissionStates, TRANSACTION_SUBMISSION_MODULE_INSTANCE,
+    tx_submission_sm_decoder, ClientInput, ClientOutput, PendingTxSubmission, TransactionBuilder,
+    TransactionDryRun, TxSubmissionContext, TxSubmissionStates,
+    TRANSACTION_SUBMISSION_MODULE_INSTANCE,
 };
 
 /// Client backup
@@ -175,6 +180,7 @@ pub mod sm;
 pub mod transaction;
 
 mod api_version_discovery;
+mod block_count_cache;
 
 /// Management of meta fields
 pub mod meta;
@@ -244,6 +250,9 @@ pub trait IGlobalClientContext: Debug + MaybeSend + MaybeSync + 'static {
     ) -> AddStateMachinesResult;
 
     async fn transaction_update_stream(&self) -> BoxStream<OperationState<TxSubmissionStates>>;
+
+    /// See [`Client::consensus_block_count_cached`]
+    async fn consensus_block_count_cached(&self, max_age: Duration) -> anyhow::Result<u64>;
 }
 
 #[apply(async_trait_maybe_send!)]
@@ -291,6 +300,10 @@ impl IGlobalClientContext for () {
     async fn transaction_update_stream(&self) -> BoxStream<OperationState<TxSubmissionStates>> {
         unimplemented!("fake implementation, only for tests");
     }
+
+    async fn consensus_block_count_cached(&self, _max_age: Duration) -> anyhow::Result<u64> {
+        unimplemented!("fake implementation, only for tests");
+    }
 }
 
 dyn_newtype_define! {
@@ -311,7 +324,10 @@ impl DynGlobalClientContext {
             .filter_map(|tx_update| {
                 std::future::ready(match tx_update.state {
                     TxSubmissionStates::Accepted(txid) if txid == query_txid => Some(Ok(())),
-                    TxSubmissionStates::Rejected(txid, submit_error) if txid == query_txid => {
+                    TxSubmissionStates::Rejected(txid, submit_error)
+                    | TxSubmissionStates::SubmissionFailed(txid, submit_error)
+                        if txid == query_txid =>
+                    {
                         Some(Err(submit_error))
                     }
                     _ => None,
@@ -513,6 +529,10 @@ impl IGlobalClientContext for ModuleGlobalClientContext {
     async fn transaction_update_stream(&self) -> BoxStream<OperationState<TxSubmissionStates>> {
         self.client.transaction_update_stream(self.operation).await
     }
+
+    async fn consensus_block_count_cached(&self, max_age: Duration) -> anyhow::Result<u64> {
+        self.client.consensus_block_count_cached(max_age).await
+    }
 }
 
 fn states_add_instance(
@@ -614,7 +634,8 @@ impl ClientHandle {
                 .ok_or_else(|| anyhow::format_err!("Already stopped"))?;
             let builder = ClientBuilder::from_existing(client);
             let config = client.config.clone();
-            let api_secret = client.api_secret.clone();
+            // Re-read from the DB to pick up a secret set by `Client::set_api_secret`
+            let api_secret = Client::get_api_secret_from_db(&client.db).await;
             let root_secret = client.root_secret.clone();
 
             (builder, config, api_secret, root_secret)
@@ -711,8 +732,54 @@ impl Drop for ClientHandle {
 const SUPPORTED_CORE_API_VERSIONS: &[fedimint_core::module::ApiVersion] =
     &[ApiVersion { major: 0, minor: 0 }];
 
+/// How often a running client re-negotiates the common api versions with the
+/// guardians
+const API_VERSION_REFRESH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
 pub type ModuleGlobalContextGen = ContextGen;
 
+/// Why a module of the federation was not initialized by the client, see
+/// [`Client::skipped_modules`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SkipReason {
+    /// No module init of the module's kind was registered with the builder
+    NotRegistered,
+    /// The client and the federation have no api version of the module in
+    /// common
+    IncompatibleApiVersion,
+}
+
+/// Whether a client database can be opened by this version of the client, see
+/// [`Client::db_compatibility`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DbCompatibility {
+    /// The database hasn't joined a federation yet
+    Uninitialized,
+    /// The database can be opened, possibly after migrating it
+    Compatible,
+    /// The database of a module was written by a newer client version, which
+    /// is required to open it
+    NewerThanSupported {
+        module_instance_id: ModuleInstanceId,
+        kind: ModuleKind,
+        db_version: DatabaseVersion,
+        supported_version: DatabaseVersion,
+    },
+}
+
+/// An active state machine state, see [`Client::list_state_machines`]
+#[derive(Debug, Clone)]
+pub struct ActiveStateMachineInfo {
+    pub operation_id: OperationId,
+    pub module_instance_id: ModuleInstanceId,
+    /// Kind of the module the state belongs to, `None` if the module isn't
+    /// initialized yet
+    pub module_kind: Option<ModuleKind>,
+    pub created_at: SystemTime,
+    /// Human readable representation of the state
+    pub state: String,
+}
+
 /// Resources particular to a module instance
 pub struct ClientModuleInstance<'m, M: ClientModule> {
     /// Instance id of the module
@@ -749,19 +816,48 @@ where
 pub struct Client {
     config: ClientConfig,
     api_secret: Option<String>,
+    /// Secret currently in use, replaced by [`Self::set_api_secret`]
+    current_api_secret: std::sync::RwLock<Option<String>>,
     decoders: ModuleDecoderRegistry,
     db: Database,
     federation_id: FederationId,
     federation_meta: BTreeMap<String, String>,
-    primary_module_instance: ModuleInstanceId,
+    /// `None` if built using [`ClientBuilder::allow_no_primary`] without a
+    /// primary module being available
+    primary_module_instance: Option<ModuleInstanceId>,
+    /// Set if no primary module was available when building the client and a
+    /// module of one of [`Self::primary_module_kinds`] was enabled later
+    late_primary_module_instance: OnceLock<ModuleInstanceId>,
+    /// See [`ClientBuilder::with_primary_module_kinds`]
+    primary_module_kinds: Vec<ModuleKind>,
     modules: ClientModuleRegistry,
     module_inits: ClientModuleInitRegistry,
     executor: Executor,
     api: DynGlobalApi,
+    /// Bottom layer of [`Self::api`], replaced by [`Self::set_api_secret`]
+    api_swap: SwappableApi,
     root_secret: DerivableSecret,
     operation_log: OperationLog,
     secp_ctx: Secp256k1<secp256k1_zkp::All>,
     meta_service: Arc<MetaService>,
+    api_request_hook: Option<ApiRequestHook>,
+    request_timeout: Option<Duration>,
+    reconnect_policy: ReconnectPolicy,
+    tx_submission_retry_limit: Option<u32>,
+    skipped_modules: Vec<(ModuleInstanceId, ModuleKind, SkipReason)>,
+    /// Modules skipped because of an incompatible api version, set once a
+    /// re-negotiation finds a common version, see
+    /// [`Self::enable_compatible_modules`]
+    late_modules: BTreeMap<ModuleInstanceId, (ModuleKind, OnceLock<DynClientModule>)>,
+    /// Handed to modules initialized by [`Self::enable_compatible_modules`]
+    final_client: FinalClient,
+    admin_auth: Option<ApiAuth>,
+    block_count_cache: BlockCountCache,
+    tx_submission_context: TxSubmissionContext,
+    /// Modules whose [`ClientModule::start`] has completed
+    started_modules: std::sync::Mutex<BTreeSet<ModuleInstanceId>>,
+    /// Common api versions negotiated with the guardians
+    api_version_sender: Arc<watch::Sender<ApiVersionSet>>,
 
     task_group: TaskGroup,
 
@@ -785,6 +881,23 @@ impl Client {
         self.api.clone()
     }
 
+    /// Returns the federation's consensus block count, reusing a value
+    /// fetched at most `max_age` ago. The value is shared by all modules and
+    /// concurrent callers wait for a single request.
+    ///
+    /// It is fetched by the first module that
+    /// [provides it](`ClientModule::provides_consensus_block_count`).
+    pub async fn consensus_block_count_cached(&self, max_age: Duration) -> anyhow::Result<u64> {
+        let (_, _, module) = self
+            .iter_modules()
+            .find(|(_, _, module)| module.provides_consensus_block_count())
+            .context("No module provides the consensus block count")?;
+
+        self.block_count_cache
+            .get_or_refresh(max_age, || module.fetch_consensus_block_count())
+            .await
+    }
+
     /// Get the [`TaskGroup`] that is tied to Client's lifetime.
     pub fn task_group(&self) -> &TaskGroup {
         &self.task_group
@@ -872,6 +985,43 @@ impl Client {
         Self::get_config_from_db(db).await.is_some()
     }
 
+    /// Checks whether `db` can be opened by a client using `module_inits`,
+    /// e.g. to ask the user to update their wallet instead of failing to open
+    /// a database written by a newer version. Modules without a registered
+    /// init are ignored, like when building the client.
+    pub async fn db_compatibility(
+        db: &Database,
+        module_inits: &ClientModuleInitRegistry,
+    ) -> DbCompatibility {
+        let Some(config) = Self::get_config_from_db(db).await else {
+            return DbCompatibility::Uninitialized;
+        };
+
+        let mut dbtx = db.begin_transaction_nc().await;
+        for (module_instance_id, module_config) in config.modules {
+            let Some(init) = module_inits.get(&module_config.kind) else {
+                continue;
+            };
+
+            let supported_version = init.database_version();
+            if let Some(db_version) = dbtx
+                .get_value(&DatabaseVersionKey(module_instance_id))
+                .await
+            {
+                if supported_version < db_version {
+                    return DbCompatibility::NewerThanSupported {
+                        module_instance_id,
+                        kind: module_config.kind,
+                        db_version,
+                        supported_version,
+                    };
+                }
+            }
+        }
+
+        DbCompatibility::Compatible
+    }
+
     pub async fn start_executor(self: &Arc<Self>) {
         debug!(
             "Starting fedimint client executor (version: {})",
@@ -907,6 +1057,95 @@ impl Client {
         &self.api_secret
     }
 
+    /// API secret currently used to authenticate with the federation
+    ///
+    /// Unlike [`Self::api_secret`] this reflects changes made by
+    /// [`Self::set_api_secret`].
+    pub fn current_api_secret(&self) -> Option<String> {
+        self.current_api_secret
+            .read()
+            .expect("Lock poisoned")
+            .clone()
+    }
+
+    /// Replace the API secret used to authenticate with the federation
+    ///
+    /// The new secret is probed against the guardians first and only persisted
+    /// if at least a threshold of them accept it. Afterwards all requests of
+    /// the running client, including those of its modules, use the new secret.
+    pub async fn set_api_secret(&self, new_secret: Option<String>) -> anyhow::Result<()> {
+        Self::probe_api_secret(&self.config, &new_secret)
+            .await
+            .context("New API secret was rejected by the federation")?;
+
+        let mut dbtx = self.db.begin_transaction().await;
+        if let Some(new_secret) = new_secret.as_ref() {
+            dbtx.insert_entry(&ApiSecretKey, new_secret).await;
+        } else {
+            dbtx.remove_entry(&ApiSecretKey).await;
+        }
+        dbtx.commit_tx_result().await?;
+
+        let ws_api = WsFederationApi::from_config_with_reconnect_policy(
+            &self.config,
+            &new_secret,
+            self.reconnect_policy,
+        );
+        self.api_swap.swap(match self.api_swap.self_peer() {
+            Some(self_peer_id) => DynGlobalApi::from_raw(ws_api.with_self_peer_id(self_peer_id)),
+            None => DynGlobalApi::from_raw(ws_api),
+        });
+        *self.current_api_secret.write().expect("Lock poisoned") = new_secret;
+
+        info!(target: LOG_CLIENT, "Updated API secret");
+        Ok(())
+    }
+
+    /// Check that a threshold of guardians accepts requests made with
+    /// `api_secret`
+    async fn probe_api_secret(
+        config: &ClientConfig,
+        api_secret: &Option<String>,
+    ) -> anyhow::Result<()> {
+        let api = DynGlobalApi::from_config(config, api_secret);
+        let num_peers = NumPeers::from(config.global.api_endpoints.len());
+
+        let mut requests = num_peers
+            .peer_ids()
+            .map(|peer_id| {
+                let api = &api;
+                async move {
+                    api.request_single_peer_typed::<SupportedApiVersionsSummary>(
+                        Some(Duration::from_secs(10)),
+                        VERSION_ENDPOINT.to_owned(),
+                        ApiRequestErased::default(),
+                        peer_id,
+                    )
+                    .await
+                }
+            })
+            .collect::<FuturesUnordered<_>>();
+
+        let mut num_accepted = 0;
+        let mut last_error = None;
+        while let Some(response) = requests.next().await {
+            match response {
+                Ok(_) => {
+                    num_accepted += 1;
+                    if num_peers.threshold() <= num_accepted {
+                        return Ok(());
+                    }
+                }
+                Err(err) => last_error = Some(err),
+            }
+        }
+
+        match last_error {
+            Some(err) => Err(anyhow!(err)),
+            None => bail!("Not enough guardians responded"),
+        }
+    }
+
     pub fn decoders(&self) -> &ModuleDecoderRegistry {
         &self.decoders
     }
@@ -921,11 +1160,47 @@ impl Client {
         &self,
         instance: ModuleInstanceId,
     ) -> Option<&maybe_add_send_sync!(dyn IClientModule)> {
-        Some(self.modules.get(instance)?.as_ref())
+        Some(self.get_module_with_kind(instance)?.1.as_ref())
+    }
+
+    /// Looks up a module, including ones enabled after the client was built
+    fn get_module_with_kind(
+        &self,
+        instance: ModuleInstanceId,
+    ) -> Option<(&ModuleKind, &DynClientModule)> {
+        self.modules
+            .get_with_kind(instance)
+            .map(|entry| (&entry.0, &entry.1))
+            .or_else(|| {
+                let (kind, module) = self.late_modules.get(&instance)?;
+                Some((kind, module.get()?))
+            })
+    }
+
+    /// Iterates over all initialized modules, including ones enabled after the
+    /// client was built
+    fn iter_modules(
+        &self,
+    ) -> impl Iterator<Item = (ModuleInstanceId, &ModuleKind, &DynClientModule)> {
+        self.modules.iter_modules().chain(
+            self.late_modules
+                .iter()
+                .filter_map(|(id, (kind, module))| Some((*id, kind, module.get()?))),
+        )
     }
 
     pub fn has_module(&self, instance: ModuleInstanceId) -> bool {
-        self.modules.get(instance).is_some()
+        self.get_module_with_kind(instance).is_some()
+    }
+
+    /// Modules of the federation the client did not initialize and can't be
+    /// used, e.g. to warn the user about unavailable functionality
+    pub fn skipped_modules(&self) -> Vec<(ModuleInstanceId, ModuleKind, SkipReason)> {
+        self.skipped_modules
+            .iter()
+            .filter(|(id, _, _)| !self.has_module(*id))
+            .cloned()
+            .collect()
     }
 
     /// Returns the input amount and output amount of a transaction
@@ -964,6 +1239,26 @@ impl Client {
         (in_amount, out_amount + fee_amount)
     }
 
+    /// Returns the sum of the fees of all inputs and outputs of `transaction`
+    ///
+    /// # Panics
+    /// If any of the input or output versions in the transaction are unknown
+    /// by the respective module.
+    fn transaction_fee(&self, transaction: &Transaction) -> Amount {
+        let input_fees = transaction.inputs.iter().map(|input| {
+            self.get_module(input.module_instance_id())
+                .input_fee(input)
+                .expect("Transactions are only built with supported input versions")
+        });
+        let output_fees = transaction.outputs.iter().map(|output| {
+            self.get_module(output.module_instance_id())
+                .output_fee(output)
+                .expect("Transactions are only built with supported output versions")
+        });
+
+        input_fees.chain(output_fees).sum()
+    }
+
     pub fn get_internal_payment_markers(&self) -> anyhow::Result<(PublicKey, u64)> {
         Ok((self.federation_id().to_fake_ln_pub_key(&self.secp_ctx)?, 0))
     }
@@ -984,6 +1279,66 @@ impl Client {
         self.executor.add_state_machines_dbtx(dbtx, states).await
     }
 
+    /// Lists all active state machine states, e.g. to inspect operations that
+    /// don't make progress anymore
+    pub async fn list_state_machines(&self) -> Vec<ActiveStateMachineInfo> {
+        self.executor
+            .get_active_states()
+            .await
+            .into_iter()
+            .map(|(state, meta)| ActiveStateMachineInfo {
+                operation_id: state.operation_id(),
+                module_instance_id: state.module_instance_id(),
+                module_kind: self
+                    .get_module_with_kind(state.module_instance_id())
+                    .map(|(kind, _)| kind.clone()),
+                created_at: meta.created_at,
+                state: format!("{state:?}"),
+            })
+            .collect()
+    }
+
+    /// Re-evaluates the transitions of all active states of an operation,
+    /// which can revive a state machine that got stuck waiting for a trigger
+    /// that will never fire. See [`Executor::retrigger_operation`].
+    pub async fn retrigger_state_machine(&self, operation_id: OperationId) -> anyhow::Result<()> {
+        if self.executor.retrigger_operation(operation_id).await == 0 {
+            bail!(
+                "Operation {} has no active state machines",
+                operation_id.fmt_short()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Lists the transactions that were neither accepted nor rejected by the
+    /// federation yet together with their submission attempts, e.g. to
+    /// diagnose a wallet that appears stuck
+    pub async fn pending_tx_submissions(&self) -> Vec<PendingTxSubmission> {
+        self.executor
+            .get_active_states()
+            .await
+            .into_iter()
+            .filter(|(state, _)| {
+                state.module_instance_id() == TRANSACTION_SUBMISSION_MODULE_INSTANCE
+            })
+            .filter_map(|(state, _)| {
+                match &state
+                    .as_any()
+                    .downcast_ref::<OperationState<TxSubmissionStates>>()?
+                    .state
+                {
+                    TxSubmissionStates::Created(transaction) => Some(
+                        self.tx_submission_context
+                            .pending_submission(transaction.tx_hash()),
+                    ),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
     // TODO: implement as part of [`OperationLog`]
     pub async fn get_active_operations(&self) -> HashSet<OperationId> {
         let active_states = self.executor.get_active_states().await;
@@ -1020,10 +1375,10 @@ impl Client {
     ) -> anyhow::Result<(Transaction, Vec<DynState>, Range<u64>)> {
         let (input_amount, output_amount) = self.transaction_builder_balance(&partial_transaction);
 
-        let (added_inputs, change_outputs) = self
-            .primary_module()
+        let (primary_module_instance, primary_module) = self.try_primary_module()?;
+        let (added_inputs, change_outputs) = primary_module
             .create_final_inputs_and_outputs(
-                self.primary_module_instance,
+                primary_module_instance,
                 dbtx,
                 operation_id,
                 input_amount,
@@ -1051,6 +1406,37 @@ impl Client {
         Ok((tx, states, change_range))
     }
 
+    /// Adds funding and/or change to the transaction builder like
+    /// [`Self::finalize_and_submit_transaction`] would, but neither persists
+    /// the changes to the database nor submits the transaction, so callers can
+    /// inspect it first, e.g. to show the exact fees before confirming.
+    ///
+    /// The result is only indicative: the funding selected by the primary
+    /// module may differ once the transaction is actually submitted if the
+    /// client's funds change in between.
+    pub async fn build_transaction_dry_run(
+        &self,
+        tx_builder: TransactionBuilder,
+    ) -> anyhow::Result<TransactionDryRun> {
+        let num_inputs = tx_builder.inputs.len() as u64;
+
+        // The transaction is never committed, discarding all changes made while
+        // selecting the funding
+        let mut dbtx = self.db().begin_transaction().await;
+        let finalized = self
+            .finalize_transaction(&mut dbtx.to_ref_nc(), OperationId::new_random(), tx_builder)
+            .await;
+        dbtx.ignore_uncommitted();
+        let (transaction, _, change_outputs) = finalized?;
+
+        Ok(TransactionDryRun {
+            funding_inputs: num_inputs..transaction.inputs.len() as u64,
+            change_outputs,
+            fee: self.transaction_fee(&transaction),
+            transaction,
+        })
+    }
+
     /// Add funding and/or change to the transaction builder as needed, finalize
     /// the transaction and submit it to the federation.
     ///
@@ -1119,6 +1505,78 @@ impl Client {
         }
     }
 
+    /// Like [`Self::finalize_and_submit_transaction`], but submits several
+    /// independent transactions as part of the same operation, e.g. to split
+    /// inputs that wouldn't fit into a single transaction. Either all of them
+    /// are submitted or none.
+    ///
+    /// `operation_meta` receives the id and change out points of every
+    /// transaction in the order of `tx_builders`.
+    pub async fn finalize_and_submit_transactions<F, M>(
+        &self,
+        operation_id: OperationId,
+        operation_type: &str,
+        operation_meta: F,
+        tx_builders: Vec<TransactionBuilder>,
+    ) -> anyhow::Result<Vec<(TransactionId, Vec<OutPoint>)>>
+    where
+        F: Fn(Vec<(TransactionId, Vec<OutPoint>)>) -> M + Clone + MaybeSend + MaybeSync,
+        M: serde::Serialize + MaybeSend,
+    {
+        let operation_type = operation_type.to_owned();
+
+        let autocommit_res = self
+            .db
+            .autocommit(
+                |dbtx, _| {
+                    let operation_type = operation_type.clone();
+                    let tx_builders = tx_builders.clone();
+                    let operation_meta = operation_meta.clone();
+                    Box::pin(async move {
+                        if Client::operation_exists_dbtx(dbtx, operation_id).await {
+                            bail!("There already exists an operation with id {operation_id:?}")
+                        }
+
+                        let mut transactions = Vec::with_capacity(tx_builders.len());
+                        for tx_builder in tx_builders {
+                            transactions.push(
+                                self.finalize_and_submit_transaction_inner(
+                                    dbtx,
+                                    operation_id,
+                                    tx_builder,
+                                )
+                                .await?,
+                            );
+                        }
+
+                        self.operation_log()
+                            .add_operation_log_entry(
+                                dbtx,
+                                operation_id,
+                                &operation_type,
+                                operation_meta(transactions.clone()),
+                            )
+                            .await;
+
+                        Ok(transactions)
+                    })
+                },
+                Some(100), // TODO: handle what happens after 100 retries
+            )
+            .await;
+
+        match autocommit_res {
+            Ok(transactions) => Ok(transactions),
+            Err(AutocommitError::ClosureError { error, .. }) => Err(error),
+            Err(AutocommitError::CommitFailed {
+                attempts,
+                last_error,
+            }) => panic!(
+                "Failed to commit tx submission dbtx after {attempts} attempts: {last_error}"
+            ),
+        }
+    }
+
     async fn finalize_and_submit_transaction_inner(
         &self,
         dbtx: &mut DatabaseTransaction<'_>,
@@ -1153,6 +1611,8 @@ impl Client {
             );
         }
 
+        OperationLog::add_fees_paid(dbtx, operation_id, self.transaction_fee(&transaction)).await;
+
         let txid = transaction.tx_hash();
 
         debug!(target: LOG_CLIENT_NET_API, %txid, ?transaction,  "Finalized and submitting transaction");
@@ -1234,7 +1694,8 @@ impl Client {
         operation_id: OperationId,
         out_point: OutPoint,
     ) -> anyhow::Result<Amount> {
-        self.primary_module()
+        self.try_primary_module()?
+            .1
             .await_primary_module_output(operation_id, out_point)
             .await
     }
@@ -1283,16 +1744,16 @@ impl Client {
     /// primary module will always be returned before any other modules (which
     /// themselves are ordered by their instance ID).
     pub fn get_first_instance(&self, module_kind: &ModuleKind) -> Option<ModuleInstanceId> {
-        if self
-            .modules
-            .get_with_kind(self.primary_module_instance)
-            .is_some_and(|(kind, _)| kind == module_kind)
-        {
-            return Some(self.primary_module_instance);
+        if let Some(primary_module_instance) = self.primary_module_instance() {
+            if self
+                .get_module_with_kind(primary_module_instance)
+                .is_some_and(|(kind, _)| kind == module_kind)
+            {
+                return Some(primary_module_instance);
+            }
         }
 
-        self.modules
-            .iter_modules()
+        self.iter_modules()
             .find(|(_, kind, _module)| *kind == module_kind)
             .map(|(instance_id, _, _)| instance_id)
     }
@@ -1336,17 +1797,53 @@ impl Client {
     }
 
     /// Get the primary module
+    ///
+    /// ## Panics
+    /// If the client has no primary module, see
+    /// [`ClientBuilder::allow_no_primary`]
     pub fn primary_module(&self) -> &DynClientModule {
-        self.modules
-            .get(self.primary_module_instance)
+        self.try_primary_module()
             .expect("primary module must be present")
+            .1
+    }
+
+    /// Returns the instance id of the primary module, `None` if the client was
+    /// built using [`ClientBuilder::allow_no_primary`] and none is available
+    pub fn primary_module_instance(&self) -> Option<ModuleInstanceId> {
+        self.primary_module_instance
+            .or_else(|| self.late_primary_module_instance.get().copied())
     }
 
-    /// Balance available to the client for spending
+    /// Returns whether [`ClientModule::start`] of the module has completed
+    pub fn is_module_started(&self, module_instance_id: ModuleInstanceId) -> bool {
+        self.started_modules
+            .lock()
+            .expect("poisoned")
+            .contains(&module_instance_id)
+    }
+
+    /// Returns the primary module and its instance id or an error if the
+    /// client has none, i.e. operations requiring funding or change aren't
+    /// available
+    fn try_primary_module(&self) -> anyhow::Result<(ModuleInstanceId, &DynClientModule)> {
+        let primary_module_instance = self.primary_module_instance().context(
+            "Client has no primary module, operations requiring funding or change are unavailable",
+        )?;
+        let (_, primary_module) = self
+            .get_module_with_kind(primary_module_instance)
+            .with_context(|| format!("Primary module {primary_module_instance} is not ready"))?;
+        Ok((primary_module_instance, primary_module))
+    }
+
+    /// Balance available to the client for spending, always zero if the client
+    /// has no primary module
     pub async fn get_balance(&self) -> Amount {
-        self.primary_module()
+        let Ok((primary_module_instance, primary_module)) = self.try_primary_module() else {
+            return Amount::ZERO;
+        };
+        primary_module
             .get_balance(
-                self.primary_module_instance,
+                primary_module_instance,
                 &mut self.db().begin_transaction_nc().await,
             )
             .await
@@ -1355,11 +1852,16 @@ impl Client {
     /// Returns a stream that yields the current client balance every time it
     /// changes.
     pub async fn subscribe_balance_changes(&self) -> BoxStream<'static, Amount> {
-        let mut balance_changes = self.primary_module().subscribe_balance_changes().await;
+        let Ok((primary_module_instance, primary_module)) = self.try_primary_module() else {
+            return Box::pin(
+                futures::stream::once(futures::future::ready(Amount::ZERO))
+                    .chain(futures::stream::pending()),
+            );
+        };
+        let mut balance_changes = primary_module.subscribe_balance_changes().await;
         let initial_balance = self.get_balance().await;
         let db = self.db().clone();
-        let primary_module = self.primary_module().clone();
-        let primary_module_instance = self.primary_module_instance;
+        let primary_module = primary_module.clone();
 
         Box::pin(stream! {
             yield initial_balance;
@@ -1493,10 +1995,22 @@ impl Client {
             &self.api,
             &self.db,
             &self.task_group,
+            &self.api_version_sender,
         )
         .await
     }
 
+    /// Returns a stream of the common api versions negotiated with the
+    /// guardians, starting with the current ones and yielding a new set
+    /// whenever a re-negotiation changes it, e.g. after guardians upgraded.
+    ///
+    /// Modules skipped because of an incompatible api version are enabled
+    /// as soon as a new set makes them compatible, after which they no
+    /// longer show up in [`Self::skipped_modules`].
+    pub fn subscribe_api_version_changes(&self) -> impl Stream<Item = ApiVersionSet> {
+        WatchStream::new(self.api_version_sender.subscribe())
+    }
+
     /// Load the common api versions to use from cache and start a background
     /// process to refresh them.
     ///
@@ -1508,6 +2022,7 @@ impl Client {
         api: &DynGlobalApi,
         db: &Database,
         task_group: &TaskGroup,
+        api_version_sender: &Arc<watch::Sender<ApiVersionSet>>,
     ) -> anyhow::Result<ApiVersionSet> {
         if let Some(v) = db
             .begin_transaction()
@@ -1516,11 +2031,13 @@ impl Client {
             .await
         {
             debug!("Found existing cached common api versions");
+            api_version_sender.send_replace(v.0.clone());
             let config = config.clone();
             let client_module_init = module_init.clone();
             let api = api.clone();
             let db = db.clone();
             let task_group = task_group.clone();
+            let api_version_sender = api_version_sender.clone();
             // Separate task group, because we actually don't want to be waiting for this to
             // finish, and it's just best effort.
             task_group
@@ -1532,6 +2049,7 @@ impl Client {
                         &api,
                         &db,
                         task_group,
+                        &api_version_sender,
                     )
                     .await
                     {
@@ -1543,8 +2061,15 @@ impl Client {
         }
 
         debug!("No existing cached common api versions found, waiting for initial discovery");
-        Self::refresh_common_api_version_static(config, module_init, api, db, task_group.clone())
-            .await
+        Self::refresh_common_api_version_static(
+            config,
+            module_init,
+            api,
+            db,
+            task_group.clone(),
+            api_version_sender,
+        )
+        .await
     }
 
     async fn refresh_common_api_version_static(
@@ -1553,6 +2078,7 @@ impl Client {
         api: &DynGlobalApi,
         db: &Database,
         task_group: TaskGroup,
+        api_version_sender: &watch::Sender<ApiVersionSet>,
     ) -> anyhow::Result<ApiVersionSet> {
         debug!("Refreshing common api versions");
 
@@ -1602,9 +2128,175 @@ impl Client {
 
         dbtx.commit_tx().await;
 
+        api_version_sender.send_if_modified(|current| {
+            if *current == common_api_versions {
+                return false;
+            }
+            info!(
+                target: LOG_CLIENT,
+                value = ?common_api_versions,
+                "Common api versions changed"
+            );
+            *current = common_api_versions.clone();
+            true
+        });
+
         Ok(common_api_versions)
     }
 
+    /// Re-negotiates the common api versions every
+    /// [`API_VERSION_REFRESH_INTERVAL`], so long-lived clients notice
+    /// guardian upgrades, see [`Self::subscribe_api_version_changes`]
+    fn spawn_api_version_refresh_task(&self) {
+        let config = self.config.clone();
+        let module_inits = self.module_inits.clone();
+        let api = self.api.clone();
+        let db = self.db.clone();
+        let task_group = self.task_group.clone();
+        let api_version_sender = self.api_version_sender.clone();
+        self.task_group
+            .spawn_cancellable("refresh common api versions periodically", async move {
+                loop {
+                    runtime::sleep(API_VERSION_REFRESH_INTERVAL).await;
+                    if let Err(error) = Self::refresh_common_api_version_static(
+                        &config,
+                        &module_inits,
+                        &api,
+                        &db,
+                        task_group.clone(),
+                        &api_version_sender,
+                    )
+                    .await
+                    {
+                        warn!(target: LOG_CLIENT, %error, "Failed to refresh common api versions");
+                    }
+                }
+            });
+    }
+
+    /// Initializes and starts the modules skipped because of an incompatible
+    /// api version for which `api_versions` has a common version
+    async fn enable_compatible_modules(&self, api_versions: &ApiVersionSet) -> anyhow::Result<()> {
+        if ClientBuilder::load_init_state(&self.db)
+            .await
+            .does_require_recovery()
+            .is_some()
+        {
+            // The modules have to be recovered first, which happens once the client is
+            // opened again after the pending recoveries completed
+            debug!(
+                target: LOG_CLIENT,
+                "Not enabling compatible modules while the client requires recovery"
+            );
+            return Ok(());
+        }
+
+        // Enable modules of the preferred primary module kinds first, so the most
+        // preferred one becomes primary if none was available before
+        let mut late_modules = self.late_modules.iter().collect::<Vec<_>>();
+        late_modules.sort_by_key(|(_, (kind, _))| {
+            self.primary_module_kinds
+                .iter()
+                .position(|primary_kind| primary_kind == kind)
+                .unwrap_or(usize::MAX)
+        });
+        for (&module_instance_id, (kind, module_slot)) in late_modules {
+            if module_slot.get().is_some() {
+                continue;
+            }
+            let Some(&api_version) = api_versions.modules.get(&module_instance_id) else {
+                continue;
+            };
+            let module_init = self.module_inits.get(kind).expect(
+                "Only modules with a registered module init are skipped for their api version",
+            );
+            let module_config = self
+                .config
+                .modules
+                .get(&module_instance_id)
+                .expect("Skipped modules are part of the config")
+                .clone();
+
+            let module = module_init
+                .init(
+                    self.final_client.clone(),
+                    self.federation_id,
+                    self.config.global.api_endpoints.len(),
+                    module_config,
+                    self.db.clone(),
+                    module_instance_id,
+                    api_versions.core,
+                    api_version,
+                    self.root_secret.derive_module_secret(module_instance_id),
+                    self.executor.notifier().clone(),
+                    self.api.clone(),
+                    self.admin_auth.clone(),
+                    self.task_group.clone(),
+                )
+                .await?;
+
+            info!(
+                target: LOG_CLIENT,
+                module_id = module_instance_id,
+                %kind,
+                "Enabling module that became compatible with the federation"
+            );
+            let module = module_slot.get_or_init(|| module);
+            self.executor
+                .add_module_context(module.context(module_instance_id))
+                .await;
+            if self.primary_module_instance.is_none()
+                && self.primary_module_kinds.contains(kind)
+                && module.supports_being_primary()
+                && self
+                    .late_primary_module_instance
+                    .set(module_instance_id)
+                    .is_ok()
+            {
+                info!(
+                    target: LOG_CLIENT,
+                    module_id = module_instance_id,
+                    %kind,
+                    "Module enabled late became the primary module"
+                );
+            }
+            module.start().await;
+            self.started_modules
+                .lock()
+                .expect("poisoned")
+                .insert(module_instance_id);
+        }
+
+        Ok(())
+    }
+
+    /// Enables skipped modules once re-negotiated api versions make them
+    /// compatible, see [`Self::subscribe_api_version_changes`]
+    fn spawn_enable_compatible_modules_task(&self, client: ClientWeak) {
+        if self.late_modules.is_empty() {
+            return;
+        }
+
+        let mut api_versions = self.api_version_sender.subscribe();
+        self.task_group
+            .spawn_cancellable("enable compatible modules", async move {
+                loop {
+                    let versions = api_versions.borrow_and_update().clone();
+                    // Don't keep the client alive while waiting for changes
+                    let Some(client_strong) = client.upgrade() else {
+                        break;
+                    };
+                    if let Err(error) = client_strong.enable_compatible_modules(&versions).await {
+                        warn!(target: LOG_CLIENT, %error, "Failed to enable compatible modules");
+                    }
+                    drop(client_strong);
+                    if api_versions.changed().await.is_err() {
+                        break;
+                    }
+                }
+            });
+    }
+
     /// Get the client [`Metadata`]
     pub async fn get_metadata(&self) -> Metadata {
         self.db
@@ -1850,7 +2542,10 @@ impl TransactionUpdates {
             .filter_map(|tx_update| {
                 std::future::ready(match tx_update.state {
                     TxSubmissionStates::Accepted(txid) if txid == await_txid => Some(Ok(())),
-                    TxSubmissionStates::Rejected(txid, submit_error) if txid == await_txid => {
+                    TxSubmissionStates::Rejected(txid, submit_error)
+                    | TxSubmissionStates::SubmissionFailed(txid, submit_error)
+                        if txid == await_txid =>
+                    {
                         Some(Err(submit_error))
                     }
                     _ => None,
@@ -1871,13 +2566,23 @@ pub struct AdminCreds {
     pub auth: ApiAuth,
 }
 
+/// Function wrapping the [`DynGlobalApi`] a [`Client`] is built with, e.g. to
+/// intercept its requests in tests
+pub type ApiRequestHook = Arc<maybe_add_send_sync!(dyn Fn(DynGlobalApi) -> DynGlobalApi + 'static)>;
+
 /// Used to configure, assemble and build [`Client`]
 pub struct ClientBuilder {
     module_inits: ClientModuleInitRegistry,
     primary_module_instance: Option<ModuleInstanceId>,
+    primary_module_kinds: Vec<ModuleKind>,
+    allow_no_primary: bool,
     admin_creds: Option<AdminCreds>,
     db_no_decoders: Database,
     meta_service: Arc<MetaService>,
+    api_request_hook: Option<ApiRequestHook>,
+    request_timeout: Option<Duration>,
+    reconnect_policy: ReconnectPolicy,
+    tx_submission_retry_limit: Option<u32>,
     stopped: bool,
 }
 
@@ -1887,22 +2592,34 @@ impl ClientBuilder {
         ClientBuilder {
             module_inits: Default::default(),
             primary_module_instance: Default::default(),
+            primary_module_kinds: vec![],
+            allow_no_primary: false,
             admin_creds: None,
             db_no_decoders: db,
             stopped: false,
             meta_service,
+            api_request_hook: None,
+            request_timeout: None,
+            reconnect_policy: ReconnectPolicy::default(),
+            tx_submission_retry_limit: None,
         }
     }
 
     fn from_existing(client: &Client) -> Self {
         ClientBuilder {
             module_inits: client.module_inits.clone(),
-            primary_module_instance: Some(client.primary_module_instance),
+            primary_module_instance: client.primary_module_instance,
+            primary_module_kinds: vec![],
+            allow_no_primary: client.primary_module_instance.is_none(),
             admin_creds: None,
             db_no_decoders: client.db.with_decoders(Default::default()),
             stopped: false,
             // non unique
             meta_service: client.meta_service.clone(),
+            api_request_hook: client.api_request_hook.clone(),
+            request_timeout: client.request_timeout,
+            reconnect_policy: client.reconnect_policy,
+            tx_submission_retry_limit: client.tx_submission_retry_limit,
         }
     }
 
@@ -1931,15 +2648,109 @@ impl ClientBuilder {
             .replace(primary_module_instance)
             .is_some();
         assert!(
-            !was_replaced,
+            !was_replaced && self.primary_module_kinds.is_empty(),
+            "Only one primary module can be given to the builder."
+        );
+    }
+
+    /// Uses the first module of the first kind in `kinds` that the federation
+    /// offers and that supports being primary as the primary module, e.g. to
+    /// prefer a mint but fall back to another module. See
+    /// [`ClientModule::supports_being_primary`] for more information.
+    ///
+    /// ## Panics
+    /// If there was a primary module specified previously
+    pub fn with_primary_module_kinds(&mut self, kinds: Vec<ModuleKind>) {
+        assert!(
+            self.primary_module_instance.is_none() && self.primary_module_kinds.is_empty(),
             "Only one primary module can be given to the builder."
         );
+        self.primary_module_kinds = kinds;
+    }
+
+    /// Lists the modules of the federation with config `config` that could be
+    /// used as primary module, i.e. those that a registered module init
+    /// supports and that [support being
+    /// primary](ClientModuleInit::supports_being_primary). Useful to choose
+    /// the kinds passed to [`Self::with_primary_module_kinds`].
+    pub fn primary_module_candidates(
+        &self,
+        config: &ClientConfig,
+    ) -> Vec<(ModuleInstanceId, ModuleKind)> {
+        config
+            .modules
+            .iter()
+            .filter(|(_, module_config)| {
+                self.module_inits
+                    .get(module_config.kind())
+                    .is_some_and(|init| init.supports_being_primary())
+            })
+            .map(|(module_instance_id, module_config)| {
+                (*module_instance_id, module_config.kind().clone())
+            })
+            .collect()
+    }
+
+    /// Builds the client even if no primary module was given or none of the
+    /// given ones is available, e.g. to still read module state or make admin
+    /// calls. Operations requiring funding or change will fail and the balance
+    /// is always zero.
+    pub fn allow_no_primary(&mut self) {
+        self.allow_no_primary = true;
     }
 
     pub fn with_meta_service(&mut self, meta_service: Arc<MetaService>) {
         self.meta_service = meta_service;
     }
 
+    /// Wrap the federation API the client is built with, see
+    /// [`ApiRequestHook`]
+    pub fn with_api_request_hook(&mut self, hook: ApiRequestHook) {
+        self.api_request_hook = Some(hook);
+    }
+
+    /// Fail requests to a single guardian that take longer than `timeout`
+    ///
+    /// Applies on top of any [`ApiRequestHook`], so a timed out guardian is
+    /// treated as faulty by federation-wide queries instead of stalling them.
+    pub fn with_request_timeout(&mut self, timeout: Duration) {
+        self.request_timeout = Some(timeout);
+    }
+
+    /// Set how the client backs off when reconnecting to a guardian, e.g. to
+    /// save battery on mobile
+    pub fn with_reconnect_policy(&mut self, reconnect_policy: ReconnectPolicy) {
+        self.reconnect_policy = reconnect_policy;
+    }
+
+    /// Give up submitting a transaction after `retry_limit` retries that
+    /// failed transiently, e.g. timed out, instead of retrying forever
+    ///
+    /// Transactions rejected by the federation are never retried. A
+    /// transaction given up on ends in
+    /// [`TxSubmissionStates::SubmissionFailed`] and is treated as rejected by
+    /// the operation it belongs to.
+    pub fn with_tx_submission_retry_limit(&mut self, retry_limit: u32) {
+        self.tx_submission_retry_limit = Some(retry_limit);
+    }
+
+    /// Sort key placing the module instances that can become the primary module
+    /// first, in order of preference
+    fn primary_module_rank(
+        &self,
+        module_instance_id: ModuleInstanceId,
+        kind: &ModuleKind,
+    ) -> usize {
+        if self.primary_module_instance == Some(module_instance_id) {
+            return 0;
+        }
+
+        self.primary_module_kinds
+            .iter()
+            .position(|primary_kind| primary_kind == kind)
+            .map_or(usize::MAX, |preference| preference + 1)
+    }
+
     async fn migrate_database(&self, db: &Database) -> anyhow::Result<()> {
         // Only apply the client database migrations if the database has been
         // initialized.
@@ -1981,6 +2792,31 @@ impl ClientBuilder {
         self.admin_creds = Some(creds);
     }
 
+    /// Checks the credentials set with [`Self::set_admin_creds`] against their
+    /// guardian, so wrong ones are reported before the first admin call
+    ///
+    /// Requires the client to have joined the federation already.
+    pub async fn verify_admin_creds(&self) -> anyhow::Result<()> {
+        let admin_creds = self
+            .admin_creds
+            .as_ref()
+            .context("No admin credentials set")?;
+        let config = Client::get_config_from_db(&self.db_no_decoders)
+            .await
+            .context("Client config not found, join the federation first")?;
+        let api_secret = Client::get_api_secret_from_db(&self.db_no_decoders).await;
+
+        DynGlobalApi::from_config_admin(&config, &api_secret, admin_creds.peer_id)
+            .auth(admin_creds.auth.clone())
+            .await
+            .with_context(|| {
+                format!(
+                    "Guardian {} rejected the admin credentials",
+                    admin_creds.peer_id
+                )
+            })
+    }
+
     async fn init(
         self,
         root_secret: DerivableSecret,
@@ -2172,6 +3008,32 @@ impl ClientBuilder {
         Ok(client)
     }
 
+    /// Opens the client if its database already joined the federation of
+    /// `invite_code`, otherwise downloads the client config and joins it.
+    ///
+    /// Fails if the database belongs to a different federation.
+    pub async fn open_or_join(
+        self,
+        root_secret: DerivableSecret,
+        invite_code: &InviteCode,
+    ) -> anyhow::Result<ClientHandle> {
+        let federation_id = invite_code.federation_id();
+
+        if let Some(config) = Client::get_config_from_db(&self.db_no_decoders).await {
+            let db_federation_id = config.calculate_federation_id();
+            if db_federation_id != federation_id {
+                bail!(
+                    "Client database belongs to federation {db_federation_id}, not {federation_id}"
+                );
+            }
+            return self.open(root_secret).await;
+        }
+
+        let config = fedimint_api_client::download_from_invite_code(invite_code).await?;
+        self.join(root_secret, config, invite_code.api_secret())
+            .await
+    }
+
     /// Build a [`Client`] and start the executor
     async fn build(
         self,
@@ -2199,10 +3061,24 @@ impl ClientBuilder {
         let config = Self::config_decoded(config, &decoders)?;
         let fed_id = config.calculate_federation_id();
         let db = self.db_no_decoders.with_decoders(decoders.clone());
-        let api = if let Some(admin_creds) = self.admin_creds.as_ref() {
-            DynGlobalApi::from_config_admin(&config, &api_secret, admin_creds.peer_id)
+        let ws_api = WsFederationApi::from_config_with_reconnect_policy(
+            &config,
+            &api_secret,
+            self.reconnect_policy,
+        );
+        let api_swap = SwappableApi::new(if let Some(admin_creds) = self.admin_creds.as_ref() {
+            DynGlobalApi::from_raw(ws_api.with_self_peer_id(admin_creds.peer_id))
         } else {
-            DynGlobalApi::from_config(&config, &api_secret)
+            DynGlobalApi::from_raw(ws_api)
+        });
+        let api = DynGlobalApi::from_raw(api_swap.clone());
+        let api = match self.api_request_hook.as_ref() {
+            Some(hook) => hook(api),
+            None => api,
+        };
+        let api = match self.request_timeout {
+            Some(timeout) => DynGlobalApi::from_raw(RequestTimeoutApi::new(api, timeout)),
+            None => api,
         };
         let task_group = TaskGroup::new();
 
@@ -2212,18 +3088,29 @@ impl ClientBuilder {
 
         let init_state = Self::load_init_state(&db).await;
 
-        let primary_module_instance = self
-            .primary_module_instance
-            .ok_or(anyhow!("No primary module instance id was provided"))?;
+        if self.primary_module_instance.is_none()
+            && self.primary_module_kinds.is_empty()
+            && !self.allow_no_primary
+        {
+            bail!("No primary module instance id was provided");
+        }
 
         let notifier = Notifier::new(db.clone());
 
+        let api_version_sender = Arc::new(
+            watch::channel(ApiVersionSet {
+                core: ApiVersion::new(0, 0),
+                modules: BTreeMap::new(),
+            })
+            .0,
+        );
         let common_api_versions = Client::load_and_refresh_common_api_version_static(
             &config,
             &self.module_inits,
             &api,
             &db,
             &task_group,
+            &api_version_sender,
         )
         .await
         .inspect_err(|err| {
@@ -2250,18 +3137,33 @@ impl ClientBuilder {
 
         let root_secret = Self::federation_root_secret(&root_secret, &config);
 
+        let mut skipped_modules = vec![];
+        let mut late_modules = BTreeMap::new();
         let modules = {
             let mut modules = ClientModuleRegistry::default();
-            for (module_instance_id, module_config) in config.modules.clone() {
+            // Modules that can become the primary module are initialized first, see
+            // `ClientModule::start` for the guarantees this provides
+            let mut module_configs = config.modules.clone().into_iter().collect::<Vec<_>>();
+            module_configs.sort_by_key(|(module_instance_id, module_config)| {
+                self.primary_module_rank(*module_instance_id, module_config.kind())
+            });
+            for (module_instance_id, module_config) in module_configs {
                 let kind = module_config.kind().clone();
                 let Some(module_init) = self.module_inits.get(&kind).cloned() else {
                     debug!("Module kind {kind} of instance {module_instance_id} not found in module gens, skipping");
+                    skipped_modules.push((module_instance_id, kind, SkipReason::NotRegistered));
                     continue;
                 };
 
                 let Some(&api_version) = common_api_versions.modules.get(&module_instance_id)
                 else {
                     warn!("Module kind {kind} of instance {module_instance_id} has not compatible api version, skipping");
+                    late_modules.insert(module_instance_id, (kind.clone(), OnceLock::new()));
+                    skipped_modules.push((
+                        module_instance_id,
+                        kind,
+                        SkipReason::IncompatibleApiVersion,
+                    ));
                     continue;
                 };
 
@@ -2373,11 +3275,19 @@ impl ClientBuilder {
                             task_group.clone(),
                         )
                         .await?;
+                    if module.supports_being_primary() != module_init.supports_being_primary() {
+                        warn!(
+                            target: LOG_CLIENT,
+                            module_id = module_instance_id,
+                            %kind,
+                            "Module and its init disagree on supporting being primary"
+                        );
+                    }
 
-                    if primary_module_instance == module_instance_id
+                    if self.primary_module_instance == Some(module_instance_id)
                         && !module.supports_being_primary()
                     {
-                        bail!("Module instance {primary_module_instance} of kind {kind} does not support being a primary module");
+                        bail!("Module instance {module_instance_id} of kind {kind} does not support being a primary module");
                     }
 
                     modules.register_module(module_instance_id, kind, module);
@@ -2386,17 +3296,61 @@ impl ClientBuilder {
             modules
         };
 
-        if init_state.is_pending() && module_recoveries.is_empty() {
+        let primary_module_instance = match self.primary_module_instance {
+            Some(primary_module_instance) => Some(primary_module_instance),
+            None => self.primary_module_kinds.iter().find_map(|kind| {
+                config
+                    .modules
+                    .iter()
+                    .find_map(|(module_instance_id, module_config)| {
+                        // Recovering modules aren't initialized yet, like for an explicitly
+                        // chosen primary module we can't check them
+                        let usable = module_config.kind() == kind
+                            && (module_recoveries.contains_key(module_instance_id)
+                                || modules
+                                    .get(*module_instance_id)
+                                    .is_some_and(|module| module.supports_being_primary()));
+                        usable.then_some(*module_instance_id)
+                    })
+            }),
+        };
+        let primary_module_instance = match primary_module_instance {
+            Some(primary_module_instance)
+                if modules.get(primary_module_instance).is_some()
+                    || module_recoveries.contains_key(&primary_module_instance) =>
+            {
+                Some(primary_module_instance)
+            }
+            _ if self.allow_no_primary => {
+                warn!(
+                    target: LOG_CLIENT,
+                    "No primary module available, funding and change are disabled"
+                );
+                None
+            }
+            Some(primary_module_instance) => Some(primary_module_instance),
+            None => bail!("No primary module instance id was provided"),
+        };
+
+        // Modules skipped because of their api version weren't recovered yet, keep
+        // the recovery pending so they are recovered once they become compatible
+        if init_state.is_pending()
+            && module_recoveries.is_empty()
+            && (init_state.does_require_recovery().is_none() || late_modules.is_empty())
+        {
             let mut dbtx = db.begin_transaction().await;
             dbtx.insert_entry(&ClientInitStateKey, &init_state.into_complete())
                 .await;
             dbtx.commit_tx().await;
         }
 
+        let tx_submission_context = TxSubmissionContext::new(self.tx_submission_retry_limit);
         let executor = {
             let mut executor_builder = Executor::builder();
-            executor_builder
-                .with_module(TRANSACTION_SUBMISSION_MODULE_INSTANCE, TxSubmissionContext);
+            executor_builder.with_module(
+                TRANSACTION_SUBMISSION_MODULE_INSTANCE,
+                tx_submission_context.clone(),
+            );
 
             for (module_instance_id, _, module) in modules.iter_modules() {
                 executor_builder.with_module_dyn(module.context(module_instance_id));
@@ -2418,23 +3372,40 @@ impl ClientBuilder {
 
         let client_inner = Arc::new(Client {
             config: config.clone(),
+            current_api_secret: std::sync::RwLock::new(api_secret.clone()),
             api_secret,
             decoders,
             db: db.clone(),
             federation_id: fed_id,
             federation_meta: config.global.meta,
             primary_module_instance,
+            late_primary_module_instance: OnceLock::new(),
+            primary_module_kinds: self.primary_module_kinds,
             modules,
             module_inits: self.module_inits.clone(),
             executor,
             api,
+            api_swap,
             secp_ctx: Secp256k1::new(),
             root_secret,
             task_group,
             operation_log: OperationLog::new(db),
             client_recovery_progress_receiver,
             meta_service: self.meta_service,
+            api_request_hook: self.api_request_hook,
+            request_timeout: self.request_timeout,
+            reconnect_policy: self.reconnect_policy,
+            tx_submission_retry_limit: self.tx_submission_retry_limit,
+            skipped_modules,
+            late_modules,
+            final_client: final_client.clone(),
+            admin_auth: self.admin_creds.as_ref().map(|creds| creds.auth.clone()),
+            block_count_cache: BlockCountCache::default(),
+            tx_submission_context,
+            started_modules: std::sync::Mutex::default(),
+            api_version_sender,
         });
+        client_inner.spawn_api_version_refresh_task();
         client_inner
             .task_group
             .spawn_cancellable("MetaService::update_continuously", {
@@ -2449,11 +3420,22 @@ impl ClientBuilder {
 
         let client_arc = ClientHandle::new(client_inner);
 
-        for (_, _, module) in client_arc.modules.iter_modules() {
+        // Modules may already use the client when they are started
+        final_client.set(client_arc.downgrade());
+
+        let mut modules_to_start = client_arc.modules.iter_modules().collect::<Vec<_>>();
+        modules_to_start.sort_by_key(|(module_instance_id, _, _)| {
+            Some(*module_instance_id) != client_arc.primary_module_instance
+        });
+        for (module_instance_id, _, module) in modules_to_start {
             module.start().await;
+            client_arc
+                .started_modules
+                .lock()
+                .expect("poisoned")
+                .insert(module_instance_id);
         }
-
-        final_client.set(client_arc.downgrade());
+        client_arc.spawn_enable_compatible_modules_task(client_arc.downgrade());
 
         if !module_recoveries.is_empty() {
             client_arc.spawn_module_recoveries_task(
@@ -1,6 +1,6 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, VecDeque};
 use std::pin::pin;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, SystemTime};
 
 use anyhow::{bail, Context as _};
@@ -53,16 +53,55 @@ pub struct MetaValue<T> {
     pub value: Option<T>,
 }
 
+impl<T> MetaValue<T> {
+    /// Time since the value was fetched, zero if the clock went backwards
+    pub fn age(&self) -> Duration {
+        fedimint_core::time::now()
+            .duration_since(self.fetch_time)
+            .unwrap_or_default()
+    }
+
+    /// Whether the value was fetched longer than `max_age` ago, e.g. because
+    /// the meta source has been unreachable since, and might be outdated
+    pub fn is_stale(&self, max_age: Duration) -> bool {
+        max_age < self.age()
+    }
+}
+
+/// Number of fetched meta snapshots a [`MetaService`] keeps by default, see
+/// [`MetaService::recent_history`]
+pub const DEFAULT_META_HISTORY_SIZE: usize = 16;
+
+/// All meta fields saved by one fetch of a [`MetaService`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetaSnapshot {
+    pub fetch_time: SystemTime,
+    pub revision: u64,
+    pub values: BTreeMap<String, String>,
+}
+
 /// Service for managing the caching of meta fields.
 // a fancy DST to save one allocation.
 pub struct MetaService<S: ?Sized = dyn MetaSource> {
     initial_fetch_waiter: Waiter,
     meta_update_notify: Notify,
+    /// Most recent snapshots, oldest first, only kept in memory
+    history: Mutex<VecDeque<MetaSnapshot>>,
+    history_size: usize,
     source: S,
 }
 
 impl<S: MetaSource + ?Sized> MetaService<S> {
     pub fn new(source: S) -> Arc<MetaService>
+    where
+        S: Sized,
+    {
+        Self::new_with_history_size(source, DEFAULT_META_HISTORY_SIZE)
+    }
+
+    /// Like [`Self::new`], but keeping the last `history_size` snapshots
+    /// instead of [`DEFAULT_META_HISTORY_SIZE`]
+    pub fn new_with_history_size(source: S, history_size: usize) -> Arc<MetaService>
     where
         S: Sized,
     {
@@ -70,10 +109,35 @@ impl<S: MetaSource + ?Sized> MetaService<S> {
         Arc::new(MetaService {
             initial_fetch_waiter: Waiter::new(),
             meta_update_notify: Notify::new(),
+            history: Mutex::new(VecDeque::with_capacity(history_size)),
+            history_size,
             source,
         })
     }
 
+    /// Meta fields of the most recent fetches since the client started,
+    /// oldest first, e.g. to show what changed between consecutive fetches
+    pub fn recent_history(&self) -> Vec<MetaSnapshot> {
+        self.history
+            .lock()
+            .expect("lock poisoned")
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    fn record_snapshot(&self, snapshot: MetaSnapshot) {
+        if self.history_size == 0 {
+            return;
+        }
+
+        let mut history = self.history.lock().expect("lock poisoned");
+        while history.len() >= self.history_size {
+            history.pop_front();
+        }
+        history.push_back(snapshot);
+    }
+
     /// Get the value for the meta field.
     ///
     /// This may wait for significant time on first run.
@@ -110,6 +174,24 @@ impl<S: MetaSource + ?Sized> MetaService<S> {
         })
     }
 
+    /// Time since meta fields were last fetched successfully, `None` if they
+    /// never were
+    ///
+    /// The background updates keep retrying failed fetches without reporting
+    /// errors, so this is how to tell that the meta fields might be outdated.
+    pub async fn last_update_age(&self, db: &Database) -> Option<Duration> {
+        let info = db
+            .begin_transaction_nc()
+            .await
+            .get_value(&MetaServiceInfoKey)
+            .await?;
+        Some(
+            fedimint_core::time::now()
+                .duration_since(info.last_updated)
+                .unwrap_or_default(),
+        )
+    }
+
     async fn current_revision(&self, dbtx: &mut DatabaseTransaction<'_>) -> Option<u64> {
         dbtx.get_value(&MetaServiceInfoKey)
             .await
@@ -201,12 +283,13 @@ impl<S: MetaSource + ?Sized> MetaService<S> {
     }
 
     async fn save_meta_values(&self, client: &Client, meta_values: &MetaValues) {
+        let fetch_time = fedimint_core::time::now();
         let mut dbtx = client.db().begin_transaction().await;
         dbtx.remove_by_prefix(&MetaFieldPrefix).await;
         dbtx.insert_entry(
             &MetaServiceInfoKey,
             &MetaServiceInfo {
-                last_updated: fedimint_core::time::now(),
+                last_updated: fetch_time,
                 revision: meta_values.revision,
             },
         )
@@ -215,6 +298,15 @@ impl<S: MetaSource + ?Sized> MetaService<S> {
             dbtx.insert_entry(key, value).await;
         }
         dbtx.commit_tx().await;
+        self.record_snapshot(MetaSnapshot {
+            fetch_time,
+            revision: meta_values.revision,
+            values: meta_values
+                .values
+                .iter()
+                .map(|(key, value)| (key.0.clone(), value.0.clone()))
+                .collect(),
+        });
         // notify everyone about changes
         self.meta_update_notify.notify_waiters();
     }
@@ -338,3 +430,72 @@ pub fn parse_meta_value_static<V: DeserializeOwned + 'static>(
         res
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+
+    use fedimint_core::db::mem_impl::MemDatabase;
+    use fedimint_core::db::{Database, IDatabaseTransactionOpsCoreTyped};
+
+    use super::{LegacyMetaSource, MetaService, MetaSnapshot, MetaValue};
+    use crate::db::{MetaServiceInfo, MetaServiceInfoKey};
+
+    fn snapshot(revision: u64) -> MetaSnapshot {
+        MetaSnapshot {
+            fetch_time: fedimint_core::time::now(),
+            revision,
+            values: BTreeMap::from([("revision".to_owned(), revision.to_string())]),
+        }
+    }
+
+    #[test]
+    fn history_evicts_oldest_snapshot() {
+        let meta_service = MetaService::new_with_history_size(LegacyMetaSource::default(), 3);
+        assert!(meta_service.recent_history().is_empty());
+
+        for revision in 0..5 {
+            meta_service.record_snapshot(snapshot(revision));
+        }
+
+        let revisions = meta_service
+            .recent_history()
+            .into_iter()
+            .map(|snapshot| snapshot.revision)
+            .collect::<Vec<_>>();
+        assert_eq!(revisions, vec![2, 3, 4]);
+    }
+
+    #[tokio::test]
+    async fn last_update_age_grows_while_updates_stall() {
+        let meta_service = MetaService::new(LegacyMetaSource::default());
+        let db = Database::new(MemDatabase::new(), Default::default());
+        assert_eq!(meta_service.last_update_age(&db).await, None);
+
+        // The last successful fetch happened an hour ago and none since
+        let last_updated = fedimint_core::time::now() - Duration::from_secs(60 * 60);
+        let mut dbtx = db.begin_transaction().await;
+        dbtx.insert_entry(
+            &MetaServiceInfoKey,
+            &MetaServiceInfo {
+                last_updated,
+                revision: 0,
+            },
+        )
+        .await;
+        dbtx.commit_tx().await;
+
+        let age = meta_service.last_update_age(&db).await.unwrap();
+        assert!(Duration::from_secs(60 * 60) <= age);
+        fedimint_core::runtime::sleep(Duration::from_millis(10)).await;
+        assert!(age < meta_service.last_update_age(&db).await.unwrap());
+
+        let value = MetaValue {
+            fetch_time: last_updated,
+            value: Some(()),
+        };
+        assert!(value.is_stale(Duration::from_secs(10 * 60)));
+        assert!(!value.is_stale(Duration::from_secs(2 * 60 * 60)));
+    }
+}
@@ -222,6 +222,14 @@ pub trait ClientModuleInit: ModuleInit + Sized {
     /// that this client module implementation can use.
     fn supported_api_versions(&self) -> MultiApiVersion;
 
+    /// Whether modules created by this init support being a primary module,
+    /// allows checking before a module is initialized. Must match
+    /// [`ClientModule::supports_being_primary`] of [`Self::Module`], which the
+    /// client asserts when initializing the module.
+    fn supports_being_primary(&self) -> bool {
+        false
+    }
+
     /// Recover the state of the client module, optionally from an existing
     /// snapshot.
     ///
@@ -261,6 +269,9 @@ pub trait IClientModuleInit: IDynCommonModuleInit + Debug + MaybeSend + MaybeSyn
     /// See [`ClientModuleInit::supported_api_versions`]
     fn supported_api_versions(&self) -> MultiApiVersion;
 
+    /// See [`ClientModuleInit::supports_being_primary`]
+    fn supports_being_primary(&self) -> bool;
+
     #[allow(clippy::too_many_arguments)]
     async fn recover(
         &self,
@@ -322,6 +333,10 @@ where
         <Self as ClientModuleInit>::supported_api_versions(self)
     }
 
+    fn supports_being_primary(&self) -> bool {
+        <Self as ClientModuleInit>::supports_being_primary(self)
+    }
+
     async fn recover(
         &self,
         final_client: FinalClient,
@@ -3,6 +3,7 @@ use std::any::Any;
 use std::fmt::Debug;
 use std::marker::PhantomData;
 use std::sync::Arc;
+use std::time::Duration;
 use std::{ffi, marker, ops};
 
 use anyhow::{anyhow, bail};
@@ -413,6 +414,29 @@ where
             .await
     }
 
+    /// See [`crate::Client::finalize_and_submit_transactions`]
+    pub async fn finalize_and_submit_transactions<F, Meta>(
+        &self,
+        operation_id: OperationId,
+        operation_type: &str,
+        operation_meta: F,
+        tx_builders: Vec<TransactionBuilder>,
+    ) -> anyhow::Result<Vec<(TransactionId, Vec<OutPoint>)>>
+    where
+        F: Fn(Vec<(TransactionId, Vec<OutPoint>)>) -> Meta + Clone + MaybeSend + MaybeSync,
+        Meta: serde::Serialize + MaybeSend,
+    {
+        self.client
+            .get()
+            .finalize_and_submit_transactions(
+                operation_id,
+                operation_type,
+                operation_meta,
+                tx_builders,
+            )
+            .await
+    }
+
     /// See [`crate::Client::transaction_updates`]
     pub async fn transaction_updates(&self, operation_id: OperationId) -> TransactionUpdates {
         self.client.get().transaction_updates(operation_id).await
@@ -486,6 +510,24 @@ where
             .collect()
     }
 
+    /// See [`crate::Client::primary_module_instance`]
+    pub fn primary_module_instance(&self) -> Option<ModuleInstanceId> {
+        self.client.get().primary_module_instance()
+    }
+
+    /// See [`crate::Client::is_module_started`]
+    pub fn is_module_started(&self, module_instance_id: ModuleInstanceId) -> bool {
+        self.client.get().is_module_started(module_instance_id)
+    }
+
+    /// See [`crate::Client::consensus_block_count_cached`]
+    pub async fn consensus_block_count_cached(&self, max_age: Duration) -> anyhow::Result<u64> {
+        self.client
+            .get()
+            .consensus_block_count_cached(max_age)
+            .await
+    }
+
     pub fn get_config(&self) -> ClientConfig {
         self.client.get().get_config().clone()
     }
@@ -503,7 +545,7 @@ where
             any_guardian_url.url.clone(),
             *any_guardian_id,
             cfg.calculate_federation_id(),
-            self.client.get().api_secret().clone(),
+            self.client.get().current_api_secret(),
         )
     }
 
@@ -592,6 +634,9 @@ pub trait ClientModule: Debug + MaybeSend + MaybeSync + 'static {
     /// Called by the core client code on start, after [`ClientContext`] is
     /// fully initialized, so unlike during [`ClientModuleInit::init`],
     /// access to global client is allowed.
+    ///
+    /// The primary module is initialized and started before all other
+    /// modules, so it is ready to be used when they are started.
     async fn start(&self) {}
 
     async fn handle_cli_command(
@@ -631,6 +676,20 @@ pub trait ClientModule: Debug + MaybeSend + MaybeSync + 'static {
         anyhow::bail!("Backup not supported");
     }
 
+    /// Can this module fetch the federation's consensus block count
+    ///
+    /// If it does it must implement [`Self::fetch_consensus_block_count`], the
+    /// client then uses it as the source of
+    /// [`crate::Client::consensus_block_count_cached`].
+    fn provides_consensus_block_count(&self) -> bool {
+        false
+    }
+
+    /// Fetches the current consensus block count from the federation
+    async fn fetch_consensus_block_count(&self) -> anyhow::Result<u64> {
+        anyhow::bail!("Consensus block count not supported");
+    }
+
     /// Does this module support being a primary module
     ///
     /// If it does it must implement:
@@ -783,6 +842,10 @@ pub trait IClientModule: Debug {
 
     fn supports_being_primary(&self) -> bool;
 
+    fn provides_consensus_block_count(&self) -> bool;
+
+    async fn fetch_consensus_block_count(&self) -> anyhow::Result<u64>;
+
     async fn create_final_inputs_and_outputs(
         &self,
         module_instance: ModuleInstanceId,
@@ -873,6 +936,14 @@ where
         <T as ClientModule>::supports_being_primary(self)
     }
 
+    fn provides_consensus_block_count(&self) -> bool {
+        <T as ClientModule>::provides_consensus_block_count(self)
+    }
+
+    async fn fetch_consensus_block_count(&self) -> anyhow::Result<u64> {
+        <T as ClientModule>::fetch_consensus_block_count(self).await
+    }
+
     async fn create_final_inputs_and_outputs(
         &self,
         module_instance: ModuleInstanceId,
@@ -1,6 +1,7 @@
 use std::fmt::Debug;
 use std::future;
 use std::io::{Read, Write};
+use std::time::SystemTime;
 
 use async_stream::stream;
 use fedimint_core::core::OperationId;
@@ -10,14 +11,17 @@ use fedimint_core::module::registry::ModuleDecoderRegistry;
 use fedimint_core::task::{MaybeSend, MaybeSync};
 use fedimint_core::time::now;
 use fedimint_core::util::BoxStream;
+use fedimint_core::Amount;
 use futures::{stream, Stream, StreamExt};
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use tracing::{error, instrument, warn};
 
 use crate::db::{
-    ChronologicalOperationLogKey, ChronologicalOperationLogKeyPrefix, OperationLogKey,
+    ChronologicalOperationLogKey, ChronologicalOperationLogKeyPrefix, OperationFeesKey,
+    OperationLogKey,
 };
+use crate::sm::executor::{ActiveOperationStateKeyPrefixBytes, InactiveOperationStateKeyPrefix};
 
 #[derive(Debug, Clone)]
 pub struct OperationLog {
@@ -43,6 +47,7 @@ impl OperationLog {
                 meta: serde_json::to_value(operation_meta)
                     .expect("Can only fail if meta is not serializable"),
                 outcome: None,
+                fees_paid: Amount::ZERO,
             },
         )
         .await;
@@ -56,6 +61,23 @@ impl OperationLog {
         .await;
     }
 
+    /// Adds `fees` paid by a transaction of the operation to the fees reported
+    /// by [`OperationLogEntry::fees_paid`], also if the operation wasn't
+    /// logged yet
+    pub(crate) async fn add_fees_paid(
+        dbtx: &mut DatabaseTransaction<'_>,
+        operation_id: OperationId,
+        fees: Amount,
+    ) {
+        if fees == Amount::ZERO {
+            return;
+        }
+
+        let fees_key = OperationFeesKey { operation_id };
+        let fees_paid = dbtx.get_value(&fees_key).await.unwrap_or(Amount::ZERO);
+        dbtx.insert_entry(&fees_key, &(fees_paid + fees)).await;
+    }
+
     /// Returns the last `limit` operations. To fetch the next page, pass the
     /// last operation's [`ChronologicalOperationLogKey`] as `start_after`.
     pub async fn list_operations(
@@ -92,12 +114,14 @@ impl OperationLog {
         let mut operation_entries = Vec::with_capacity(operations.len());
 
         for operation in operations {
-            let entry = dbtx
+            let mut entry = dbtx
                 .get_value(&OperationLogKey {
                     operation_id: operation.operation_id,
                 })
                 .await
                 .expect("Inconsistent DB");
+            entry.fees_paid =
+                Self::get_fees_paid(&mut dbtx.to_ref_nc(), operation.operation_id).await;
             operation_entries.push((operation, entry));
         }
 
@@ -105,11 +129,19 @@ impl OperationLog {
     }
 
     pub async fn get_operation(&self, operation_id: OperationId) -> Option<OperationLogEntry> {
-        Self::get_operation_inner(
-            &mut self.db.begin_transaction().await.into_nc(),
-            operation_id,
-        )
-        .await
+        let mut dbtx = self.db.begin_transaction_nc().await;
+        let mut entry = Self::get_operation_inner(&mut dbtx, operation_id).await?;
+        entry.fees_paid = Self::get_fees_paid(&mut dbtx, operation_id).await;
+        Some(entry)
+    }
+
+    async fn get_fees_paid(
+        dbtx: &mut DatabaseTransaction<'_>,
+        operation_id: OperationId,
+    ) -> Amount {
+        dbtx.get_value(&OperationFeesKey { operation_id })
+            .await
+            .unwrap_or(Amount::ZERO)
     }
 
     async fn get_operation_inner(
@@ -119,6 +151,46 @@ impl OperationLog {
         dbtx.get_value(&OperationLogKey { operation_id }).await
     }
 
+    /// Deletes all operations created before `cutoff` that have finished,
+    /// together with their inactive state machine states, and returns how
+    /// many were removed. An operation is finished once none of its state
+    /// machines are active anymore, whether or not its outcome was cached.
+    pub async fn prune_completed_before(&self, cutoff: SystemTime) -> usize {
+        let mut dbtx = self.db.begin_transaction().await;
+        // The index is ordered by creation time, so we can stop at the cutoff
+        let candidates: Vec<ChronologicalOperationLogKey> = dbtx
+            .find_by_prefix(&ChronologicalOperationLogKeyPrefix)
+            .await
+            .map(|(key, ())| key)
+            .take_while(|key| future::ready(key.creation_time < cutoff))
+            .collect()
+            .await;
+
+        let mut pruned = 0;
+        for key in candidates {
+            let operation_id = key.operation_id;
+            let has_active_states = dbtx
+                .find_by_prefix(&ActiveOperationStateKeyPrefixBytes { operation_id })
+                .await
+                .next()
+                .await
+                .is_some();
+            if has_active_states {
+                continue;
+            }
+
+            dbtx.remove_entry(&key).await;
+            dbtx.remove_entry(&OperationLogKey { operation_id }).await;
+            dbtx.remove_entry(&OperationFeesKey { operation_id }).await;
+            dbtx.remove_by_prefix(&InactiveOperationStateKeyPrefix { operation_id })
+                .await;
+            pruned += 1;
+        }
+        dbtx.commit_tx().await;
+
+        pruned
+    }
+
     /// Sets the outcome of an operation
     #[instrument(skip(db), level = "debug")]
     pub async fn set_operation_outcome(
@@ -180,6 +252,11 @@ pub struct OperationLogEntry {
     meta: serde_json::Value,
     // TODO: probably change all that JSON to Dyn-types
     pub(crate) outcome: Option<serde_json::Value>,
+    /// Fees paid by all transactions of the operation, zero for operations
+    /// logged before fees were recorded. Not part of the encoding, it is read
+    /// from [`OperationFeesKey`] instead.
+    #[serde(default)]
+    fees_paid: Amount,
 }
 
 impl OperationLogEntry {
@@ -197,6 +274,12 @@ impl OperationLogEntry {
         serde_json::from_value(self.meta.clone()).expect("JSON deserialization should not fail")
     }
 
+    /// Returns the fees paid by all transactions submitted as part of the
+    /// operation
+    pub fn fees_paid(&self) -> Amount {
+        self.fees_paid
+    }
+
     /// Returns the last state update of the operation, if any was cached yet.
     /// If this hasn't been the case yet and `None` is returned subscribe to the
     /// appropriate update stream.
@@ -283,6 +366,7 @@ impl Decodable for OperationLogEntry {
             operation_module_kind: operation_type,
             meta,
             outcome,
+            fees_paid: Amount::ZERO,
         })
     }
 }
@@ -341,15 +425,23 @@ where
 
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
+
     use fedimint_core::core::OperationId;
     use fedimint_core::db::mem_impl::MemDatabase;
-    use fedimint_core::db::{Database, IRawDatabaseExt};
+    use fedimint_core::db::{Database, IDatabaseTransactionOpsCoreTyped, IRawDatabaseExt};
+    use fedimint_core::encoding::{Decodable, Encodable};
+    use fedimint_core::module::registry::ModuleDecoderRegistry;
+    use fedimint_core::runtime::sleep;
+    use fedimint_core::time::now;
+    use fedimint_core::Amount;
     use futures::stream::StreamExt;
     use serde::{Deserialize, Serialize};
 
     use super::UpdateStreamOrOutcome;
     use crate::db::ChronologicalOperationLogKey;
     use crate::oplog::{OperationLog, OperationLogEntry};
+    use crate::sm::executor::{ActiveStateKeyBytes, ActiveStateMeta};
 
     #[test]
     fn test_operation_log_entry_serde() {
@@ -357,6 +449,7 @@ mod tests {
             operation_module_kind: "test".to_string(),
             meta: serde_json::to_value(()).unwrap(),
             outcome: None,
+            fees_paid: Amount::ZERO,
         };
 
         op_log.meta::<()>();
@@ -379,11 +472,64 @@ mod tests {
             operation_module_kind: "test".to_string(),
             meta: serde_json::to_value(meta.clone()).unwrap(),
             outcome: None,
+            fees_paid: Amount::ZERO,
         };
 
         assert_eq!(op_log.meta::<Meta>(), meta);
     }
 
+    #[test]
+    fn test_operation_log_entry_encoding_excludes_fees_paid() {
+        // Fees are stored separately, so the encoding of entries is unchanged
+        let mut expected_bytes = vec![];
+        "test"
+            .to_string()
+            .consensus_encode(&mut expected_bytes)
+            .unwrap();
+        "null"
+            .to_string()
+            .consensus_encode(&mut expected_bytes)
+            .unwrap();
+        Some("\"done\"".to_string())
+            .consensus_encode(&mut expected_bytes)
+            .unwrap();
+
+        let op_log = OperationLogEntry {
+            operation_module_kind: "test".to_string(),
+            meta: serde_json::to_value(()).unwrap(),
+            outcome: Some(serde_json::to_value("done").unwrap()),
+            fees_paid: Amount::from_sats(3),
+        };
+        assert_eq!(op_log.consensus_encode_to_vec(), expected_bytes);
+
+        let decoded = OperationLogEntry::consensus_decode_vec(
+            expected_bytes,
+            &ModuleDecoderRegistry::default(),
+        )
+        .unwrap();
+        assert_eq!(decoded.fees_paid(), Amount::ZERO);
+        assert_eq!(decoded.outcome::<String>(), Some("done".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_fees_paid_before_operation_is_logged() {
+        let op_id = OperationId([0x42; 32]);
+
+        let db = Database::new(MemDatabase::new(), Default::default());
+        let op_log = OperationLog::new(db.clone());
+
+        let mut dbtx = db.begin_transaction().await;
+        OperationLog::add_fees_paid(&mut dbtx.to_ref_nc(), op_id, Amount::from_sats(1)).await;
+        op_log
+            .add_operation_log_entry(&mut dbtx.to_ref_nc(), op_id, "foo", "bar")
+            .await;
+        OperationLog::add_fees_paid(&mut dbtx.to_ref_nc(), op_id, Amount::from_sats(2)).await;
+        dbtx.commit_tx().await;
+
+        let op = op_log.get_operation(op_id).await.expect("op exists");
+        assert_eq!(op.fees_paid(), Amount::from_sats(3));
+    }
+
     #[tokio::test]
     async fn test_operation_log_update() {
         let op_id = OperationId([0x32; 32]);
@@ -448,6 +594,66 @@ mod tests {
         assert_eq!(op_updated.outcome::<String>(), Some("baz".to_string()));
     }
 
+    #[tokio::test]
+    async fn test_prune_completed_before() {
+        let db = MemDatabase::new().into_database();
+        let op_log = OperationLog::new(db.clone());
+
+        let add_operation = |operation_id: OperationId| {
+            let db = db.clone();
+            let op_log = op_log.clone();
+            async move {
+                let mut dbtx = db.begin_transaction().await;
+                op_log
+                    .add_operation_log_entry(&mut dbtx.to_ref_nc(), operation_id, "foo", "bar")
+                    .await;
+                dbtx.commit_tx().await;
+            }
+        };
+
+        let old_completed = OperationId([0x01; 32]);
+        let old_pending = OperationId([0x02; 32]);
+        let recent_completed = OperationId([0x03; 32]);
+
+        // The outcome of `old_completed` is never cached, it is pruned anyway
+        // since none of its state machines are active
+        add_operation(old_completed).await;
+        add_operation(old_pending).await;
+        let mut dbtx = db.begin_transaction().await;
+        dbtx.insert_new_entry(
+            &ActiveStateKeyBytes {
+                operation_id: old_pending,
+                module_instance_id: 0,
+                state: vec![0, 0, 1, 2, 3],
+            },
+            &ActiveStateMeta::default(),
+        )
+        .await;
+        dbtx.commit_tx().await;
+
+        sleep(Duration::from_millis(10)).await;
+        let cutoff = now();
+        sleep(Duration::from_millis(10)).await;
+
+        add_operation(recent_completed).await;
+        OperationLog::set_operation_outcome(&db, recent_completed, &"done")
+            .await
+            .unwrap();
+
+        assert_eq!(op_log.prune_completed_before(cutoff).await, 1);
+        assert!(op_log.get_operation(old_completed).await.is_none());
+        assert!(op_log.get_operation(old_pending).await.is_some());
+        assert!(op_log.get_operation(recent_completed).await.is_some());
+
+        let remaining = op_log.list_operations(10, None).await;
+        assert_eq!(remaining.len(), 2);
+        assert!(remaining
+            .iter()
+            .all(|(key, _)| key.operation_id != old_completed));
+
+        assert_eq!(op_log.prune_completed_before(cutoff).await, 0);
+    }
+
     #[tokio::test]
     async fn test_pagination() {
         fn assert_page_entries(
@@ -1,4 +1,4 @@
-use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::convert::Infallible;
 use std::fmt::{Debug, Formatter};
 use std::io::{Error, Read, Write};
@@ -18,8 +18,9 @@ use fedimint_core::module::registry::ModuleDecoderRegistry;
 use fedimint_core::task::TaskGroup;
 use fedimint_core::util::BoxFuture;
 use fedimint_logging::LOG_CLIENT_REACTOR;
-use futures::future::{self, select_all};
+use futures::future::{self, select_all, AbortHandle, Abortable};
 use futures::stream::{FuturesUnordered, StreamExt};
+use futures::FutureExt;
 use tokio::select;
 use tokio::sync::{mpsc, oneshot, Mutex};
 use tracing::{debug, error, info, trace, warn, Instrument};
@@ -59,14 +60,20 @@ pub struct Executor {
 struct ExecutorInner {
     db: Database,
     context: Mutex<Option<ContextGen>>,
-    module_contexts: BTreeMap<ModuleInstanceId, DynContext>,
-    valid_module_ids: BTreeSet<ModuleInstanceId>,
+    /// Extended by [`Executor::add_module_context`] when a module is
+    /// initialized after the executor was built
+    module_contexts: std::sync::RwLock<BTreeMap<ModuleInstanceId, DynContext>>,
+    valid_module_ids: std::sync::RwLock<BTreeSet<ModuleInstanceId>>,
     notifier: Notifier,
     shutdown_executor: Mutex<Option<oneshot::Sender<()>>>,
     /// Any time executor should notice state machine update (e.g. because it
     /// was created), it's must be sent through this channel for it to notice.
     sm_update_tx: mpsc::UnboundedSender<DynState>,
     sm_update_rx: Mutex<Option<mpsc::UnboundedReceiver<DynState>>>,
+    /// Active states sent through this channel have their pending transitions
+    /// dropped and re-evaluated, see [`Executor::retrigger_operation`].
+    sm_retrigger_tx: mpsc::UnboundedSender<DynState>,
+    sm_retrigger_rx: Mutex<Option<mpsc::UnboundedReceiver<DynState>>>,
     client_task_group: TaskGroup,
 }
 
@@ -131,6 +138,8 @@ impl Executor {
             if !self
                 .inner
                 .valid_module_ids
+                .read()
+                .expect("poisoned")
                 .contains(&state.module_instance_id())
             {
                 return Err(AddStateMachinesError::Other(anyhow!("Unknown module")));
@@ -153,9 +162,8 @@ impl Executor {
             // so we can't check if the state is terminal. However the
             // [`Self::get_transitions_for`] function will double check and
             // deactivate any terminal states that would slip past this check.
-            if let Some(module_context) =
-                self.inner.module_contexts.get(&state.module_instance_id())
-            {
+            let module_context = self.inner.module_context(state.module_instance_id());
+            if let Some(module_context) = module_context {
                 let context = {
                     let context_gen_guard = self.inner.context.lock().await;
                     let context_gen = context_gen_guard
@@ -164,7 +172,7 @@ impl Executor {
                     context_gen(state.module_instance_id(), state.operation_id())
                 };
 
-                if state.is_terminal(module_context, &context) {
+                if state.is_terminal(&module_context, &context) {
                     return Err(AddStateMachinesError::Other(anyhow!(
                         "State is already terminal, adding it to the executor doesn't make sense."
                     )));
@@ -263,6 +271,76 @@ impl Executor {
         (active_states, inactive_states)
     }
 
+    /// Drops the pending transitions of all active states of `operation_id`
+    /// and re-evaluates them, which can get a state machine going again that
+    /// is waiting on a trigger that will never fire. States whose transition
+    /// function is currently being executed are left alone.
+    ///
+    /// Returns the number of active states that were retriggered.
+    pub async fn retrigger_operation(&self, operation_id: OperationId) -> usize {
+        let active_states: Vec<DynState> = self
+            .inner
+            .db
+            .begin_transaction_nc()
+            .await
+            .find_by_prefix(&ActiveOperationStateKeyPrefix { operation_id })
+            .await
+            .map(|(active_key, _)| active_key.state)
+            // ignore states from modules that are not initialized yet
+            .filter(|state| {
+                future::ready(
+                    self.inner
+                        .module_context(state.module_instance_id())
+                        .is_some(),
+                )
+            })
+            .collect()
+            .await;
+
+        for state in &active_states {
+            // Fails only if the executor isn't running, in which case the state will be
+            // re-evaluated once it's started
+            let _ = self.inner.sm_retrigger_tx.send(state.clone());
+        }
+
+        active_states.len()
+    }
+
+    /// Allows the executor to run the state machines of a module that was
+    /// initialized after the executor was built, e.g. because it became
+    /// compatible with the federation. Active states of the module that were
+    /// ignored so far are picked up.
+    ///
+    /// ## Panics
+    /// If the executor already has a context for the module.
+    pub async fn add_module_context(&self, context: DynContext) {
+        let module_instance_id = context.module_instance_id();
+        self.inner
+            .valid_module_ids
+            .write()
+            .expect("poisoned")
+            .insert(module_instance_id);
+        let replaced_old_context = self
+            .inner
+            .module_contexts
+            .write()
+            .expect("poisoned")
+            .insert(module_instance_id, context)
+            .is_some();
+        assert!(
+            !replaced_old_context,
+            "Tried to add two modules with the same instance id!"
+        );
+
+        for (state, _meta) in self.inner.get_active_states().await {
+            if state.module_instance_id() == module_instance_id {
+                // Fails only if the executor isn't running, in which case the state will be
+                // picked up once it's started
+                let _ = self.inner.sm_update_tx.send(state);
+            }
+        }
+    }
+
     /// Starts the background thread that runs the state machines. This cannot
     /// be done when building the executor since some global contexts in turn
     /// may depend on the executor, forming a cyclic dependency.
@@ -288,6 +366,13 @@ impl Executor {
             .await
             .take()
             .expect("start_executor was called previously: no sm_update_rx available");
+        let sm_retrigger_rx = self
+            .inner
+            .sm_retrigger_rx
+            .lock()
+            .await
+            .take()
+            .expect("start_executor was called previously: no sm_retrigger_rx available");
 
         let (shutdown_sender, shutdown_receiver) = tokio::sync::oneshot::channel::<()>();
 
@@ -305,7 +390,7 @@ impl Executor {
 
         let task_runner_inner = self.inner.clone();
         let _handle = self.inner.client_task_group.spawn("sm-executor", |task_handle| async move {
-            let executor_runner = task_runner_inner.run(context_gen, sm_update_rx);
+            let executor_runner = task_runner_inner.run(context_gen, sm_update_rx, sm_retrigger_rx);
             let task_group_shutdown_rx = task_handle.make_shutdown_rx();
             select! {
                 () = task_group_shutdown_rx => {
@@ -370,10 +455,11 @@ impl ExecutorInner {
         &self,
         global_context_gen: ContextGen,
         sm_update_rx: tokio::sync::mpsc::UnboundedReceiver<DynState>,
+        sm_retrigger_rx: tokio::sync::mpsc::UnboundedReceiver<DynState>,
     ) {
         debug!(target: LOG_CLIENT_REACTOR, "Starting state machine executor task");
         if let Err(err) = self
-            .run_state_machines_executor_inner(global_context_gen, sm_update_rx)
+            .run_state_machines_executor_inner(global_context_gen, sm_update_rx, sm_retrigger_rx)
             .await
         {
             warn!(
@@ -390,13 +476,12 @@ impl ExecutorInner {
         global_context_gen: &ContextGen,
     ) -> Vec<BoxFuture<'static, TransitionForActiveState>> {
         let module_instance = state.module_instance_id();
-        let context = &self
-            .module_contexts
-            .get(&module_instance)
+        let context = self
+            .module_context(module_instance)
             .expect("Unknown module");
         let transitions = state
             .transitions(
-                context,
+                &context,
                 &global_context_gen(module_instance, state.operation_id()),
             )
             .into_iter()
@@ -447,6 +532,7 @@ impl ExecutorInner {
         &self,
         global_context_gen: ContextGen,
         mut sm_update_rx: tokio::sync::mpsc::UnboundedReceiver<DynState>,
+        mut sm_retrigger_rx: tokio::sync::mpsc::UnboundedReceiver<DynState>,
     ) -> anyhow::Result<()> {
         /// All futures in the executor resolve to this type, so the handling
         /// code can tell them apart.
@@ -454,6 +540,9 @@ impl ExecutorInner {
             /// Notification about `DynState` arrived and should be handled,
             /// usually added to the list of pending futures.
             New { state: DynState },
+            /// The pending transitions of `DynState` should be dropped and
+            /// re-evaluated
+            Retrigger { state: DynState },
             /// One of trigger futures of a state machine finished and
             /// returned transition function to run
             Triggered(TransitionForActiveState),
@@ -462,6 +551,9 @@ impl ExecutorInner {
                 state: DynState,
                 outcome: ActiveOrInactiveState,
             },
+            /// The trigger futures of a state machine were dropped because it
+            /// was retriggered
+            Aborted,
             /// New job receiver disconnected, that can only mean termination
             Disconnected,
         }
@@ -477,6 +569,9 @@ impl ExecutorInner {
         // Keeps track of things already running, so we can deduplicate, just
         // in case.
         let mut currently_running_sms = HashSet::<DynState>::new();
+        // Allows dropping the trigger futures of state machines that are still
+        // waiting for one of their transitions to be triggered
+        let mut pending_triggers = HashMap::<DynState, AbortHandle>::new();
         // All things happening in parallel go into here
         let mut futures: FuturesUnordered<BoxFuture<'_, ExecutorLoopEvent>> =
             FuturesUnordered::new();
@@ -493,6 +588,10 @@ impl ExecutorInner {
                     }
                 },
 
+                Some(state) = sm_retrigger_rx.recv() => {
+                    ExecutorLoopEvent::Retrigger { state }
+                },
+
                 event = futures.next(), if !futures.is_empty() => event.expect("we only .next() if there are pending futures"),
             };
 
@@ -519,11 +618,19 @@ impl ExecutorInner {
 
                     let transitions_num = transitions.len();
                     currently_running_sms.insert(state.clone());
-                    futures.push(Box::pin(async {
-                        let (first_completed_result, _index, _unused_transitions) =
-                            select_all(transitions).await;
-                        ExecutorLoopEvent::Triggered(first_completed_result)
-                    }));
+                    let (abort_handle, abort_registration) = AbortHandle::new_pair();
+                    pending_triggers.insert(state.clone(), abort_handle);
+                    futures.push(Box::pin(
+                        Abortable::new(
+                            async {
+                                let (first_completed_result, _index, _unused_transitions) =
+                                    select_all(transitions).await;
+                                ExecutorLoopEvent::Triggered(first_completed_result)
+                            },
+                            abort_registration,
+                        )
+                        .map(|result| result.unwrap_or(ExecutorLoopEvent::Aborted)),
+                    ));
 
                     debug!(target: LOG_CLIENT_REACTOR, operation_id = %state.operation_id().fmt_short(), total = futures.len(), transitions_num, "New active state machine.");
                     trace!(target: LOG_CLIENT_REACTOR, state = ?state, "Started new active state machine, details.");
@@ -534,6 +641,7 @@ impl ExecutorInner {
                     meta,
                     transition_fn,
                 }) => {
+                    pending_triggers.remove(&state);
                     debug!(
                         target: LOG_CLIENT_REACTOR,
                         operation_id = %state.operation_id().fmt_short(),
@@ -553,7 +661,8 @@ impl ExecutorInner {
                         let sm_update_tx = self.sm_update_tx.clone();
                         let db = self.db.clone();
                         let notifier = self.notifier.clone();
-                        let module_contexts = self.module_contexts.clone();
+                        let module_contexts =
+                            self.module_contexts.read().expect("poisoned").clone();
                         let global_context_gen = global_context_gen.clone();
                         Box::pin(
                             async move {
@@ -675,6 +784,21 @@ impl ExecutorInner {
                         "State transition complete"
                     );
                 }
+                ExecutorLoopEvent::Retrigger { state } => {
+                    if let Some(abort_handle) = pending_triggers.remove(&state) {
+                        abort_handle.abort();
+                        currently_running_sms.remove(&state);
+                    } else if currently_running_sms.contains(&state) {
+                        debug!(target: LOG_CLIENT_REACTOR, operation_id = %state.operation_id().fmt_short(), "State transition in progress, not retriggering");
+                        continue;
+                    }
+
+                    debug!(target: LOG_CLIENT_REACTOR, operation_id = %state.operation_id().fmt_short(), "Retriggering state machine");
+                    self.sm_update_tx
+                        .send(state)
+                        .expect("Must be able to send state machine to own opened channel");
+                }
+                ExecutorLoopEvent::Aborted => {}
                 ExecutorLoopEvent::Disconnected => {
                     break;
                 }
@@ -685,6 +809,14 @@ impl ExecutorInner {
         Ok(())
     }
 
+    fn module_context(&self, module_instance_id: ModuleInstanceId) -> Option<DynContext> {
+        self.module_contexts
+            .read()
+            .expect("poisoned")
+            .get(&module_instance_id)
+            .cloned()
+    }
+
     async fn get_active_states(&self) -> Vec<(DynState, ActiveStateMeta)> {
         self.db
             .begin_transaction()
@@ -694,8 +826,8 @@ impl ExecutorInner {
             // ignore states from modules that are not initialized yet
             .filter(|(state, _)| {
                 future::ready(
-                    self.module_contexts
-                        .contains_key(&state.state.module_instance_id()),
+                    self.module_context(state.state.module_instance_id())
+                        .is_some(),
                 )
             })
             .map(|(state, meta)| (state.state, meta))
@@ -705,12 +837,7 @@ impl ExecutorInner {
 
     async fn get_active_state(&self, state: &DynState) -> Option<ActiveStateMeta> {
         // ignore states from modules that are not initialized yet
-        if !self
-            .module_contexts
-            .contains_key(&state.module_instance_id())
-        {
-            return None;
-        }
+        self.module_context(state.module_instance_id())?;
         self.db
             .begin_transaction()
             .await
@@ -727,8 +854,8 @@ impl ExecutorInner {
             // ignore states from modules that are not initialized yet
             .filter(|(state, _)| {
                 future::ready(
-                    self.module_contexts
-                        .contains_key(&state.state.module_instance_id()),
+                    self.module_context(state.state.module_instance_id())
+                        .is_some(),
                 )
             })
             .map(|(state, meta)| (state.state, meta))
@@ -800,21 +927,25 @@ impl ExecutorBuilder {
     /// cannot be an isolated DB instance itself.
     pub fn build(self, db: Database, notifier: Notifier, client_task_group: TaskGroup) -> Executor {
         let (sm_update_tx, sm_update_rx) = tokio::sync::mpsc::unbounded_channel();
+        let (sm_retrigger_tx, sm_retrigger_rx) = tokio::sync::mpsc::unbounded_channel();
+        let instances = self.module_contexts.keys().copied().collect::<Vec<_>>();
 
         let inner = Arc::new(ExecutorInner {
             db,
             context: Mutex::new(None),
-            module_contexts: self.module_contexts,
-            valid_module_ids: self.valid_module_ids,
+            module_contexts: std::sync::RwLock::new(self.module_contexts),
+            valid_module_ids: std::sync::RwLock::new(self.valid_module_ids),
             notifier,
             shutdown_executor: Default::default(),
             sm_update_tx,
             sm_update_rx: Mutex::new(Some(sm_update_rx)),
+            sm_retrigger_tx,
+            sm_retrigger_rx: Mutex::new(Some(sm_retrigger_rx)),
             client_task_group,
         });
 
         debug!(
-            instances = ?inner.module_contexts.keys().copied().collect::<Vec<_>>(),
+            ?instances,
             "Initialized state machine executor with module instances"
         );
         Executor { inner }
@@ -977,6 +1108,23 @@ impl ::fedimint_core::db::DatabaseLookup for ActiveStateKeyPrefixBytes {
     type Record = ActiveStateKeyBytes;
 }
 
+/// Active states of an operation, without decoding them, so it also finds
+/// states of modules that aren't loaded
+#[derive(Debug)]
+pub(crate) struct ActiveOperationStateKeyPrefixBytes {
+    pub operation_id: OperationId,
+}
+
+impl Encodable for ActiveOperationStateKeyPrefixBytes {
+    fn consensus_encode<W: Write>(&self, writer: &mut W) -> Result<usize, Error> {
+        self.operation_id.consensus_encode(writer)
+    }
+}
+
+impl ::fedimint_core::db::DatabaseLookup for ActiveOperationStateKeyPrefixBytes {
+    type Record = ActiveStateKeyBytes;
+}
+
 impl Default for ActiveStateMeta {
     fn default() -> Self {
         Self {
@@ -1174,6 +1322,7 @@ impl ActiveOrInactiveState {
 #[cfg(test)]
 mod tests {
     use std::fmt::Debug;
+    use std::sync::atomic::{AtomicBool, Ordering};
     use std::sync::Arc;
     use std::time::Duration;
 
@@ -1186,6 +1335,7 @@ mod tests {
     use fedimint_core::module::registry::ModuleDecoderRegistry;
     use fedimint_core::runtime;
     use fedimint_core::task::TaskGroup;
+    use futures::future;
     use tokio::sync::broadcast::Sender;
     use tracing::{info, trace};
 
@@ -1197,6 +1347,9 @@ mod tests {
     enum MockStateMachine {
         Start,
         ReceivedNonNull(u64),
+        /// Only makes progress if `MockContext::unstalled` is set when its
+        /// transitions are evaluated
+        Stalled,
         Final,
     }
 
@@ -1257,6 +1410,17 @@ mod tests {
                         |_dbtx, (), _state| Box::pin(async { MockStateMachine::Final }),
                     )]
                 }
+                MockStateMachine::Stalled => {
+                    let unstalled = context.unstalled.load(Ordering::SeqCst);
+                    vec![StateTransition::new(
+                        async move {
+                            if !unstalled {
+                                future::pending::<()>().await;
+                            }
+                        },
+                        |_dbtx, (), _state| Box::pin(async { MockStateMachine::Final }),
+                    )]
+                }
                 MockStateMachine::Final => {
                     vec![]
                 }
@@ -1279,6 +1443,7 @@ mod tests {
     #[derive(Debug, Clone)]
     struct MockContext {
         broadcast: tokio::sync::broadcast::Sender<u64>,
+        unstalled: Arc<AtomicBool>,
     }
 
     impl IntoDynInstance for MockContext {
@@ -1291,8 +1456,9 @@ mod tests {
 
     impl Context for MockContext {}
 
-    async fn get_executor() -> (Executor, Sender<u64>, Database) {
+    async fn get_executor() -> (Executor, Sender<u64>, Arc<AtomicBool>, Database) {
         let (broadcast, _) = tokio::sync::broadcast::channel(10);
+        let unstalled = Arc::new(AtomicBool::new(false));
 
         let mut decoder_builder = Decoder::builder();
         decoder_builder.with_decodable_type::<MockStateMachine>();
@@ -1307,6 +1473,7 @@ mod tests {
             42,
             MockContext {
                 broadcast: broadcast.clone(),
+                unstalled: unstalled.clone(),
             },
         );
         let executor =
@@ -1316,7 +1483,7 @@ mod tests {
             .await;
 
         info!("Initialized test executor");
-        (executor, broadcast, db)
+        (executor, broadcast, unstalled, db)
     }
 
     #[tokio::test]
@@ -1325,7 +1492,7 @@ mod tests {
         const MOCK_INSTANCE_1: ModuleInstanceId = 42;
         const MOCK_INSTANCE_2: ModuleInstanceId = 21;
 
-        let (executor, sender, _db) = get_executor().await;
+        let (executor, sender, _unstalled, _db) = get_executor().await;
         executor
             .add_state_machines(vec![DynState::from_typed(
                 MOCK_INSTANCE_1,
@@ -1370,4 +1537,52 @@ mod tests {
             "State was written to DB and waits for broadcast"
         );
     }
+
+    #[tokio::test]
+    async fn test_retrigger_stalled_state_machine() {
+        const MOCK_INSTANCE: ModuleInstanceId = 42;
+
+        let (executor, _sender, unstalled, _db) = get_executor().await;
+        executor
+            .add_state_machines(vec![DynState::from_typed(
+                MOCK_INSTANCE,
+                MockStateMachine::Stalled,
+            )])
+            .await
+            .unwrap();
+
+        runtime::sleep(Duration::from_secs(1)).await;
+        assert!(
+            executor
+                .contains_active_state(MOCK_INSTANCE, MockStateMachine::Stalled)
+                .await,
+            "State machine is stuck"
+        );
+
+        // Fixing the cause of the stall alone doesn't help since the pending
+        // transition was evaluated before
+        unstalled.store(true, Ordering::SeqCst);
+        runtime::sleep(Duration::from_secs(1)).await;
+        assert!(
+            executor
+                .contains_active_state(MOCK_INSTANCE, MockStateMachine::Stalled)
+                .await
+        );
+
+        assert_eq!(
+            executor.retrigger_operation(OperationId([0u8; 32])).await,
+            1
+        );
+        runtime::sleep(Duration::from_secs(1)).await;
+        assert!(
+            executor
+                .contains_inactive_state(MOCK_INSTANCE, MockStateMachine::Final)
+                .await,
+            "Retriggered state machine completed"
+        );
+        assert_eq!(
+            executor.retrigger_operation(OperationId([0u8; 32])).await,
+            0
+        );
+    }
 }
@@ -1,3 +1,4 @@
+use std::ops::Range;
 use std::sync::Arc;
 
 use bitcoin::key::KeyPair;
@@ -11,6 +12,21 @@ use secp256k1_zkp::Secp256k1;
 use crate::module::StateGenerator;
 use crate::sm::DynState;
 
+/// A transaction finalized by [`crate::Client::build_transaction_dry_run`]
+/// without being submitted
+#[derive(Debug, Clone)]
+pub struct TransactionDryRun {
+    /// The balanced transaction as it would have been submitted
+    pub transaction: Transaction,
+    /// Indices of the inputs added by the primary module to fund the
+    /// transaction
+    pub funding_inputs: Range<u64>,
+    /// Indices of the change outputs added by the primary module
+    pub change_outputs: Range<u64>,
+    /// Sum of the fees of all inputs and outputs of the transaction
+    pub fee: Amount,
+}
+
 #[derive(Clone)]
 pub struct ClientInput<I = DynInput, S = DynState> {
     pub input: I,
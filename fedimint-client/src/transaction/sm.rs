@@ -1,5 +1,7 @@
 //! State machine for submitting transactions
 
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use fedimint_core::core::{Decoder, IntoDynInstance, ModuleInstanceId, OperationId};
@@ -8,6 +10,7 @@ use fedimint_core::runtime::sleep;
 use fedimint_core::transaction::{Transaction, TransactionSubmissionOutcome};
 use fedimint_core::TransactionId;
 use fedimint_logging::LOG_CLIENT_NET_API;
+use serde::{Deserialize, Serialize};
 use tracing::warn;
 
 use crate::sm::{Context, DynContext, OperationState, State, StateTransition};
@@ -22,10 +25,69 @@ pub const LOG_TARGET: &str = "transaction_submission";
 const RETRY_INTERVAL: Duration = Duration::from_secs(5);
 
 #[derive(Debug, Clone)]
-pub struct TxSubmissionContext;
+pub struct TxSubmissionContext {
+    /// Submission progress of the transactions in the `Created` state, only
+    /// kept in memory since the client was started
+    progress: Arc<Mutex<BTreeMap<TransactionId, PendingTxSubmission>>>,
+    /// Number of times a submission is retried after a transient failure
+    /// before giving up, `None` retries forever
+    retry_limit: Option<u32>,
+}
+
+impl TxSubmissionContext {
+    pub(crate) fn new(retry_limit: Option<u32>) -> Self {
+        Self {
+            progress: Arc::default(),
+            retry_limit,
+        }
+    }
+
+    /// Returns the submission progress of `txid`, see
+    /// [`crate::Client::pending_tx_submissions`]
+    pub(crate) fn pending_submission(&self, txid: TransactionId) -> PendingTxSubmission {
+        self.progress
+            .lock()
+            .expect("poisoned")
+            .get(&txid)
+            .cloned()
+            .unwrap_or(PendingTxSubmission {
+                txid,
+                attempts: 0,
+                last_error: None,
+            })
+    }
+
+    fn record_attempt(&self, txid: TransactionId, error: Option<String>) {
+        let mut progress = self.progress.lock().expect("poisoned");
+        let submission = progress.entry(txid).or_insert(PendingTxSubmission {
+            txid,
+            attempts: 0,
+            last_error: None,
+        });
+        submission.attempts += 1;
+        if error.is_some() {
+            submission.last_error = error;
+        }
+    }
+
+    fn remove(&self, txid: TransactionId) {
+        self.progress.lock().expect("poisoned").remove(&txid);
+    }
+}
 
 impl Context for TxSubmissionContext {}
 
+/// A transaction that was neither accepted nor rejected by the federation yet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingTxSubmission {
+    pub txid: TransactionId,
+    /// Number of times the transaction was submitted since the client was
+    /// started
+    pub attempts: u32,
+    /// Error of the last failed submission attempt, if any
+    pub last_error: Option<String>,
+}
+
 impl IntoDynInstance for TxSubmissionContext {
     type DynType = DynContext;
 
@@ -42,6 +104,7 @@ impl IntoDynInstance for TxSubmissionContext {
 /// flowchart LR
 ///     Created -- tx is accepted by consensus --> Accepted
 ///     Created -- tx is rejected on submission --> Rejected
+///     Created -- retry limit reached --> SubmissionFailed
 /// ```
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Decodable, Encodable)]
 pub enum TxSubmissionStates {
@@ -61,6 +124,20 @@ pub enum TxSubmissionStates {
     // but due to some rust bug/limitation it seem impossible to prevent
     // existing usages from spamming compilation output with warnings.
     NonRetryableError(String),
+    /// The submission failed transiently (e.g. timed out) more often than
+    /// the configured retry limit allows, the string is the last error
+    ///
+    /// **This state is final**
+    SubmissionFailed(TransactionId, String),
+}
+
+/// How the submission of a transaction ended without it being accepted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum SubmissionError {
+    /// Permanent failure, the transaction was rejected by a quorum
+    Rejected(String),
+    /// The retry limit for transient failures was reached
+    RetriesExhausted(String),
 }
 
 impl State for TxSubmissionStates {
@@ -68,28 +145,48 @@ impl State for TxSubmissionStates {
 
     fn transitions(
         &self,
-        _context: &Self::ModuleContext,
+        context: &Self::ModuleContext,
         global_context: &DynGlobalClientContext,
     ) -> Vec<StateTransition<Self>> {
         match self {
             TxSubmissionStates::Created(transaction) => {
                 let txid = transaction.tx_hash();
+                let rejected_context = context.clone();
+                let accepted_context = context.clone();
                 vec![
                     StateTransition::new(
-                        Self::trigger_created_rejected(transaction.clone(), global_context.clone()),
+                        Self::trigger_created_rejected(
+                            transaction.clone(),
+                            context.clone(),
+                            global_context.clone(),
+                        ),
                         move |_, error, _| {
-                            Box::pin(async move { TxSubmissionStates::Rejected(txid, error) })
+                            rejected_context.remove(txid);
+                            Box::pin(async move {
+                                match error {
+                                    SubmissionError::Rejected(error) => {
+                                        TxSubmissionStates::Rejected(txid, error)
+                                    }
+                                    SubmissionError::RetriesExhausted(error) => {
+                                        TxSubmissionStates::SubmissionFailed(txid, error)
+                                    }
+                                }
+                            })
                         },
                     ),
                     StateTransition::new(
                         Self::trigger_created_accepted(txid, global_context.clone()),
-                        move |_, (), _| Box::pin(async move { TxSubmissionStates::Accepted(txid) }),
+                        move |_, (), _| {
+                            accepted_context.remove(txid);
+                            Box::pin(async move { TxSubmissionStates::Accepted(txid) })
+                        },
                     ),
                 ]
             }
             TxSubmissionStates::Accepted(..)
             | TxSubmissionStates::Rejected(..)
-            | TxSubmissionStates::NonRetryableError(..) => {
+            | TxSubmissionStates::NonRetryableError(..)
+            | TxSubmissionStates::SubmissionFailed(..) => {
                 vec![]
             }
         }
@@ -101,21 +198,44 @@ impl State for TxSubmissionStates {
 }
 
 impl TxSubmissionStates {
-    async fn trigger_created_rejected(tx: Transaction, context: DynGlobalClientContext) -> String {
+    async fn trigger_created_rejected(
+        tx: Transaction,
+        context: TxSubmissionContext,
+        global_context: DynGlobalClientContext,
+    ) -> SubmissionError {
+        let txid = tx.tx_hash();
+        let mut transient_failures = 0;
         loop {
-            match context.api().submit_transaction(tx.clone()).await {
-                Ok(serde_outcome) => match serde_outcome.try_into_inner(context.decoders()) {
-                    Ok(outcome) => {
-                        if let TransactionSubmissionOutcome(Err(transaction_error)) = outcome {
-                            return transaction_error.to_string();
+            let transient_error = match global_context.api().submit_transaction(tx.clone()).await {
+                Ok(serde_outcome) => {
+                    match serde_outcome.try_into_inner(global_context.decoders()) {
+                        Ok(outcome) => {
+                            if let TransactionSubmissionOutcome(Err(transaction_error)) = outcome {
+                                return SubmissionError::Rejected(transaction_error.to_string());
+                            }
+                            None
+                        }
+                        Err(decode_error) => {
+                            warn!(target: LOG_CLIENT_NET_API, error = %decode_error, "Failed to decode SerdeModuleEncoding");
+                            Some(decode_error.to_string())
                         }
                     }
-                    Err(decode_error) => {
-                        warn!(target: LOG_CLIENT_NET_API, error = %decode_error, "Failed to decode SerdeModuleEncoding");
-                    }
-                },
+                }
                 Err(error) => {
                     error.report_if_important();
+                    Some(error.to_string())
+                }
+            };
+            context.record_attempt(txid, transient_error.clone());
+
+            if let Some(error) = transient_error {
+                transient_failures += 1;
+                if context
+                    .retry_limit
+                    .is_some_and(|retry_limit| retry_limit < transient_failures)
+                {
+                    warn!(target: LOG_TARGET, %txid, %error, "Giving up on transaction submission");
+                    return SubmissionError::RetriesExhausted(error);
                 }
             }
 
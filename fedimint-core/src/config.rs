@@ -34,7 +34,7 @@ use crate::module::{
     CoreConsensusVersion, DynCommonModuleInit, DynServerModuleInit, IDynCommonModuleInit,
     ModuleConsensusVersion,
 };
-use crate::{bls12_381_serde, maybe_add_send_sync, PeerId};
+use crate::{bls12_381_serde, maybe_add_send_sync, NumPeersExt, PeerId};
 
 // TODO: make configurable
 /// This limits the RAM consumption of a AlephBFT Unit to roughly 50kB
@@ -200,6 +200,17 @@ impl ClientConfig {
         self.global.calculate_federation_id()
     }
 
+    /// Summarizes the trust assumptions implied by the number of guardians
+    pub fn trust_summary(&self) -> TrustSummary {
+        let num_peers = self.global.api_endpoints.to_num_peers();
+        TrustSummary {
+            guardians: num_peers.total(),
+            threshold: num_peers.threshold(),
+            max_dishonest: num_peers.max_evil(),
+            max_offline: num_peers.max_evil(),
+        }
+    }
+
     /// Get the value of a given meta field
     pub fn meta<V: serde::de::DeserializeOwned + 'static>(
         &self,
@@ -227,6 +238,33 @@ impl ClientConfig {
         }
     }
 
+    /// Federation name from config metadata (if set). Unlike
+    /// [`GlobalClientConfig::federation_name`] a JSON encoded name is decoded.
+    pub fn federation_name(&self) -> Option<String> {
+        self.meta_string(META_FEDERATION_NAME_KEY)
+    }
+
+    /// Message to show users when joining the federation (if set)
+    pub fn welcome_message(&self) -> Option<String> {
+        self.meta_string(META_WELCOME_MESSAGE_KEY)
+    }
+
+    /// URL of the federation's icon, `None` if it isn't set or not a valid
+    /// URL
+    pub fn federation_icon_url(&self) -> Option<SafeUrl> {
+        let url = self.meta_string(META_FEDERATION_ICON_URL_KEY)?;
+        SafeUrl::parse(&url)
+            .map_err(|err| {
+                warn!(target: LOG_CORE, %err, %url, "Invalid federation icon url in config meta");
+            })
+            .ok()
+    }
+
+    fn meta_string(&self, key: &str) -> Option<String> {
+        // Values that aren't valid JSON are returned as they are, so this can't fail
+        self.meta::<String>(key).ok().flatten()
+    }
+
     /// Create an invite code with the api endpoint of the given peer which can
     /// be used to download this client config
     pub fn invite_code(&self, peer: &PeerId, api_secret: &Option<String>) -> Option<InviteCode> {
@@ -342,6 +380,27 @@ impl FederationId {
         Self(sha256::Hash::from_byte_array(bytes))
     }
 
+    /// Computes the federation id of a client config given as JSON, without
+    /// decoding its module configs
+    ///
+    /// Accepts both the [`JsonClientConfig`] format and the serde format of
+    /// [`ClientConfig`], which flattens the global config.
+    pub fn from_client_config_json(config_json: &str) -> anyhow::Result<Self> {
+        let config: serde_json::Value =
+            serde_json::from_str(config_json).context("Client config is not valid JSON")?;
+        let serde_json::Value::Object(mut config) = config else {
+            bail!("Client config has to be a JSON object");
+        };
+        let global = match config.remove("global") {
+            Some(global) => global,
+            None => serde_json::Value::Object(config),
+        };
+        let global: GlobalClientConfig =
+            serde_json::from_value(global).context("Client config has no valid global config")?;
+
+        Ok(global.calculate_federation_id())
+    }
+
     pub fn to_prefix(&self) -> FederationIdPrefix {
         FederationIdPrefix(self.0[..4].try_into().expect("can't fail"))
     }
@@ -372,6 +431,30 @@ impl FromStr for FederationId {
     }
 }
 
+/// The trust model of a federation, see [`ClientConfig::trust_summary`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrustSummary {
+    /// Number of guardians in the federation
+    pub guardians: usize,
+    /// Number of guardians that have to agree to make progress
+    pub threshold: usize,
+    /// Number of guardians that can be malicious without putting funds at risk
+    pub max_dishonest: usize,
+    /// Number of guardians that can be offline without the federation halting
+    pub max_offline: usize,
+}
+
+impl Display for TrustSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Funds are safe as long as at least {} of {} guardians are honest, and the \
+             federation keeps working with up to {} guardians offline",
+            self.threshold, self.guardians, self.max_offline
+        )
+    }
+}
+
 impl ClientConfig {
     /// Returns the consensus hash for a given client config
     pub fn consensus_hash(&self) -> sha256::Hash {
@@ -983,6 +1066,14 @@ impl SGroup for G1Projective {
 /// of the config
 pub const META_FEDERATION_NAME_KEY: &str = "federation_name";
 
+/// Key under which a welcome message can be sent to client in the `meta` part
+/// of the config
+pub const META_WELCOME_MESSAGE_KEY: &str = "welcome_message";
+
+/// Key under which the URL of the federation's icon can be sent to client in
+/// the `meta` part of the config
+pub const META_FEDERATION_ICON_URL_KEY: &str = "federation_icon_url";
+
 /// Key under which the vetted gateways can be sent to client in the `meta` part
 /// of the config
 pub const META_VETTED_GATEWAYS_KEY: &str = "vetted_gateways";
@@ -1028,7 +1119,92 @@ pub mod serde_binary_human_readable {
 mod tests {
     use fedimint_core::config::{ClientConfig, GlobalClientConfig};
 
+    use super::{
+        FederationId, PeerUrl, TrustSummary, META_FEDERATION_ICON_URL_KEY,
+        META_FEDERATION_NAME_KEY, META_WELCOME_MESSAGE_KEY,
+    };
     use crate::module::CoreConsensusVersion;
+    use crate::util::SafeUrl;
+    use crate::PeerId;
+
+    #[test]
+    fn test_federation_id_from_config_json() {
+        let config = ClientConfig {
+            global: GlobalClientConfig {
+                api_endpoints: [(
+                    PeerId::from(0),
+                    PeerUrl {
+                        url: SafeUrl::parse("wss://guardian.example.com").unwrap(),
+                        name: "guardian".to_owned(),
+                    },
+                )]
+                .into(),
+                consensus_version: CoreConsensusVersion { major: 0, minor: 0 },
+                meta: Default::default(),
+            },
+            modules: Default::default(),
+        };
+        let federation_id = config.calculate_federation_id();
+
+        let json_config = serde_json::to_string(&config.to_json()).unwrap();
+        assert_eq!(
+            FederationId::from_client_config_json(&json_config).unwrap(),
+            federation_id
+        );
+        let serde_config = serde_json::to_string(&config).unwrap();
+        assert_eq!(
+            FederationId::from_client_config_json(&serde_config).unwrap(),
+            federation_id
+        );
+
+        assert!(FederationId::from_client_config_json("not json").is_err());
+        assert!(FederationId::from_client_config_json("[]").is_err());
+        assert!(FederationId::from_client_config_json(r#"{"global": {}}"#).is_err());
+    }
+
+    #[test]
+    fn test_trust_summary() {
+        let config_with_guardians = |guardians: u16| ClientConfig {
+            global: GlobalClientConfig {
+                api_endpoints: (0..guardians)
+                    .map(|peer| {
+                        let url = PeerUrl {
+                            url: SafeUrl::parse(&format!("wss://guardian{peer}.example.com"))
+                                .unwrap(),
+                            name: format!("guardian{peer}"),
+                        };
+                        (PeerId::from(peer), url)
+                    })
+                    .collect(),
+                consensus_version: CoreConsensusVersion { major: 0, minor: 0 },
+                meta: Default::default(),
+            },
+            modules: Default::default(),
+        };
+
+        // 4 = 3f + 1 guardians with f = 1 tolerate one faulty guardian, and need
+        // 2f + 1 = 3 of them to agree
+        let summary = config_with_guardians(4).trust_summary();
+        assert_eq!(
+            summary,
+            TrustSummary {
+                guardians: 4,
+                threshold: 3,
+                max_dishonest: 1,
+                max_offline: 1,
+            }
+        );
+        assert_eq!(
+            summary.to_string(),
+            "Funds are safe as long as at least 3 of 4 guardians are honest, and the federation \
+             keeps working with up to 1 guardians offline"
+        );
+
+        let summary = config_with_guardians(7).trust_summary();
+        assert_eq!((summary.threshold, summary.max_dishonest), (5, 2));
+        let summary = config_with_guardians(1).trust_summary();
+        assert_eq!((summary.threshold, summary.max_offline), (1, 0));
+    }
 
     #[test]
     fn test_dcode_meta() {
@@ -1073,4 +1249,49 @@ mod tests {
             Some("[\"1\", \"2\"]".to_string())
         );
     }
+
+    #[test]
+    fn test_typed_meta_getters() {
+        let config_with_meta = |meta: Vec<(&str, &str)>| ClientConfig {
+            global: GlobalClientConfig {
+                api_endpoints: Default::default(),
+                consensus_version: CoreConsensusVersion { major: 0, minor: 0 },
+                meta: meta
+                    .into_iter()
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .collect(),
+            },
+            modules: Default::default(),
+        };
+
+        let config = config_with_meta(vec![
+            (META_FEDERATION_NAME_KEY, "Test Federation"),
+            (META_WELCOME_MESSAGE_KEY, "\"Welcome!\""),
+            (META_FEDERATION_ICON_URL_KEY, "https://example.com/icon.png"),
+        ]);
+        assert_eq!(
+            config.federation_name(),
+            Some("Test Federation".to_string())
+        );
+        assert_eq!(config.welcome_message(), Some("Welcome!".to_string()));
+        assert_eq!(
+            config.federation_icon_url(),
+            Some(SafeUrl::parse("https://example.com/icon.png").unwrap())
+        );
+
+        let config = config_with_meta(vec![]);
+        assert_eq!(config.federation_name(), None);
+        assert_eq!(config.welcome_message(), None);
+        assert_eq!(config.federation_icon_url(), None);
+
+        let config = config_with_meta(vec![(META_FEDERATION_ICON_URL_KEY, "not a url")]);
+        assert_eq!(config.federation_icon_url(), None);
+
+        let config = config_with_meta(vec![(META_FEDERATION_NAME_KEY, "\"Test Federation\"")]);
+        assert_eq!(
+            config.federation_name(),
+            Some("Test Federation".to_string())
+        );
+        assert_eq!(config.global.federation_name(), Some("\"Test Federation\""));
+    }
 }
@@ -107,6 +107,14 @@ impl IRawDatabase for MemDatabase {
     fn checkpoint(&self, _backup_path: &Path) -> Result<()> {
         Ok(())
     }
+
+    async fn size_on_disk(&self) -> Result<u64> {
+        Ok(0)
+    }
+
+    async fn key_count(&self) -> Result<u64> {
+        Ok(self.data.read().await.len() as u64)
+    }
 }
 
 // In-memory database transaction should only be used for test code and never
@@ -248,6 +256,11 @@ mod tests {
         fedimint_core::db::verify_insert_elements(database()).await;
     }
 
+    #[test_log::test(tokio::test)]
+    async fn test_key_count() {
+        fedimint_core::db::verify_key_count(database()).await;
+    }
+
     #[test_log::test(tokio::test)]
     async fn test_dbtx_remove_nonexisting() {
         fedimint_core::db::verify_remove_nonexisting(database()).await;
@@ -171,6 +171,16 @@ pub trait IRawDatabase: Debug + MaybeSend + MaybeSync + 'static {
 
     // Checkpoint the database to a backup directory
     fn checkpoint(&self, backup_path: &Path) -> Result<()>;
+
+    /// Approximate number of bytes the database uses on disk
+    async fn size_on_disk(&self) -> Result<u64> {
+        bail!("Database does not support reporting its size on disk")
+    }
+
+    /// Approximate number of keys stored in the database
+    async fn key_count(&self) -> Result<u64> {
+        bail!("Database does not support counting its keys")
+    }
 }
 
 #[apply(async_trait_maybe_send!)]
@@ -187,6 +197,14 @@ where
     fn checkpoint(&self, backup_path: &Path) -> Result<()> {
         (**self).checkpoint(backup_path)
     }
+
+    async fn size_on_disk(&self) -> Result<u64> {
+        (**self).size_on_disk().await
+    }
+
+    async fn key_count(&self) -> Result<u64> {
+        (**self).key_count().await
+    }
 }
 
 /// An extension trait with convenience operations on [`IRawDatabase`]
@@ -226,6 +244,16 @@ pub trait IDatabase: Debug + MaybeSend + MaybeSync + 'static {
 
     /// Checkpoints the database to a backup directory
     fn checkpoint(&self, backup_path: &Path) -> Result<()>;
+
+    /// See [`IRawDatabase::size_on_disk`]
+    async fn size_on_disk(&self) -> Result<u64> {
+        bail!("Database does not support reporting its size on disk")
+    }
+
+    /// See [`IRawDatabase::key_count`]
+    async fn key_count(&self) -> Result<u64> {
+        bail!("Database does not support counting its keys")
+    }
 }
 
 #[apply(async_trait_maybe_send!)]
@@ -250,6 +278,14 @@ where
     fn checkpoint(&self, backup_path: &Path) -> Result<()> {
         (**self).checkpoint(backup_path)
     }
+
+    async fn size_on_disk(&self) -> Result<u64> {
+        (**self).size_on_disk().await
+    }
+
+    async fn key_count(&self) -> Result<u64> {
+        (**self).key_count().await
+    }
 }
 
 /// Base functionality around [`IRawDatabase`] to make it a [`IDatabase`]
@@ -288,6 +324,14 @@ impl<RawDatabase: IRawDatabase + MaybeSend + 'static> IDatabase for BaseDatabase
     fn checkpoint(&self, backup_path: &Path) -> Result<()> {
         self.raw.checkpoint(backup_path)
     }
+
+    async fn size_on_disk(&self) -> Result<u64> {
+        self.raw.size_on_disk().await
+    }
+
+    async fn key_count(&self) -> Result<u64> {
+        self.raw.key_count().await
+    }
 }
 
 /// A public-facing newtype over `IDatabase`
@@ -409,6 +453,18 @@ impl Database {
         self.inner.checkpoint(backup_path)
     }
 
+    /// Approximate number of bytes the whole underlying database uses on
+    /// disk, also if `self` is isolated to a prefix
+    pub async fn size_on_disk(&self) -> Result<u64> {
+        self.inner.size_on_disk().await
+    }
+
+    /// Approximate number of keys in the whole underlying database, also if
+    /// `self` is isolated to a prefix
+    pub async fn key_count(&self) -> Result<u64> {
+        self.inner.key_count().await
+    }
+
     /// Runs a closure with a reference to a database transaction and tries to
     /// commit the transaction if the closure returns `Ok` and rolls it back
     /// otherwise. If committing fails the closure is run for up to
@@ -617,6 +673,15 @@ where
     fn checkpoint(&self, backup_path: &Path) -> Result<()> {
         self.inner.checkpoint(backup_path)
     }
+
+    // Stats are only tracked for the whole database, not per prefix
+    async fn size_on_disk(&self) -> Result<u64> {
+        self.inner.size_on_disk().await
+    }
+
+    async fn key_count(&self) -> Result<u64> {
+        self.inner.key_count().await
+    }
 }
 
 /// A database transactions that wraps an `inner` one and adds a prefix to all
@@ -1656,11 +1721,8 @@ where
             return Err(DecodingError::wrong_prefix(Self::DB_PREFIX, data[0]));
         }
 
-        <Self as crate::encoding::Decodable>::consensus_decode(
-            &mut std::io::Cursor::new(&data[1..]),
-            modules,
-        )
-        .map_err(|decode_error| DecodingError::Other(decode_error.0))
+        <Self as crate::encoding::Decodable>::consensus_decode_slice(&data[1..], modules)
+            .map_err(|decode_error| DecodingError::Other(decode_error.into()))
     }
 }
 
@@ -1669,8 +1731,7 @@ where
     T: Debug + Encodable + Decodable,
 {
     fn from_bytes(data: &[u8], modules: &ModuleDecoderRegistry) -> Result<Self, DecodingError> {
-        T::consensus_decode(&mut std::io::Cursor::new(data), modules)
-            .map_err(|e| DecodingError::Other(e.0))
+        T::consensus_decode_slice(data, modules).map_err(|e| DecodingError::Other(e.into()))
     }
 
     fn to_bytes(&self) -> Vec<u8> {
@@ -2212,6 +2273,23 @@ mod test_utils {
         dbtx.commit_tx().await;
     }
 
+    pub async fn verify_key_count(db: Database) {
+        let initial_count = db.key_count().await.expect("key count available");
+
+        let mut dbtx = db.begin_transaction().await;
+        for i in 0..10 {
+            dbtx.insert_entry(&TestKey(i), &TestVal(i)).await;
+        }
+        dbtx.commit_tx().await;
+        assert_eq!(db.key_count().await.unwrap(), initial_count + 10);
+
+        // Overwriting keys doesn't change the count
+        let mut dbtx = db.begin_transaction().await;
+        dbtx.insert_entry(&TestKey(0), &TestVal(42)).await;
+        dbtx.commit_tx().await;
+        assert_eq!(db.key_count().await.unwrap(), initial_count + 10);
+    }
+
     pub async fn verify_remove_nonexisting(db: Database) {
         let mut dbtx = db.begin_transaction().await;
         assert_eq!(dbtx.get_value(&TestKey(1)).await, None);
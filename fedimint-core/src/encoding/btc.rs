@@ -262,6 +262,45 @@ mod tests {
         assert_eq!(hash, hash_decoded);
     }
 
+    #[test_log::test]
+    fn amount_roundtrip() {
+        for sats in [0, 1, 252, 253, 65_535, 21_000_000 * 100_000_000, u64::MAX] {
+            let amount = bitcoin::Amount::from_sat(sats);
+            let encoded = amount.consensus_encode_to_vec();
+            // Sat values go through BigSize, so small amounts stay small on the wire
+            assert_eq!(encoded, sats.consensus_encode_to_vec());
+            let decoded =
+                bitcoin::Amount::consensus_decode_vec(encoded, &ModuleDecoderRegistry::default())
+                    .unwrap();
+            assert_eq!(amount, decoded);
+        }
+    }
+
+    #[test_log::test]
+    fn txid_roundtrip() {
+        let txid = bitcoin::Txid::hash(b"Hello world!");
+        let encoded = txid.consensus_encode_to_vec();
+        assert_eq!(encoded.len(), 32);
+        let decoded =
+            bitcoin::Txid::consensus_decode_vec(encoded.clone(), &ModuleDecoderRegistry::default())
+                .unwrap();
+        assert_eq!(txid, decoded);
+
+        let hex_decoded = bitcoin::Txid::consensus_decode_hex(
+            &txid.consensus_encode_to_hex(),
+            &ModuleDecoderRegistry::default(),
+        )
+        .unwrap();
+        assert_eq!(txid, hex_decoded);
+
+        // Truncated input is rejected instead of being zero-padded
+        assert!(bitcoin::Txid::consensus_decode_vec(
+            encoded[..31].to_vec(),
+            &ModuleDecoderRegistry::default()
+        )
+        .is_err());
+    }
+
     #[test_log::test]
     fn address_roundtrip() {
         let addresses = [
@@ -195,16 +195,26 @@ pub trait Decodable: Sized {
         let bytes = Vec::<u8>::from_hex(hex)
             .map_err(anyhow::Error::from)
             .map_err(DecodeError::new_custom)?;
-        let mut reader = std::io::Cursor::new(bytes);
-        Decodable::consensus_decode(&mut reader, modules)
+        Self::consensus_decode_slice(&bytes, modules)
     }
 
     fn consensus_decode_vec(
         bytes: Vec<u8>,
         modules: &ModuleDecoderRegistry,
+    ) -> Result<Self, DecodeError> {
+        Self::consensus_decode_slice(&bytes, modules)
+    }
+
+    /// Decode an object from `bytes`, on failure reporting the approximate
+    /// offset into `bytes` at which decoding failed, see
+    /// [`DecodeError::offset`]
+    fn consensus_decode_slice(
+        bytes: &[u8],
+        modules: &ModuleDecoderRegistry,
     ) -> Result<Self, DecodeError> {
         let mut reader = std::io::Cursor::new(bytes);
         Decodable::consensus_decode(&mut reader, modules)
+            .map_err(|e| e.at_offset(reader.position()))
     }
 }
 
@@ -226,17 +236,37 @@ impl Decodable for SafeUrl {
 }
 
 #[derive(Debug, Error)]
-pub struct DecodeError(pub(crate) anyhow::Error);
+pub struct DecodeError {
+    error: anyhow::Error,
+    offset: Option<u64>,
+}
 
 impl DecodeError {
     pub fn new_custom(e: anyhow::Error) -> Self {
-        Self(e)
+        Self {
+            error: e,
+            offset: None,
+        }
+    }
+
+    /// Approximate byte offset into the input at which decoding failed, i.e.
+    /// how far the input was read. Only known if the whole input was given
+    /// upfront, e.g. to [`Decodable::consensus_decode_slice`].
+    pub fn offset(&self) -> Option<u64> {
+        self.offset
+    }
+
+    fn at_offset(self, offset: u64) -> Self {
+        Self {
+            offset: self.offset.or(Some(offset)),
+            ..self
+        }
     }
 }
 
 impl From<anyhow::Error> for DecodeError {
     fn from(e: anyhow::Error) -> Self {
-        DecodeError(e)
+        DecodeError::new_custom(e)
     }
 }
 
@@ -330,6 +360,74 @@ macro_rules! impl_encode_decode_tuple {
     );
 }
 
+/// Decode a value from `bytes`, failing if any bytes are left over afterwards
+///
+/// [`Decodable::consensus_decode_slice`] silently ignores trailing bytes,
+/// which hides corruption of e.g. persisted values that happen to start with a
+/// valid encoding.
+pub fn decode_exact<T: Decodable>(
+    bytes: &[u8],
+    modules: &ModuleDecoderRegistry,
+) -> Result<T, DecodeError> {
+    let mut reader = std::io::Cursor::new(bytes);
+    let value =
+        T::consensus_decode(&mut reader, modules).map_err(|e| e.at_offset(reader.position()))?;
+
+    let consumed = reader.position();
+    if consumed != bytes.len() as u64 {
+        return Err(DecodeError::new_custom(format_err!(
+            "{} trailing bytes after decoded value",
+            bytes.len() as u64 - consumed
+        ))
+        .at_offset(consumed));
+    }
+
+    Ok(value)
+}
+
+/// Encodes `value` prefixed with a `type_tag` and `schema_version` header
+///
+/// Plain encodings carry no type information, so a persisted value whose
+/// schema changed can be decoded as garbage without any error. Values encoded
+/// with this function have to be decoded with [`decode_with_header`], which
+/// checks the header before decoding the value.
+pub fn encode_with_header<T: Encodable>(value: &T, type_tag: u16, schema_version: u16) -> Vec<u8> {
+    let mut bytes = (type_tag, schema_version).consensus_encode_to_vec();
+    value
+        .consensus_encode(&mut bytes)
+        .expect("encoding to bytes can't fail for io reasons");
+    bytes
+}
+
+/// Decodes a value encoded with [`encode_with_header`], failing if the type
+/// tag or schema version don't match the expected ones or if there are
+/// trailing bytes
+pub fn decode_with_header<T: Decodable>(
+    bytes: &[u8],
+    type_tag: u16,
+    schema_version: u16,
+    modules: &ModuleDecoderRegistry,
+) -> Result<T, DecodeError> {
+    let mut reader = std::io::Cursor::new(bytes);
+    let (found_tag, found_version) = <(u16, u16)>::consensus_decode(&mut reader, modules)?;
+    if found_tag != type_tag {
+        return Err(DecodeError::new_custom(format_err!(
+            "Expected type tag {type_tag}, found {found_tag}"
+        )));
+    }
+    if found_version != schema_version {
+        return Err(DecodeError::new_custom(format_err!(
+            "Expected schema version {schema_version} of type {type_tag}, found {found_version}"
+        )));
+    }
+
+    let header_len = reader.position() as usize;
+    decode_exact(&bytes[header_len..], modules).map_err(|e| {
+        let offset = e.offset.map(|offset| offset + header_len as u64);
+        DecodeError { offset, ..e }
+    })
+}
+
 /// Specialized version of Encodable for bytes
 pub fn consensus_encode_bytes<W: std::io::Write>(
     bytes: &[u8],
@@ -343,6 +441,11 @@ pub fn consensus_encode_bytes<W: std::io::Write>(
 }
 
 /// Specialized version of Encodable for static byte arrays
+///
+/// Unlike [`consensus_encode_bytes`] no length prefix is written, so changing a
+/// field between `Vec<u8>` and `[u8; N]` changes its wire format. Use
+/// [`consensus_encode_bytes_static_length_prefixed`] and
+/// [`consensus_decode_bytes_static_length_prefixed`] to keep the old format.
 pub fn consensus_encode_bytes_static<const N: usize, W: std::io::Write>(
     bytes: &[u8; N],
     writer: &mut W,
@@ -419,6 +522,82 @@ pub fn consensus_decode_bytes_static_from_finite_reader<const N: usize, D: std::
     Ok(bytes)
 }
 
+/// Encodes a fixed-size byte array in the length-prefixed wire format of
+/// `Vec<u8>` and `&[u8]`, for fields that changed from `Vec<u8>` to `[u8; N]`
+/// but have to stay compatible with the old encoding
+pub fn consensus_encode_bytes_static_length_prefixed<const N: usize, W: std::io::Write>(
+    bytes: &[u8; N],
+    writer: &mut W,
+) -> Result<usize, Error> {
+    consensus_encode_bytes(bytes, writer)
+}
+
+/// Decodes a fixed-size byte array from the length-prefixed wire format of
+/// `Vec<u8>` and `&[u8]`, failing if the encoded length is not `N`
+pub fn consensus_decode_bytes_static_length_prefixed<const N: usize, D: std::io::Read>(
+    r: &mut D,
+) -> Result<[u8; N], DecodeError> {
+    let bytes = consensus_decode_bytes(r)?;
+    let len = bytes.len();
+    bytes.try_into().map_err(|_| {
+        DecodeError::new_custom(format_err!("Expected {N} length-prefixed bytes, got {len}"))
+    })
+}
+
+/// Encodes `items` as a stream that can be decoded one item at a time with
+/// [`decode_length_delimited_stream`]: a [`BigSize`] item count followed by
+/// the items
+pub fn encode_length_delimited_stream<'a, T, I, W>(items: I, writer: &mut W) -> Result<usize, Error>
+where
+    T: Encodable + 'a,
+    I: IntoIterator<Item = &'a T>,
+    I::IntoIter: ExactSizeIterator,
+    W: std::io::Write,
+{
+    let items = items.into_iter();
+    let mut len = BigSize(items.len() as u64).consensus_encode(writer)?;
+    for item in items {
+        len += item.consensus_encode(writer)?;
+    }
+    Ok(len)
+}
+
+/// Lazily decodes a stream written by [`encode_length_delimited_stream`]
+///
+/// Every item is decoded from a reader limited to [`MAX_DECODE_SIZE`], so the
+/// stream as a whole can be arbitrarily long. The iterator ends after the
+/// first error.
+pub fn decode_length_delimited_stream<'a, T, R>(
+    mut reader: R,
+    modules: &'a ModuleDecoderRegistry,
+) -> impl Iterator<Item = Result<T, DecodeError>> + 'a
+where
+    T: Decodable + 'a,
+    R: std::io::Read + 'a,
+{
+    let mut remaining = None;
+    std::iter::from_fn(move || {
+        let count = match remaining {
+            Some(count) => count,
+            None => match BigSize::consensus_decode(&mut reader, modules) {
+                Ok(count) => count.0,
+                Err(e) => {
+                    remaining = Some(0);
+                    return Some(Err(e));
+                }
+            },
+        };
+        if count == 0 {
+            remaining = Some(0);
+            return None;
+        }
+
+        let item = T::consensus_decode(&mut reader, modules);
+        remaining = Some(if item.is_ok() { count - 1 } else { 0 });
+        Some(item)
+    })
+}
+
 impl_encode_decode_tuple!(T1, T2);
 impl_encode_decode_tuple!(T1, T2, T3);
 impl_encode_decode_tuple!(T1, T2, T3, T4);
@@ -460,13 +639,22 @@ where
         d: &mut D,
         modules: &ModuleDecoderRegistry,
     ) -> Result<Self, DecodeError> {
+        let _depth_guard = modules.enter_nested()?;
+        let len = u64::consensus_decode_from_finite_reader(d, modules)?;
+        modules.consume_alloc_budget(len.saturating_mul(mem::size_of::<T>() as u64))?;
+
         if TypeId::of::<T>() == TypeId::of::<u8>() {
+            let len: usize =
+                usize::try_from(len).map_err(|_| DecodeError::from_str("size exceeds memory"))?;
+            let opts = ReadBytesFromFiniteReaderOpts {
+                len,
+                chunk_size: 64 * 1024,
+            };
+            let bytes = read_bytes_from_finite_reader(d, opts).map_err(DecodeError::from_err)?;
+
             // unsafe: we've just checked that T is `u8` so the transmute here is a no-op
-            return Ok(unsafe {
-                mem::transmute::<Vec<u8>, Vec<T>>(consensus_decode_bytes_from_finite_reader(d)?)
-            });
+            return Ok(unsafe { mem::transmute::<Vec<u8>, Vec<T>>(bytes) });
         }
-        let len = u64::consensus_decode_from_finite_reader(d, modules)?;
 
         // `collect` under the hood uses `FromIter::from_iter`, which can potentially be
         // backed by code like:
@@ -506,6 +694,91 @@ fn vec_decode_sanity() {
     assert!(Vec::<u16>::consensus_decode(&mut buf.as_slice(), &Default::default()).is_err());
 }
 
+/// A `Vec<T>` that refuses to decode more than `MAX` elements
+///
+/// Encodes exactly like `Vec<T>`, so it can replace one in an existing type
+/// without changing its encoding. When decoding, the declared length is
+/// checked against `MAX` before anything is allocated, which makes it
+/// suitable for fields whose size is bounded by the protocol and which are
+/// decoded from untrusted peers.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "Vec<T>", into = "Vec<T>")]
+#[serde(bound(
+    serialize = "T: Serialize + Clone",
+    deserialize = "T: Deserialize<'de>"
+))]
+pub struct BoundedVec<T, const MAX: usize>(Vec<T>);
+
+impl<T, const MAX: usize> BoundedVec<T, MAX> {
+    /// Maximum number of elements this vector may hold
+    pub const MAX_LEN: usize = MAX;
+
+    pub fn into_inner(self) -> Vec<T> {
+        self.0
+    }
+}
+
+impl<T, const MAX: usize> TryFrom<Vec<T>> for BoundedVec<T, MAX> {
+    type Error = anyhow::Error;
+
+    fn try_from(v: Vec<T>) -> Result<Self, Self::Error> {
+        if MAX < v.len() {
+            anyhow::bail!("Vec of length {} exceeds the bound of {MAX}", v.len());
+        }
+        Ok(Self(v))
+    }
+}
+
+impl<T, const MAX: usize> From<BoundedVec<T, MAX>> for Vec<T> {
+    fn from(v: BoundedVec<T, MAX>) -> Self {
+        v.0
+    }
+}
+
+impl<T, const MAX: usize> std::ops::Deref for BoundedVec<T, MAX> {
+    type Target = [T];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T, const MAX: usize> Encodable for BoundedVec<T, MAX>
+where
+    T: Encodable + 'static,
+{
+    fn consensus_encode<W: std::io::Write>(&self, writer: &mut W) -> Result<usize, Error> {
+        self.0.consensus_encode(writer)
+    }
+}
+
+impl<T, const MAX: usize> Decodable for BoundedVec<T, MAX>
+where
+    T: Decodable + 'static,
+{
+    fn consensus_decode_from_finite_reader<D: std::io::Read>(
+        d: &mut D,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Self, DecodeError> {
+        let _depth_guard = modules.enter_nested()?;
+        let len = u64::consensus_decode_from_finite_reader(d, modules)?;
+        if (MAX as u64) < len {
+            return Err(DecodeError::new_custom(format_err!(
+                "Vec of length {len} exceeds the bound of {MAX}"
+            )));
+        }
+        modules.consume_alloc_budget(len.saturating_mul(mem::size_of::<T>() as u64))?;
+
+        // `len` is bounded by `MAX` at this point, so preallocating is fine
+        let mut v = Vec::with_capacity(len as usize);
+        for _ in 0..len {
+            v.push(T::consensus_decode_from_finite_reader(d, modules)?);
+        }
+
+        Ok(Self(v))
+    }
+}
+
 impl<T, const SIZE: usize> Encodable for [T; SIZE]
 where
     T: Encodable + 'static,
@@ -537,7 +810,7 @@ unsafe fn horribe_array_transmute_workaround<const N: usize, A, B>(mut arr: [A;
 
 impl<T, const SIZE: usize> Decodable for [T; SIZE]
 where
-    T: Decodable + Debug + Default + Copy + 'static,
+    T: Decodable + Debug + 'static,
 {
     fn consensus_decode_from_finite_reader<D: std::io::Read>(
         d: &mut D,
@@ -550,15 +823,34 @@ where
                 horribe_array_transmute_workaround::<SIZE, u8, T>(arr)
             });
         }
-        // todo: impl without copy
-        let mut data = [T::default(); SIZE];
-        for item in &mut data {
-            *item = T::consensus_decode_from_finite_reader(d, modules)?;
+        // Decode incrementally instead of allocating the whole array upfront, so a
+        // large `SIZE` with a truncated reader fails before allocating the full
+        // array. Like for `Vec`, only a bounded capacity is reserved in advance.
+        let mut data = Vec::with_capacity(cmp::min(8_000 / mem::size_of::<T>().max(1), SIZE));
+        for _ in 0..SIZE {
+            data.push(T::consensus_decode_from_finite_reader(d, modules)?);
         }
-        Ok(data)
+        Ok(data.try_into().expect("We decoded exactly SIZE items"))
     }
 }
 
+#[test]
+fn array_decode_truncated_reader() {
+    // Every zero byte decodes as one item, so the reader holds 80 of 1024 items
+    let buf = [0u8; 80];
+
+    assert!(<[u64; 1024]>::consensus_decode(&mut buf.as_slice(), &Default::default()).is_err());
+
+    let mut encoded = [7u64; 1024].consensus_encode_to_vec();
+    assert_eq!(
+        <[u64; 1024]>::consensus_decode(&mut encoded.as_slice(), &Default::default()).unwrap(),
+        [7u64; 1024]
+    );
+
+    encoded.pop();
+    assert!(<[u64; 1024]>::consensus_decode(&mut encoded.as_slice(), &Default::default()).is_err());
+}
+
 impl<T> Encodable for Option<T>
 where
     T: Encodable,
@@ -583,6 +875,7 @@ where
         d: &mut D,
         modules: &ModuleDecoderRegistry,
     ) -> Result<Self, DecodeError> {
+        let _depth_guard = modules.enter_nested()?;
         let flag = u8::consensus_decode_from_finite_reader(d, modules)?;
         match flag {
             0 => Ok(None),
@@ -626,6 +919,7 @@ where
         d: &mut D,
         modules: &ModuleDecoderRegistry,
     ) -> Result<Self, DecodeError> {
+        let _depth_guard = modules.enter_nested()?;
         let flag = u8::consensus_decode_from_finite_reader(d, modules)?;
         match flag {
             0 => Ok(Err(E::consensus_decode_from_finite_reader(d, modules)?)),
@@ -654,6 +948,7 @@ where
         d: &mut D,
         modules: &ModuleDecoderRegistry,
     ) -> Result<Self, DecodeError> {
+        let _depth_guard = modules.enter_nested()?;
         Ok(Box::new(T::consensus_decode_from_finite_reader(
             d, modules,
         )?))
@@ -713,7 +1008,9 @@ impl Decodable for SystemTime {
         modules: &ModuleDecoderRegistry,
     ) -> Result<Self, DecodeError> {
         let duration = Duration::consensus_decode(d, modules)?;
-        Ok(UNIX_EPOCH + duration)
+        UNIX_EPOCH.checked_add(duration).ok_or_else(|| {
+            DecodeError::new_custom(format_err!("Timestamp out of range: {duration:?}"))
+        })
     }
 }
 
@@ -733,7 +1030,14 @@ impl Decodable for Duration {
         modules: &ModuleDecoderRegistry,
     ) -> Result<Self, DecodeError> {
         let secs = Decodable::consensus_decode(d, modules)?;
-        let nsecs = Decodable::consensus_decode(d, modules)?;
+        let nsecs: u32 = Decodable::consensus_decode(d, modules)?;
+        // `Duration::new` would carry excess nanoseconds into the seconds and panic
+        // if that overflows
+        if nsecs >= 1_000_000_000 {
+            return Err(DecodeError::new_custom(format_err!(
+                "Invalid subsecond nanos: {nsecs}"
+            )));
+        }
         Ok(Duration::new(secs, nsecs))
     }
 }
@@ -772,9 +1076,13 @@ impl Decodable for lightning_invoice::Bolt11Invoice {
         d: &mut D,
         modules: &ModuleDecoderRegistry,
     ) -> Result<Self, DecodeError> {
+        // Include the debug representation of the error, its display message alone
+        // often does not tell which part of the invoice failed to parse
         String::consensus_decode(d, modules)?
             .parse::<lightning_invoice::Bolt11Invoice>()
-            .map_err(DecodeError::from_err)
+            .map_err(|e| {
+                DecodeError::new_custom(format_err!("Failed to parse Bolt11 invoice: {e:?}"))
+            })
     }
 }
 
@@ -817,17 +1125,21 @@ impl DecodeError {
 
         impl std::error::Error for StrError {}
 
-        DecodeError(anyhow::Error::from(StrError(s)))
+        DecodeError::new_custom(anyhow::Error::from(StrError(s)))
     }
 
     pub fn from_err<E: std::error::Error + Send + Sync + 'static>(e: E) -> Self {
-        DecodeError(anyhow::Error::from(e))
+        DecodeError::new_custom(anyhow::Error::from(e))
     }
 }
 
 impl std::fmt::Display for DecodeError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        std::fmt::Display::fmt(&self.0, f)
+        std::fmt::Display::fmt(&self.error, f)?;
+        if let Some(offset) = self.offset {
+            write!(f, " (at byte offset {offset})")?;
+        }
+        Ok(())
     }
 }
 
@@ -847,6 +1159,10 @@ where
     }
 }
 
+/// Only accepts the canonical encoding with keys in ascending order, as
+/// produced by the `Encodable` impl, so every map has exactly one valid
+/// encoding. Use [`decode_map_lenient`] to import data from sources that don't
+/// guarantee the key order.
 impl<K, V> Decodable for BTreeMap<K, V>
 where
     K: Decodable + Ord,
@@ -856,6 +1172,7 @@ where
         d: &mut D,
         modules: &ModuleDecoderRegistry,
     ) -> Result<Self, DecodeError> {
+        let _depth_guard = modules.enter_nested()?;
         let mut res = BTreeMap::new();
         let len = u64::consensus_decode_from_finite_reader(d, modules)?;
         for _ in 0..len {
@@ -868,13 +1185,42 @@ where
             }
             let v = V::consensus_decode_from_finite_reader(d, modules)?;
             if res.insert(k, v).is_some() {
-                return Err(DecodeError(format_err!("Duplicate key")));
+                return Err(DecodeError::new_custom(format_err!("Duplicate key")));
             }
         }
         Ok(res)
     }
 }
 
+/// Decodes a `BTreeMap` like its `Decodable` impl, but accepting keys in any
+/// order, e.g. when importing maps produced by external tools.
+///
+/// Duplicate keys are still rejected. Never use this for consensus data:
+/// accepting multiple encodings of the same map means the encoding can no
+/// longer be relied on to be unique, e.g. for hashing.
+pub fn decode_map_lenient<K, V, D>(
+    d: &mut D,
+    modules: &ModuleDecoderRegistry,
+) -> Result<BTreeMap<K, V>, DecodeError>
+where
+    K: Decodable + Ord,
+    V: Decodable,
+    D: std::io::Read,
+{
+    let d = &mut d.take(MAX_DECODE_SIZE as u64);
+    let _depth_guard = modules.enter_nested()?;
+    let mut res = BTreeMap::new();
+    let len = u64::consensus_decode_from_finite_reader(d, modules)?;
+    for _ in 0..len {
+        let k = K::consensus_decode_from_finite_reader(d, modules)?;
+        let v = V::consensus_decode_from_finite_reader(d, modules)?;
+        if res.insert(k, v).is_some() {
+            return Err(DecodeError::new_custom(format_err!("Duplicate key")));
+        }
+    }
+    Ok(res)
+}
+
 impl<K> Encodable for BTreeSet<K>
 where
     K: Encodable,
@@ -897,6 +1243,7 @@ where
         d: &mut D,
         modules: &ModuleDecoderRegistry,
     ) -> Result<Self, DecodeError> {
+        let _depth_guard = modules.enter_nested()?;
         let mut res = BTreeSet::new();
         let len = u64::consensus_decode_from_finite_reader(d, modules)?;
         for _ in 0..len {
@@ -905,7 +1252,7 @@ where
                 return Err(DecodeError::from_str("Non-canonical encoding"));
             }
             if !res.insert(k) {
-                return Err(DecodeError(format_err!("Duplicate key")));
+                return Err(DecodeError::new_custom(format_err!("Duplicate key")));
             }
         }
         Ok(res)
@@ -1153,6 +1500,144 @@ mod tests {
         assert_eq!(cursor.position(), len as u64);
     }
 
+    #[test]
+    fn test_bounded_vec() {
+        test_roundtrip(&BoundedVec::<u16, 4>::try_from(vec![1, 2, 3, 4]).unwrap());
+        assert!(BoundedVec::<u16, 4>::try_from(vec![1, 2, 3, 4, 5]).is_err());
+
+        // Encodes exactly like a `Vec`
+        let mut bounded = Vec::new();
+        BoundedVec::<u8, 8>::try_from(vec![1u8, 2, 3])
+            .unwrap()
+            .consensus_encode(&mut bounded)
+            .unwrap();
+        assert_eq!(bounded, vec![1u8, 2, 3].consensus_encode_to_vec());
+
+        // A huge declared length with no elements following it must be rejected
+        // based on the length alone, without allocating or reading any elements
+        let bytes = u64::MAX.consensus_encode_to_vec();
+        let err = BoundedVec::<u64, 4>::consensus_decode(
+            &mut bytes.as_slice(),
+            &ModuleDecoderRegistry::default(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("exceeds the bound of 4"), "{err}");
+
+        let bytes = vec![1u16, 2, 3, 4, 5].consensus_encode_to_vec();
+        assert!(BoundedVec::<u16, 4>::consensus_decode(
+            &mut bytes.as_slice(),
+            &ModuleDecoderRegistry::default()
+        )
+        .is_err());
+    }
+
+    #[test_log::test]
+    fn test_alloc_budget_covers_nested_vecs() {
+        let value = vec![vec![0u8; 1000]; 4];
+        let bytes = value.consensus_encode_to_vec();
+        let modules = ModuleDecoderRegistry::default();
+        let outer_len = 4 * mem::size_of::<Vec<u8>>() as u64;
+
+        // Every single vec fits the budget, but all of them together don't
+        let budget = modules.with_alloc_budget(outer_len + 3000);
+        assert!(Vec::<Vec<u8>>::consensus_decode_vec(bytes.clone(), &budget).is_err());
+
+        let budget = modules.with_alloc_budget(outer_len + 4000);
+        assert_eq!(
+            Vec::<Vec<u8>>::consensus_decode_vec(bytes.clone(), &budget).unwrap(),
+            value
+        );
+
+        // The budget is used up by the first decoding
+        assert!(Vec::<Vec<u8>>::consensus_decode_vec(bytes.clone(), &budget).is_err());
+
+        assert_eq!(
+            Vec::<Vec<u8>>::consensus_decode_vec(bytes, &modules).unwrap(),
+            value
+        );
+    }
+
+    #[test_log::test]
+    fn test_max_depth_rejects_deeply_nested_values() {
+        #[derive(Debug)]
+        struct Nested(Option<Box<Nested>>);
+
+        impl Decodable for Nested {
+            fn consensus_decode_from_finite_reader<R: std::io::Read>(
+                r: &mut R,
+                modules: &ModuleDecoderRegistry,
+            ) -> Result<Self, DecodeError> {
+                Ok(Nested(Option::consensus_decode_from_finite_reader(
+                    r, modules,
+                )?))
+            }
+        }
+
+        // Every level of nesting is encoded as the `Some` flag and enters both
+        // an `Option` and a `Box`
+        fn nested_bytes(levels: usize) -> Vec<u8> {
+            let mut bytes = vec![1u8; levels];
+            bytes.push(0);
+            bytes
+        }
+
+        let modules = ModuleDecoderRegistry::default().with_max_depth(10);
+        assert!(Nested::consensus_decode_vec(nested_bytes(4), &modules).is_ok());
+        assert!(Nested::consensus_decode_vec(nested_bytes(5), &modules).is_err());
+        // A rejected value doesn't leave the depth counter behind
+        assert!(Nested::consensus_decode_vec(nested_bytes(4), &modules).is_ok());
+
+        // Would overflow the stack without the default limit
+        let modules = ModuleDecoderRegistry::default();
+        assert!(Nested::consensus_decode_vec(nested_bytes(1_000_000), &modules).is_err());
+    }
+
+    #[test_log::test]
+    fn test_length_delimited_stream() {
+        #[derive(Debug, Eq, PartialEq, Encodable, Decodable)]
+        struct Item {
+            id: u64,
+            name: String,
+        }
+
+        let items = (0..1000)
+            .map(|id| Item {
+                id,
+                name: format!("item {id}"),
+            })
+            .collect::<Vec<_>>();
+
+        let mut bytes = vec![];
+        let len = encode_length_delimited_stream(&items, &mut bytes).unwrap();
+        assert_eq!(len, bytes.len());
+
+        let modules = ModuleDecoderRegistry::default();
+        let mut stream = decode_length_delimited_stream::<Item, _>(Cursor::new(&bytes), &modules);
+        let first = stream
+            .by_ref()
+            .take(10)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(first, items[..10]);
+        let rest = stream.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(rest, items[10..]);
+
+        // Cutting the stream in the middle of the last item only fails that item
+        let truncated = &bytes[..bytes.len() - 1];
+        let decoded = decode_length_delimited_stream::<Item, _>(Cursor::new(truncated), &modules)
+            .collect::<Vec<_>>();
+        assert_eq!(decoded.len(), 1000);
+        assert!(decoded[..999].iter().all(Result::is_ok));
+        assert!(decoded[999].is_err());
+
+        assert!(
+            decode_length_delimited_stream::<Item, _>(Cursor::new(&[] as &[u8]), &modules)
+                .next()
+                .unwrap()
+                .is_err()
+        );
+    }
+
     #[derive(Debug, Eq, PartialEq, Encodable, Decodable)]
     enum NoDefaultEnum {
         Foo,
@@ -1296,6 +1781,86 @@ mod tests {
             .parse::<lightning_invoice::Bolt11Invoice>()
             .unwrap();
         test_roundtrip(&invoice);
+
+        // Changing the last character breaks the bech32 checksum
+        let mut corrupted = invoice_str[..invoice_str.len() - 1].to_owned();
+        corrupted.push('q');
+        let err = lightning_invoice::Bolt11Invoice::consensus_decode_vec(
+            corrupted.consensus_encode_to_vec(),
+            &ModuleDecoderRegistry::default(),
+        )
+        .expect_err("Corrupted invoice must not decode");
+        assert!(
+            err.to_string().contains("InvalidChecksum"),
+            "Unexpected error: {err}"
+        );
+    }
+
+    #[test_log::test]
+    fn test_byte_wire_formats() {
+        // Fixed-size byte arrays are encoded without a length prefix ...
+        assert_eq!([1u8, 2, 3].consensus_encode_to_vec(), vec![1, 2, 3]);
+        // ... while byte vectors and slices are prefixed with their length
+        assert_eq!(vec![1u8, 2, 3].consensus_encode_to_vec(), vec![3, 1, 2, 3]);
+        assert_eq!(
+            (&[1u8, 2, 3][..]).consensus_encode_to_vec(),
+            vec![3, 1, 2, 3]
+        );
+        // Other fixed-size arrays encode each item
+        assert_eq!([1u16, 256].consensus_encode_to_vec(), vec![1, 0xfd, 1, 0]);
+
+        // Which is why changing a field from `Vec<u8>` to `[u8; N]` silently
+        // misinterprets old data
+        assert_eq!(
+            <[u8; 4]>::consensus_decode_vec(
+                vec![1u8, 2, 3].consensus_encode_to_vec(),
+                &ModuleDecoderRegistry::default()
+            )
+            .unwrap(),
+            [3, 1, 2, 3]
+        );
+
+        // The length-prefixed helpers keep the `Vec<u8>` format
+        let mut encoded = vec![];
+        consensus_encode_bytes_static_length_prefixed(&[1u8, 2, 3], &mut encoded).unwrap();
+        assert_eq!(encoded, vec![1u8, 2, 3].consensus_encode_to_vec());
+        assert_eq!(
+            consensus_decode_bytes_static_length_prefixed::<3, _>(&mut encoded.as_slice()).unwrap(),
+            [1, 2, 3]
+        );
+        assert!(
+            consensus_decode_bytes_static_length_prefixed::<4, _>(&mut encoded.as_slice()).is_err()
+        );
+    }
+
+    #[test_log::test]
+    fn test_decode_error_offset() {
+        let encoded = (vec![7u8; 100], 42u64).consensus_encode_to_vec();
+
+        // Truncated in the middle of the byte vector
+        let err = <(Vec<u8>, u64)>::consensus_decode_slice(
+            &encoded[..50],
+            &ModuleDecoderRegistry::default(),
+        )
+        .expect_err("Input is truncated");
+        let offset = err.offset().expect("Offset is known");
+        assert!((40..=50).contains(&offset), "Unexpected offset {offset}");
+        assert!(err
+            .to_string()
+            .contains(&format!("at byte offset {offset}")));
+
+        // Invalid value after the byte vector
+        let mut encoded = vec![1u8; 10].consensus_encode_to_vec();
+        encoded.push(2);
+        let err =
+            <(Vec<u8>, bool)>::consensus_decode_vec(encoded, &ModuleDecoderRegistry::default())
+                .expect_err("2 is not a valid bool");
+        assert_eq!(err.offset(), Some(12));
+
+        // Without the whole input the offset is unknown
+        let err = bool::consensus_decode(&mut [2u8].as_slice(), &ModuleDecoderRegistry::default())
+            .expect_err("2 is not a valid bool");
+        assert_eq!(err.offset(), None);
     }
 
     #[test_log::test]
@@ -1316,6 +1881,24 @@ mod tests {
         test_roundtrip(&fedimint_core::time::now());
     }
 
+    #[test_log::test]
+    fn test_duration_rejects_invalid_nanos() {
+        test_roundtrip(&Duration::new(u64::MAX, 999_999_999));
+
+        let mut bytes = Vec::new();
+        u64::MAX.consensus_encode(&mut bytes).unwrap();
+        1_000_000_000u32.consensus_encode(&mut bytes).unwrap();
+        assert!(Duration::consensus_decode_vec(bytes, &ModuleDecoderRegistry::default()).is_err());
+    }
+
+    #[test_log::test]
+    fn test_systemtime_rejects_overflow() {
+        let bytes = Duration::new(u64::MAX, 0).consensus_encode_to_vec();
+        assert!(
+            SystemTime::consensus_decode_vec(bytes, &ModuleDecoderRegistry::default()).is_err()
+        );
+    }
+
     #[test]
     fn test_derive_empty_enum_decode() {
         #[derive(Debug, Encodable, Decodable)]
@@ -1330,6 +1913,97 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_decode_exact_rejects_trailing_bytes() {
+        #[derive(Debug, PartialEq, Eq, Encodable, Decodable)]
+        struct Record {
+            id: u64,
+            name: String,
+        }
+
+        let record = Record {
+            id: 42,
+            name: "fedimint".to_owned(),
+        };
+        let modules = ModuleDecoderRegistry::default();
+
+        let bytes = record.consensus_encode_to_vec();
+        assert_eq!(decode_exact::<Record>(&bytes, &modules).unwrap(), record);
+
+        let mut corrupt = bytes.clone();
+        corrupt.extend_from_slice(&[0xde, 0xad]);
+        assert_eq!(
+            Record::consensus_decode_slice(&corrupt, &modules).unwrap(),
+            record
+        );
+        let err = decode_exact::<Record>(&corrupt, &modules).unwrap_err();
+        assert_eq!(err.offset(), Some(bytes.len() as u64));
+
+        let tuple = (1u32, 2u64).consensus_encode_to_vec();
+        assert!(decode_exact::<(u32, u64)>(&tuple, &modules).is_ok());
+        assert!(decode_exact::<(u32, u32)>(&tuple, &modules).is_err());
+    }
+
+    #[test]
+    fn test_encoding_with_header() {
+        const TYPE_TAG: u16 = 7;
+        let modules = ModuleDecoderRegistry::default();
+
+        let value = (42u64, "backup".to_owned());
+        let bytes = encode_with_header(&value, TYPE_TAG, 1);
+        assert_eq!(
+            decode_with_header::<(u64, String)>(&bytes, TYPE_TAG, 1, &modules).unwrap(),
+            value
+        );
+
+        let err = decode_with_header::<(u64, String)>(&bytes, TYPE_TAG, 2, &modules).unwrap_err();
+        assert!(err.to_string().contains("schema version"), "{err}");
+        assert!(decode_with_header::<(u64, String)>(&bytes, TYPE_TAG + 1, 1, &modules).is_err());
+        assert!(decode_with_header::<(u64, String)>(&bytes[..2], TYPE_TAG, 1, &modules).is_err());
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(
+            decode_with_header::<(u64, String)>(&trailing, TYPE_TAG, 1, &modules)
+                .unwrap_err()
+                .offset(),
+            Some(bytes.len() as u64)
+        );
+    }
+
+    #[test]
+    fn test_lenient_map_decoding() {
+        let modules = ModuleDecoderRegistry::default();
+        let encode_entries = |entries: &[(u32, u8)]| {
+            let mut bytes = (entries.len() as u64).consensus_encode_to_vec();
+            for entry in entries {
+                entry.consensus_encode(&mut bytes).unwrap();
+            }
+            bytes
+        };
+
+        let sorted = encode_entries(&[(1, 10), (2, 20)]);
+        let unsorted = encode_entries(&[(2, 20), (1, 10)]);
+        let duplicate = encode_entries(&[(1, 10), (1, 20)]);
+        let expected = BTreeMap::from([(1u32, 10u8), (2, 20)]);
+
+        assert_eq!(
+            BTreeMap::<u32, u8>::consensus_decode_vec(sorted.clone(), &modules).unwrap(),
+            expected
+        );
+        assert!(BTreeMap::<u32, u8>::consensus_decode_vec(unsorted.clone(), &modules).is_err());
+
+        assert_eq!(
+            decode_map_lenient::<u32, u8, _>(&mut sorted.as_slice(), &modules).unwrap(),
+            expected
+        );
+        assert_eq!(
+            decode_map_lenient::<u32, u8, _>(&mut unsorted.as_slice(), &modules).unwrap(),
+            expected
+        );
+        assert!(decode_map_lenient::<u32, u8, _>(&mut duplicate.as_slice(), &modules).is_err());
+    }
+
     #[test]
     fn test_custom_index_enum() {
         #[derive(Debug, PartialEq, Eq, Encodable, Decodable)]
@@ -1475,4 +2149,17 @@ mod tests {
             ],
         );
     }
+
+    #[test]
+    fn test_safe_url_roundtrip_preserves_plain_urls() {
+        for url in [
+            "https://blockstream.info/api/",
+            "https://blockstream.info/api",
+            "http://127.0.0.1:50002/",
+        ] {
+            let safe_url = SafeUrl::parse(url).unwrap();
+            assert_eq!(safe_url.as_str(), url);
+            test_roundtrip_expected(&safe_url, &url.consensus_encode_to_vec());
+        }
+    }
 }
@@ -101,6 +101,8 @@ mod tests {
     use secp256k1_zkp::Message;
 
     use super::super::tests::test_roundtrip;
+    use crate::encoding::{Decodable, Encodable};
+    use crate::module::registry::ModuleDecoderRegistry;
 
     #[test_log::test]
     fn test_ecdsa_sig() {
@@ -129,4 +131,28 @@ mod tests {
 
         test_roundtrip(&sig);
     }
+
+    #[test_log::test]
+    fn test_schnorr_sig_malformed_length() {
+        let ctx = secp256k1_zkp::global::SECP256K1;
+        let key_pair = bitcoin::key::KeyPair::new(ctx, &mut rand::rngs::OsRng);
+        let sig = ctx.sign_schnorr(
+            &secp256k1_zkp::hashes::sha256::Hash::hash(b"Hello World!").into(),
+            &key_pair,
+        );
+
+        let bytes = sig.consensus_encode_to_vec();
+        assert_eq!(
+            bytes.len(),
+            secp256k1_zkp::constants::SCHNORR_SIGNATURE_SIZE
+        );
+
+        for truncated_len in [0, 32, bytes.len() - 1] {
+            assert!(secp256k1_zkp::schnorr::Signature::consensus_decode(
+                &mut &bytes[..truncated_len],
+                &ModuleDecoderRegistry::default(),
+            )
+            .is_err());
+        }
+    }
 }
@@ -56,6 +56,16 @@ impl Decodable for InviteCode {
 }
 
 impl InviteCode {
+    /// Start building an [`InviteCode`] for the federation with
+    /// `federation_id`
+    pub fn builder(federation_id: FederationId) -> InviteCodeBuilder {
+        InviteCodeBuilder {
+            federation_id,
+            peers: BTreeMap::new(),
+            api_secret: None,
+        }
+    }
+
     pub fn new(
         url: SafeUrl,
         peer: PeerId,
@@ -170,6 +180,74 @@ impl InviteCode {
             })
             .expect("Ensured by constructor")
     }
+
+    /// Returns a copy of this invite code that only contains the API endpoint
+    /// of `peer`, e.g. to share it with someone who should only contact that
+    /// guardian
+    pub fn with_single_guardian(&self, peer: PeerId) -> anyhow::Result<InviteCode> {
+        ensure!(
+            self.peers().contains_key(&peer),
+            "Guardian {peer} is not part of the invite code"
+        );
+
+        Ok(InviteCode(
+            self.0
+                .iter()
+                .filter(|part| {
+                    !matches!(part, InviteCodePart::Api { peer: api_peer, .. } if *api_peer != peer)
+                })
+                .cloned()
+                .collect(),
+        ))
+    }
+}
+
+/// Builder for an [`InviteCode`], see [`InviteCode::builder`]
+#[derive(Clone, Debug)]
+pub struct InviteCodeBuilder {
+    federation_id: FederationId,
+    peers: BTreeMap<PeerId, SafeUrl>,
+    api_secret: Option<String>,
+}
+
+impl InviteCodeBuilder {
+    /// Add the API endpoint of a guardian, replacing a previously added one
+    /// of the same guardian
+    pub fn with_peer(mut self, peer: PeerId, url: SafeUrl) -> Self {
+        self.peers.insert(peer, url);
+        self
+    }
+
+    /// Add the API endpoints of several guardians
+    pub fn with_peers(mut self, peers: impl IntoIterator<Item = (PeerId, SafeUrl)>) -> Self {
+        self.peers.extend(peers);
+        self
+    }
+
+    pub fn with_api_secret(mut self, api_secret: impl Into<String>) -> Self {
+        self.api_secret = Some(api_secret.into());
+        self
+    }
+
+    /// Fails if no guardian endpoint was added
+    pub fn build(self) -> anyhow::Result<InviteCode> {
+        ensure!(
+            !self.peers.is_empty(),
+            "An invite code needs the API endpoint of at least one guardian"
+        );
+
+        let mut parts: Vec<InviteCodePart> = self
+            .peers
+            .into_iter()
+            .map(|(peer, url)| InviteCodePart::Api { url, peer })
+            .collect();
+        parts.push(InviteCodePart::FederationId(self.federation_id));
+        if let Some(api_secret) = self.api_secret {
+            parts.push(InviteCodePart::ApiSecret(api_secret));
+        }
+
+        Ok(InviteCode(parts))
+    }
 }
 
 /// For extendability [`InviteCode`] consists of parts, where client can ignore
@@ -294,6 +372,43 @@ mod tests {
         );
     }
 
+    #[test]
+    fn invite_code_builder_roundtrip() {
+        let peers = BTreeMap::from_iter([
+            (
+                PeerId::from(0),
+                SafeUrl::parse("wss://alpha.example.com/").unwrap(),
+            ),
+            (
+                PeerId::from(2),
+                SafeUrl::parse("wss://gamma.example.com/").unwrap(),
+            ),
+        ]);
+
+        let invite_code = InviteCode::builder(FederationId::dummy())
+            .with_peers(peers.clone())
+            .with_api_secret("secret")
+            .build()
+            .expect("Has a peer");
+        let parsed = InviteCode::from_str(&invite_code.to_string()).expect("Valid invite code");
+
+        assert_eq!(parsed, invite_code);
+        assert_eq!(parsed.federation_id(), FederationId::dummy());
+        assert_eq!(parsed.peers(), peers);
+        assert_eq!(parsed.api_secret(), Some("secret".to_string()));
+
+        let single = parsed
+            .with_single_guardian(PeerId::from(2))
+            .expect("Peer is part of the invite code");
+        assert_eq!(single.peer(), PeerId::from(2));
+        assert_eq!(single.peers().len(), 1);
+        assert_eq!(single.federation_id(), FederationId::dummy());
+        assert_eq!(single.api_secret(), Some("secret".to_string()));
+        assert!(parsed.with_single_guardian(PeerId::from(1)).is_err());
+
+        assert!(InviteCode::builder(FederationId::dummy()).build().is_err());
+    }
+
     #[test]
     fn invite_code_v2_encode_base64_roundtrip() {
         let invite_code = InviteCodeV2 {
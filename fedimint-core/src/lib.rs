@@ -231,11 +231,112 @@ impl Amount {
         self.msats as f64 / 1000.0
     }
 
+    /// Adds `other`, returning `None` on overflow instead of panicking or
+    /// wrapping like `+`
+    pub fn checked_add(self, other: Amount) -> Option<Self> {
+        Some(Self {
+            msats: self.msats.checked_add(other.msats)?,
+        })
+    }
+
+    /// Subtracts `other`, returning `None` on underflow instead of panicking
+    /// or wrapping like `-`
     pub fn checked_sub(self, other: Amount) -> Option<Self> {
         Some(Self {
             msats: self.msats.checked_sub(other.msats)?,
         })
     }
+
+    /// Formats the amount in satoshis without losing millisatoshi precision,
+    /// e.g. `1.234` for 1234 msat
+    pub fn format_sats_precise(&self) -> String {
+        format_decimal(self.msats, MSATS_PER_SAT_DECIMALS)
+    }
+
+    /// Formats the amount in bitcoins without losing millisatoshi precision,
+    /// e.g. `0.00000001234` for 1234 msat
+    pub fn format_btc_precise(&self) -> String {
+        format_decimal(self.msats, MSATS_PER_BTC_DECIMALS)
+    }
+
+    /// Parses a decimal amount of bitcoins with up to millisatoshi precision,
+    /// the inverse of [`Amount::format_btc_precise`]
+    pub fn from_decimal_btc_str(s: &str) -> Result<Amount, ParseAmountError> {
+        parse_decimal(s, MSATS_PER_BTC_DECIMALS).map(Amount::from_msats)
+    }
+
+    /// Parses an amount with an explicit unit suffix, e.g. `1000msat`, `10sat`
+    /// or `0.5btc`
+    ///
+    /// Unlike [`Amount::from_str`] the unit is mandatory, so there is no
+    /// ambiguity between sats and msats, and decimals are parsed exactly
+    /// instead of via floats. Fractions of a millisatoshi are rejected.
+    pub fn from_str_with_unit(s: &str) -> Result<Amount, ParseAmountError> {
+        let s = s.trim();
+        let unit_start = s
+            .find(char::is_alphabetic)
+            .ok_or_else(|| ParseAmountError::MissingUnit(s.to_owned()))?;
+        let (value, unit) = s.split_at(unit_start);
+
+        let decimals = match unit.to_lowercase().as_str() {
+            "msat" | "msats" => 0,
+            "sat" | "sats" => MSATS_PER_SAT_DECIMALS,
+            "btc" => MSATS_PER_BTC_DECIMALS,
+            _ => return Err(ParseAmountError::UnknownUnit(unit.to_owned())),
+        };
+
+        parse_decimal(value.trim(), decimals).map(Amount::from_msats)
+    }
+}
+
+/// Number of decimal places of a millisatoshi in satoshis
+const MSATS_PER_SAT_DECIMALS: u32 = 3;
+/// Number of decimal places of a millisatoshi in bitcoins
+const MSATS_PER_BTC_DECIMALS: u32 = 11;
+
+/// Parses a non-negative decimal `s` into `s * 10^decimals` exactly, failing
+/// if it has more than `decimals` fractional digits
+fn parse_decimal(s: &str, decimals: u32) -> Result<u64, ParseAmountError> {
+    let invalid = || ParseAmountError::InvalidDecimal(s.to_owned());
+
+    let (int, frac) = s.split_once('.').unwrap_or((s, ""));
+    if (int.is_empty() && frac.is_empty())
+        || !int.chars().chain(frac.chars()).all(|c| c.is_ascii_digit())
+    {
+        return Err(invalid());
+    }
+    if frac.len() > decimals as usize {
+        return Err(ParseAmountError::TooPrecise);
+    }
+
+    let int_value = if int.is_empty() {
+        0
+    } else {
+        int.parse::<u64>()
+            .ok()
+            .and_then(|int| int.checked_mul(10u64.pow(decimals)))
+            .ok_or_else(invalid)?
+    };
+    let frac_value = if frac.is_empty() {
+        0
+    } else {
+        let padding = decimals - frac.len() as u32;
+        frac.parse::<u64>().map_err(|_| invalid())? * 10u64.pow(padding)
+    };
+
+    int_value.checked_add(frac_value).ok_or_else(invalid)
+}
+
+/// Formats `value / 10^decimals` exactly, omitting trailing zeros
+fn format_decimal(value: u64, decimals: u32) -> String {
+    let unit = 10u64.pow(decimals);
+    let (int, frac) = (value / unit, value % unit);
+    if frac == 0 {
+        return int.to_string();
+    }
+
+    let frac = format!("{frac:0width$}", width = decimals as usize);
+    format!("{int}.{}", frac.trim_end_matches('0'))
 }
 
 /// Shorthand for [`Amount::from_msats`]
@@ -324,6 +425,14 @@ pub enum ParseAmountError {
     NotANumber(#[from] ParseIntError),
     #[error("Error parsing string as a bitcoin amount: {0}")]
     WrongBitcoinAmount(#[from] bitcoin::amount::ParseAmountError),
+    #[error("Invalid decimal amount: {0}")]
+    InvalidDecimal(String),
+    #[error("Amount is using a precision smaller than millisatoshi")]
+    TooPrecise,
+    #[error("Amount is missing a unit (msat, sat or btc): {0}")]
+    MissingUnit(String),
+    #[error("Unknown amount unit: {0}")]
+    UnknownUnit(String),
 }
 
 impl<T> NumPeersExt for BTreeMap<PeerId, T> {
@@ -639,6 +748,22 @@ mod tests {
         assert_eq!(123 * Amount::from_msats(1000), Amount::from_msats(123_000));
     }
 
+    #[test]
+    fn amount_checked_arithmetic() {
+        let max = Amount::from_msats(u64::MAX);
+
+        assert_eq!(
+            Amount::from_msats(u64::MAX - 1).checked_add(msats(1)),
+            Some(max)
+        );
+        assert_eq!(max.checked_add(msats(1)), None);
+        assert_eq!(max.checked_add(Amount::ZERO), Some(max));
+
+        assert_eq!(msats(1).checked_sub(msats(1)), Some(Amount::ZERO));
+        assert_eq!(Amount::ZERO.checked_sub(msats(1)), None);
+        assert_eq!(max.checked_sub(max), Some(Amount::ZERO));
+    }
+
     #[test]
     fn converts_weight_to_vbytes() {
         assert_eq!(1, weight_to_vbytes(4));
@@ -690,6 +815,108 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_amount_precise_formatting() {
+        assert_eq!(Amount::from_msats(1234).format_sats_precise(), "1.234");
+        assert_eq!(Amount::from_msats(1001).format_sats_precise(), "1.001");
+        assert_eq!(Amount::from_msats(1).format_sats_precise(), "0.001");
+        assert_eq!(Amount::from_sats(21).format_sats_precise(), "21");
+
+        assert_eq!(
+            Amount::from_msats(1234).format_btc_precise(),
+            "0.00000001234"
+        );
+        assert_eq!(Amount::from_msats(1).format_btc_precise(), "0.00000000001");
+        assert_eq!(Amount::from_sats(150_000_000).format_btc_precise(), "1.5");
+        assert_eq!(Amount::ZERO.format_btc_precise(), "0");
+    }
+
+    #[test]
+    fn test_amount_decimal_btc_parsing() {
+        assert_eq!(
+            Amount::from_decimal_btc_str("0.00000001234").unwrap(),
+            Amount::from_msats(1234)
+        );
+        assert_eq!(
+            Amount::from_decimal_btc_str("1.5").unwrap(),
+            Amount::from_sats(150_000_000)
+        );
+        assert_eq!(
+            Amount::from_decimal_btc_str(".1").unwrap(),
+            Amount::from_sats(10_000_000)
+        );
+        assert_eq!(
+            Amount::from_decimal_btc_str("2").unwrap(),
+            Amount::from_bitcoins(2)
+        );
+
+        assert!(matches!(
+            Amount::from_decimal_btc_str("0.000000000001"),
+            Err(ParseAmountError::TooPrecise)
+        ));
+        for invalid in ["", ".", "-1", "1.2.3", "1e3", "0x10", "999999999999"] {
+            assert!(Amount::from_decimal_btc_str(invalid).is_err(), "{invalid}");
+        }
+
+        for msats in [
+            0,
+            1,
+            999,
+            1000,
+            1234,
+            100_000_000_000,
+            2_100_000_000_000_001,
+        ] {
+            let amount = Amount::from_msats(msats);
+            assert_eq!(
+                Amount::from_decimal_btc_str(&amount.format_btc_precise()).unwrap(),
+                amount
+            );
+        }
+    }
+
+    #[test]
+    fn test_amount_parsing_with_unit() {
+        assert_eq!(
+            Amount::from_str_with_unit("1000msat").unwrap(),
+            Amount::from_msats(1000)
+        );
+        assert_eq!(
+            Amount::from_str_with_unit("10sat").unwrap(),
+            Amount::from_sats(10)
+        );
+        assert_eq!(
+            Amount::from_str_with_unit("1.5 sat").unwrap(),
+            Amount::from_msats(1500)
+        );
+        assert_eq!(
+            Amount::from_str_with_unit("0.5btc").unwrap(),
+            Amount::from_sats(50_000_000)
+        );
+        assert_eq!(
+            Amount::from_str_with_unit("0.00000001234BTC").unwrap(),
+            Amount::from_msats(1234)
+        );
+
+        assert!(matches!(
+            Amount::from_str_with_unit("1.5msat"),
+            Err(ParseAmountError::TooPrecise)
+        ));
+        assert!(matches!(
+            Amount::from_str_with_unit("0.0001sat"),
+            Err(ParseAmountError::TooPrecise)
+        ));
+        assert!(matches!(
+            Amount::from_str_with_unit("1000"),
+            Err(ParseAmountError::MissingUnit(_))
+        ));
+        assert!(matches!(
+            Amount::from_str_with_unit("10bits"),
+            Err(ParseAmountError::UnknownUnit(_))
+        ));
+        assert!(Amount::from_str_with_unit("-1sat").is_err());
+    }
+
     #[test]
     fn test_deserialize_amount_or_all() {
         let all: BitcoinAmountOrAll = serde_json::from_str("\"all\"").unwrap();
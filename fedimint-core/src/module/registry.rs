@@ -1,9 +1,13 @@
+use std::cell::Cell;
 use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 
 use anyhow::anyhow;
 
 pub use crate::core::ModuleInstanceId;
 use crate::core::{Decoder, ModuleKind};
+use crate::encoding::DecodeError;
 use crate::server::DynServerModule;
 
 /// Module Registry hold module-specific data `M` by the `ModuleInstanceId`
@@ -183,24 +187,123 @@ pub enum DecodingMode {
     Fallback,
 }
 
+/// Maximum nesting depth of `Option`s, `Box`es and collections when decoding
+/// using a [`ModuleDecoderRegistry`] that doesn't set a different one
+pub const DEFAULT_MAX_DECODE_DEPTH: usize = 256;
+
+thread_local! {
+    /// Current nesting depth of the value being decoded on this thread.
+    /// Decoding is synchronous, so all nested decode calls of a value happen
+    /// on the same thread, no matter which registry clone they use.
+    static DECODE_DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// State of a [`ModuleDecoderRegistry`] shared by all nested decode calls
+#[derive(Default, Clone, Debug)]
+pub struct DecodingState {
+    mode: DecodingMode,
+    /// Remaining number of bytes nested collections may allocate, unlimited
+    /// if `None`
+    alloc_budget: Option<Arc<AtomicU64>>,
+    /// Maximum nesting depth, [`DEFAULT_MAX_DECODE_DEPTH`] if `None`
+    max_depth: Option<usize>,
+}
+
+/// Marks a nested value as being decoded until dropped, see
+/// [`ModuleDecoderRegistry::enter_nested`]
+#[must_use]
+pub struct DecodeDepthGuard(());
+
+impl Drop for DecodeDepthGuard {
+    fn drop(&mut self) {
+        DECODE_DEPTH.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
 /// Collection of decoders belonging to modules, typically obtained from a
 /// `ModuleRegistry`
-pub type ModuleDecoderRegistry = ModuleRegistry<Decoder, DecodingMode>;
+pub type ModuleDecoderRegistry = ModuleRegistry<Decoder, DecodingState>;
 
 impl ModuleDecoderRegistry {
     pub fn with_fallback(self) -> Self {
         Self {
-            state: DecodingMode::Fallback,
+            state: DecodingState {
+                mode: DecodingMode::Fallback,
+                ..self.state
+            },
             ..self
         }
     }
 
     pub fn decoding_mode(&self) -> DecodingMode {
-        self.state
+        self.state.mode
     }
 
     /// Panic if the [`Self::decoding_mode`] is not `Reject`
     pub fn assert_reject_mode(&self) {
-        assert_eq!(self.state, DecodingMode::Reject);
+        assert_eq!(self.state.mode, DecodingMode::Reject);
+    }
+
+    /// Limit the total memory collections decoded using the returned registry
+    /// may allocate to `bytes`, no matter how deeply they are nested
+    ///
+    /// The budget is shared with clones of the returned registry, so a new
+    /// one should be created for every value to decode.
+    pub fn with_alloc_budget(&self, bytes: u64) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            state: DecodingState {
+                alloc_budget: Some(Arc::new(AtomicU64::new(bytes))),
+                ..self.state.clone()
+            },
+        }
+    }
+
+    /// Limit how deeply `Option`s, `Box`es and collections may be nested in
+    /// values decoded using the returned registry
+    pub fn with_max_depth(&self, depth: usize) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            state: DecodingState {
+                max_depth: Some(depth),
+                ..self.state.clone()
+            },
+        }
+    }
+
+    /// Increase the nesting depth for as long as the returned guard is alive,
+    /// failing if that exceeds the maximum depth. Decoders of types that can
+    /// contain values of arbitrary other types have to call this to prevent
+    /// malicious inputs from overflowing the stack.
+    pub fn enter_nested(&self) -> Result<DecodeDepthGuard, DecodeError> {
+        let max_depth = self.state.max_depth.unwrap_or(DEFAULT_MAX_DECODE_DEPTH);
+        DECODE_DEPTH.with(|depth| {
+            if max_depth <= depth.get() {
+                return Err(DecodeError::new_custom(anyhow!(
+                    "Value is nested deeper than the maximum depth of {max_depth}"
+                )));
+            }
+            depth.set(depth.get() + 1);
+            Ok(DecodeDepthGuard(()))
+        })
+    }
+
+    /// Deduct `bytes` from the allocation budget, failing if not enough of it
+    /// is left
+    pub fn consume_alloc_budget(&self, bytes: u64) -> Result<(), DecodeError> {
+        let Some(budget) = &self.state.alloc_budget else {
+            return Ok(());
+        };
+
+        budget
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |remaining| {
+                remaining.checked_sub(bytes)
+            })
+            .map(|_| ())
+            .map_err(|remaining| {
+                DecodeError::new_custom(anyhow!(
+                    "Allocating {bytes} bytes exceeds the decode budget ({remaining} left)"
+                ))
+            })
     }
 }
@@ -24,6 +24,10 @@ use tracing::debug;
 
 use crate::envs::FM_ROCKSDB_WRITE_BUFFER_SIZE_ENV;
 
+/// RocksDB's own, cheap to read estimates used for the database stats
+const TOTAL_SST_FILES_SIZE_PROPERTY: &str = "rocksdb.total-sst-files-size";
+const ESTIMATE_NUM_KEYS_PROPERTY: &str = "rocksdb.estimate-num-keys";
+
 #[derive(Debug)]
 pub struct RocksDb(rocksdb::OptimisticTransactionDB);
 
@@ -163,6 +167,18 @@ impl IRawDatabase for RocksDb {
         checkpoint.create_checkpoint(backup_path)?;
         Ok(())
     }
+
+    async fn size_on_disk(&self) -> Result<u64> {
+        self.0
+            .property_int_value(TOTAL_SST_FILES_SIZE_PROPERTY)?
+            .context("RocksDB did not report its size")
+    }
+
+    async fn key_count(&self) -> Result<u64> {
+        self.0
+            .property_int_value(ESTIMATE_NUM_KEYS_PROPERTY)?
+            .context("RocksDB did not report its number of keys")
+    }
 }
 
 #[async_trait]
@@ -177,6 +193,18 @@ impl IRawDatabase for RocksDbReadOnly {
         checkpoint.create_checkpoint(backup_path)?;
         Ok(())
     }
+
+    async fn size_on_disk(&self) -> Result<u64> {
+        self.0
+            .property_int_value(TOTAL_SST_FILES_SIZE_PROPERTY)?
+            .context("RocksDB did not report its size")
+    }
+
+    async fn key_count(&self) -> Result<u64> {
+        self.0
+            .property_int_value(ESTIMATE_NUM_KEYS_PROPERTY)?
+            .context("RocksDB did not report its number of keys")
+    }
 }
 
 #[async_trait]
@@ -0,0 +1,195 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use fedimint_api_client::api::{
+    DynGlobalApi, DynModuleApi, IModuleFederationApi, IRawFederationApi, JsonRpcResult,
+};
+use fedimint_client::ApiRequestHook;
+use fedimint_core::core::ModuleInstanceId;
+use fedimint_core::task::{sleep, MaybeSend, MaybeSync};
+use fedimint_core::{apply, async_trait_maybe_send, PeerId};
+use jsonrpsee_core::client::Error as JsonRpcClientError;
+use serde_json::Value;
+
+/// Selects which API calls a [`FaultAction`] applies to
+///
+/// `None` fields match anything.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CallPattern {
+    pub peer_id: Option<PeerId>,
+    pub method: Option<String>,
+}
+
+impl CallPattern {
+    /// Matches every call to every peer
+    pub fn any() -> Self {
+        Self::default()
+    }
+
+    /// Matches every call to `peer_id`
+    pub fn peer(peer_id: PeerId) -> Self {
+        Self::any().with_peer(peer_id)
+    }
+
+    pub fn with_peer(mut self, peer_id: PeerId) -> Self {
+        self.peer_id = Some(peer_id);
+        self
+    }
+
+    pub fn with_method(mut self, method: impl Into<String>) -> Self {
+        self.method = Some(method.into());
+        self
+    }
+
+    pub fn matches(&self, peer_id: PeerId, method: &str) -> bool {
+        self.peer_id.map_or(true, |p| p == peer_id)
+            && self.method.as_deref().map_or(true, |m| m == method)
+    }
+}
+
+/// What to do with an API call matching a [`CallPattern`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FaultAction {
+    /// Delay the call before forwarding it to the peer
+    Delay(Duration),
+    /// Fail the next matching call with a timeout, then stop matching
+    DropOnce,
+    /// Fail every matching call with the given message
+    Error(String),
+}
+
+/// Scripted, deterministic faults for the API calls of a client
+///
+/// Clones share the same script, so a test can keep a handle and change the
+/// script while the client is running, e.g. to simulate a guardian outage
+/// and its recovery. The first entry matching a call decides its fate, calls
+/// matching no entry are forwarded unchanged.
+#[derive(Debug, Clone, Default)]
+pub struct FaultInjector {
+    script: Arc<Mutex<Vec<(CallPattern, FaultAction)>>>,
+    /// Every call seen so far, see [`Self::call_count`]
+    calls: Arc<Mutex<Vec<(PeerId, String)>>>,
+}
+
+impl FaultInjector {
+    pub fn new(script: Vec<(CallPattern, FaultAction)>) -> Self {
+        Self {
+            script: Arc::new(Mutex::new(script)),
+            calls: Arc::default(),
+        }
+    }
+
+    /// Number of calls matching `pattern` made so far, including faulted ones
+    pub fn call_count(&self, pattern: &CallPattern) -> usize {
+        self.calls
+            .lock()
+            .expect("lock poisoned")
+            .iter()
+            .filter(|(peer_id, method)| pattern.matches(*peer_id, method))
+            .count()
+    }
+
+    /// Replace the current script
+    pub fn set_script(&self, script: Vec<(CallPattern, FaultAction)>) {
+        *self.script.lock().expect("lock poisoned") = script;
+    }
+
+    /// Remove all faults, forwarding every call unchanged
+    pub fn clear(&self) {
+        self.set_script(vec![]);
+    }
+
+    /// Hook to pass to
+    /// [`fedimint_client::ClientBuilder::with_api_request_hook`]
+    pub fn api_request_hook(&self) -> ApiRequestHook {
+        let injector = self.clone();
+        Arc::new(move |api: DynGlobalApi| {
+            DynGlobalApi::from_raw(FaultInjectingApi::new(api, injector.clone()))
+        })
+    }
+
+    fn next_action(&self, peer_id: PeerId, method: &str) -> Option<FaultAction> {
+        self.calls
+            .lock()
+            .expect("lock poisoned")
+            .push((peer_id, method.to_owned()));
+
+        let mut script = self.script.lock().expect("lock poisoned");
+        let idx = script
+            .iter()
+            .position(|(pattern, _)| pattern.matches(peer_id, method))?;
+
+        if script[idx].1 == FaultAction::DropOnce {
+            Some(script.remove(idx).1)
+        } else {
+            Some(script[idx].1.clone())
+        }
+    }
+}
+
+/// Federation API applying the faults of a [`FaultInjector`] before
+/// forwarding calls to `inner`
+#[derive(Clone)]
+pub struct FaultInjectingApi<T> {
+    inner: T,
+    faults: FaultInjector,
+}
+
+impl<T> FaultInjectingApi<T> {
+    pub fn new(inner: T, faults: FaultInjector) -> Self {
+        Self { inner, faults }
+    }
+}
+
+impl<T> fmt::Debug for FaultInjectingApi<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FaultInjectingApi")
+            .field("faults", &self.faults)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> IModuleFederationApi for FaultInjectingApi<T>
+where
+    T: Deref + MaybeSend + MaybeSync + 'static,
+    T::Target: IRawFederationApi,
+{
+}
+
+#[apply(async_trait_maybe_send!)]
+impl<T> IRawFederationApi for FaultInjectingApi<T>
+where
+    T: Deref + MaybeSend + MaybeSync + 'static,
+    T::Target: IRawFederationApi,
+{
+    fn all_peers(&self) -> &BTreeSet<PeerId> {
+        self.inner.all_peers()
+    }
+
+    fn self_peer(&self) -> Option<PeerId> {
+        self.inner.self_peer()
+    }
+
+    fn with_module(&self, id: ModuleInstanceId) -> DynModuleApi {
+        FaultInjectingApi::new(self.inner.with_module(id), self.faults.clone()).into()
+    }
+
+    async fn request_raw(
+        &self,
+        peer_id: PeerId,
+        method: &str,
+        params: &[Value],
+    ) -> JsonRpcResult<Value> {
+        match self.faults.next_action(peer_id, method) {
+            Some(FaultAction::Delay(duration)) => sleep(duration).await,
+            Some(FaultAction::DropOnce) => return Err(JsonRpcClientError::RequestTimeout),
+            Some(FaultAction::Error(msg)) => return Err(JsonRpcClientError::Custom(msg)),
+            None => {}
+        }
+
+        self.inner.request_raw(peer_id, method, params).await
+    }
+}
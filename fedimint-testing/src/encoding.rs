@@ -0,0 +1,45 @@
+use std::fmt::Debug;
+
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::module::registry::ModuleDecoderRegistry;
+use fedimint_core::module::ModuleConsensusVersion;
+
+/// Simulates a node running `decoder_version` receiving `value`, a variant
+/// introduced in `value_version`, where `Old` is the type as known to the
+/// decoding node, e.g. a module input created with
+/// `extensible_associated_module_type!` before a new variant was added.
+///
+/// Asserts that `value` decodes as `Old`, re-encodes to the exact same bytes so
+/// it can be passed on unchanged, and lands in the fallback variant (as
+/// determined by `is_fallback`) if and only if the decoder is older than the
+/// value. Returns the decoded value for further checks.
+pub fn assert_downgrade_decoding<New, Old>(
+    value: &New,
+    value_version: ModuleConsensusVersion,
+    decoder_version: ModuleConsensusVersion,
+    is_fallback: impl Fn(&Old) -> bool,
+) -> Old
+where
+    New: Encodable,
+    Old: Encodable + Decodable + Debug,
+{
+    let bytes = value.consensus_encode_to_vec();
+    let decoded = Old::consensus_decode_vec(bytes.clone(), &ModuleDecoderRegistry::default())
+        .expect("Older decoder failed to decode value of newer consensus version");
+
+    assert_eq!(
+        decoded.consensus_encode_to_vec(),
+        bytes,
+        "Re-encoding {decoded:?} changed its bytes"
+    );
+
+    let is_newer =
+        (value_version.major, value_version.minor) > (decoder_version.major, decoder_version.minor);
+    assert_eq!(
+        is_fallback(&decoded),
+        is_newer,
+        "Value of version {value_version:?} decoded as {decoded:?} by version {decoder_version:?}"
+    );
+
+    decoded
+}
@@ -1,11 +1,11 @@
 use std::collections::{BTreeMap, HashMap};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
-use fedimint_api_client::api::{DynGlobalApi, FederationApiExt};
+use fedimint_api_client::api::{DynGlobalApi, DynModuleApi, FederationApiExt};
 use fedimint_client::module::init::ClientModuleInitRegistry;
 use fedimint_client::secret::{PlainRootSecretStrategy, RootSecretStrategy};
-use fedimint_client::{AdminCreds, Client, ClientHandleArc};
+use fedimint_client::{AdminCreds, ApiRequestHook, Client, ClientHandleArc};
 use fedimint_core::admin_client::{ConfigGenParamsConsensus, PeerServerParams};
 use fedimint_core::config::{
     ClientConfig, FederationId, ServerModuleConfigGenParamsRegistry, ServerModuleInitRegistry,
@@ -24,10 +24,13 @@ use fedimint_rocksdb::RocksDb;
 use fedimint_server::config::api::ConfigGenParamsLocal;
 use fedimint_server::config::{gen_cert_and_key, ConfigGenParams, ServerConfig};
 use fedimint_server::consensus;
+use fedimint_server::net::api::ApiSecrets;
 use fedimint_server::net::connect::parse_host_port;
 use tokio_rustls::rustls;
 use tracing::info;
 
+use crate::api::FaultInjector;
+
 /// Test fixture for a running fedimint federation
 #[derive(Clone)]
 pub struct FederationTest {
@@ -35,6 +38,7 @@ pub struct FederationTest {
     server_init: ServerModuleInitRegistry,
     client_init: ClientModuleInitRegistry,
     primary_client: ModuleInstanceId,
+    api_secret: Option<String>,
     _task: TaskGroup,
 }
 
@@ -85,11 +89,89 @@ impl FederationTest {
             .await
     }
 
+    /// Create a client connected to this fed whose API calls go through
+    /// `faults`, e.g. to simulate a guardian outage
+    pub async fn new_client_with_faults(&self, faults: &FaultInjector) -> ClientHandleArc {
+        let client_config = self.configs[&PeerId::from(0)]
+            .consensus
+            .to_client_config(&self.server_init)
+            .unwrap();
+
+        self.build_client(
+            client_config,
+            MemDatabase::new().into(),
+            None,
+            Some(faults.api_request_hook()),
+            None,
+            None,
+        )
+        .await
+    }
+
+    /// Like [`Self::new_client_with_faults`], but giving up on transaction
+    /// submissions after `retry_limit` transient failures
+    pub async fn new_client_with_faults_and_retry_limit(
+        &self,
+        faults: &FaultInjector,
+        retry_limit: u32,
+    ) -> ClientHandleArc {
+        let client_config = self.configs[&PeerId::from(0)]
+            .consensus
+            .to_client_config(&self.server_init)
+            .unwrap();
+
+        self.build_client(
+            client_config,
+            MemDatabase::new().into(),
+            None,
+            Some(faults.api_request_hook()),
+            None,
+            Some(retry_limit),
+        )
+        .await
+    }
+
+    /// Like [`Self::new_client_with_faults`], but failing requests to a
+    /// single guardian that take longer than `request_timeout`
+    pub async fn new_client_with_faults_and_timeout(
+        &self,
+        faults: &FaultInjector,
+        request_timeout: Duration,
+    ) -> ClientHandleArc {
+        let client_config = self.configs[&PeerId::from(0)]
+            .consensus
+            .to_client_config(&self.server_init)
+            .unwrap();
+
+        self.build_client(
+            client_config,
+            MemDatabase::new().into(),
+            None,
+            Some(faults.api_request_hook()),
+            Some(request_timeout),
+            None,
+        )
+        .await
+    }
+
     pub async fn new_client_with(
         &self,
         client_config: ClientConfig,
         db: Database,
         admin_creds: Option<AdminCreds>,
+    ) -> ClientHandleArc {
+        self.build_client(client_config, db, admin_creds, None, None, None)
+            .await
+    }
+
+    async fn build_client(
+        &self,
+        client_config: ClientConfig,
+        db: Database,
+        admin_creds: Option<AdminCreds>,
+        api_request_hook: Option<ApiRequestHook>,
+        request_timeout: Option<Duration>,
+        tx_submission_retry_limit: Option<u32>,
     ) -> ClientHandleArc {
         info!(target: LOG_TEST, "Setting new client with config");
         let mut client_builder = Client::builder(db);
@@ -98,6 +180,15 @@ impl FederationTest {
         if let Some(admin_creds) = admin_creds {
             client_builder.set_admin_creds(admin_creds);
         }
+        if let Some(api_request_hook) = api_request_hook {
+            client_builder.with_api_request_hook(api_request_hook);
+        }
+        if let Some(request_timeout) = request_timeout {
+            client_builder.with_request_timeout(request_timeout);
+        }
+        if let Some(retry_limit) = tx_submission_retry_limit {
+            client_builder.with_tx_submission_retry_limit(retry_limit);
+        }
         let client_secret = Client::load_or_generate_client_secret(client_builder.db_no_decoders())
             .await
             .unwrap();
@@ -105,7 +196,7 @@ impl FederationTest {
             .join(
                 PlainRootSecretStrategy::to_root_secret(&client_secret),
                 client_config,
-                None,
+                self.api_secret.clone(),
             )
             .await
             .map(Arc::new)
@@ -114,7 +205,7 @@ impl FederationTest {
 
     /// Return first invite code for gateways
     pub fn invite_code(&self) -> InviteCode {
-        self.configs[&PeerId::from(0)].get_invite_code(None)
+        self.configs[&PeerId::from(0)].get_invite_code(self.api_secret.clone())
     }
 
     ///  Return the federation id
@@ -136,9 +227,11 @@ pub struct FederationTestBuilder {
     base_port: u16,
     primary_client: ModuleInstanceId,
     version_hash: String,
+    api_secrets: ApiSecrets,
     params: ServerModuleConfigGenParamsRegistry,
     server_init: ServerModuleInitRegistry,
     client_init: ClientModuleInitRegistry,
+    ln_apis: Arc<Mutex<Vec<DynModuleApi>>>,
 }
 
 impl FederationTestBuilder {
@@ -155,12 +248,24 @@ impl FederationTestBuilder {
                 .expect("Failed to allocate a port range"),
             primary_client: 0,
             version_hash: "fedimint-testing-dummy-version-hash".to_owned(),
+            api_secrets: ApiSecrets::none(),
             params,
             server_init,
             client_init,
+            ln_apis: Arc::default(),
         }
     }
 
+    /// Adds the API of the federation's lightning module, if it has one, to
+    /// `ln_apis` once it is started
+    pub(crate) fn register_ln_api(
+        mut self,
+        ln_apis: Arc<Mutex<Vec<DynModuleApi>>>,
+    ) -> FederationTestBuilder {
+        self.ln_apis = ln_apis;
+        self
+    }
+
     pub fn num_peers(mut self, num_peers: u16) -> FederationTestBuilder {
         self.num_peers = num_peers;
         self
@@ -186,6 +291,12 @@ impl FederationTestBuilder {
         self
     }
 
+    /// Require clients to authenticate with one of the given API secrets
+    pub fn api_secrets(mut self, api_secrets: ApiSecrets) -> FederationTestBuilder {
+        self.api_secrets = api_secrets;
+        self
+    }
+
     pub async fn build(self) -> FederationTest {
         let num_offline = self.num_offline;
         assert!(
@@ -211,6 +322,7 @@ impl FederationTestBuilder {
             let module_init_registry = self.server_init.clone();
             let subgroup = task_group.make_subgroup();
             let checkpoint_dir = tempfile::Builder::new().tempdir().unwrap().into_path();
+            let api_secrets = self.api_secrets.clone();
 
             task_group.spawn("fedimintd", |_| async move {
                 consensus::run(
@@ -218,7 +330,7 @@ impl FederationTestBuilder {
                     db.clone(),
                     module_init_registry,
                     &subgroup,
-                    fedimint_server::net::api::ApiSecrets::default(),
+                    api_secrets,
                     checkpoint_dir,
                 )
                 .await
@@ -236,7 +348,11 @@ impl FederationTestBuilder {
                 .to_client_config(&self.server_init)
                 .unwrap();
 
-            let api = DynGlobalApi::from_config_admin(&client_config, &None, peer_id);
+            let api = DynGlobalApi::from_config_admin(
+                &client_config,
+                &self.api_secrets.get_active(),
+                peer_id,
+            );
 
             while let Err(e) = api
                 .request_admin_no_auth::<u64>(SESSION_COUNT_ENDPOINT, ApiRequestErased::default())
@@ -250,11 +366,28 @@ impl FederationTestBuilder {
             }
         }
 
+        let client_config = configs[&PeerId::from(0)]
+            .consensus
+            .to_client_config(&self.server_init)
+            .unwrap();
+        if let Some((ln_instance_id, _)) = client_config
+            .modules
+            .iter()
+            .find(|(_, module)| module.is_kind(&fedimint_ln_common::KIND))
+        {
+            let api = DynGlobalApi::from_config(&client_config, &self.api_secrets.get_active());
+            self.ln_apis
+                .lock()
+                .expect("poisoned")
+                .push(api.with_module(*ln_instance_id));
+        }
+
         FederationTest {
             configs,
             server_init: self.server_init,
             client_init: self.client_init,
             primary_client: self.primary_client,
+            api_secret: self.api_secrets.get_active(),
             _task: task_group,
         }
     }
@@ -1,8 +1,10 @@
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use std::{env, fs};
 
+use anyhow::{bail, Context};
+use fedimint_api_client::api::{DynModuleApi, FederationApiExt};
 use fedimint_bitcoind::{create_bitcoind, DynBitcoindRpc};
 use fedimint_client::module::init::{
     ClientModuleInitRegistry, DynClientModuleInit, IClientModuleInit,
@@ -12,11 +14,13 @@ use fedimint_core::config::{
 };
 use fedimint_core::core::{ModuleInstanceId, ModuleKind};
 use fedimint_core::envs::BitcoinRpcConfig;
-use fedimint_core::module::{DynServerModuleInit, IServerModuleInit};
+use fedimint_core::module::{ApiRequestErased, DynServerModuleInit, IServerModuleInit};
 use fedimint_core::runtime::block_in_place;
-use fedimint_core::task::{MaybeSend, MaybeSync, TaskGroup};
+use fedimint_core::task::{sleep_in_test, MaybeSend, MaybeSync, TaskGroup};
 use fedimint_core::util::SafeUrl;
+use fedimint_ln_common::federation_endpoint_constants::BLOCK_COUNT_ENDPOINT;
 use fedimint_logging::TracingSetup;
+use ln_gateway::lightning::LightningBuilder;
 use tempfile::TempDir;
 
 use crate::btc::mock::FakeBitcoinFactory;
@@ -26,8 +30,8 @@ use crate::envs::{
     FM_PORT_ESPLORA_ENV, FM_TEST_BITCOIND_RPC_ENV, FM_TEST_DIR_ENV, FM_TEST_USE_REAL_DAEMONS_ENV,
 };
 use crate::federation::{FederationTest, FederationTestBuilder};
-use crate::gateway::GatewayTest;
-use crate::ln::FakeLightningTest;
+use crate::gateway::{FakeLightningBuilder, GatewayTest};
+use crate::ln::{FakeLightningTest, MockLightning};
 
 /// A default timeout for things happening in tests
 pub const TIMEOUT: Duration = Duration::from_secs(10);
@@ -41,6 +45,9 @@ pub struct Fixtures {
     bitcoin: Arc<dyn BitcoinTest>,
     dyn_bitcoin_rpc: DynBitcoindRpc,
     id: ModuleInstanceId,
+    /// Lightning module APIs of the federations started from these fixtures,
+    /// see [`Fixtures::advance_consensus_block_count`]
+    ln_apis: Arc<Mutex<Vec<DynModuleApi>>>,
 }
 
 impl Fixtures {
@@ -81,6 +88,7 @@ impl Fixtures {
             bitcoin,
             dyn_bitcoin_rpc,
             id: 0,
+            ln_apis: Arc::default(),
         }
         .with_module(client, server, params)
     }
@@ -130,6 +138,7 @@ impl Fixtures {
             ServerModuleInitRegistry::from(self.servers.clone()),
             ClientModuleInitRegistry::from(self.clients.clone()),
         )
+        .register_ln_api(self.ln_apis.clone())
     }
 
     /// Starts a new gateway with a given lightning node
@@ -137,6 +146,49 @@ impl Fixtures {
         &self,
         num_route_hints: u32,
         cli_password: Option<String>,
+    ) -> GatewayTest {
+        self.new_gateway_with_lightning(
+            num_route_hints,
+            cli_password,
+            Arc::new(FakeLightningBuilder),
+            true,
+        )
+        .await
+    }
+
+    /// Starts a new gateway that releases preimages without checking the
+    /// `preimage_auth` of outgoing payments
+    pub async fn new_gateway_without_preimage_auth(
+        &self,
+        num_route_hints: u32,
+        cli_password: Option<String>,
+    ) -> GatewayTest {
+        self.new_gateway_with_lightning(
+            num_route_hints,
+            cli_password,
+            Arc::new(FakeLightningBuilder),
+            false,
+        )
+        .await
+    }
+
+    /// Starts a new gateway paying invoices through `lightning`
+    pub async fn new_gateway_with_mock_lightning(
+        &self,
+        num_route_hints: u32,
+        cli_password: Option<String>,
+        lightning: &MockLightning,
+    ) -> GatewayTest {
+        self.new_gateway_with_lightning(num_route_hints, cli_password, lightning.builder(), true)
+            .await
+    }
+
+    async fn new_gateway_with_lightning(
+        &self,
+        num_route_hints: u32,
+        cli_password: Option<String>,
+        lightning_builder: Arc<dyn LightningBuilder + Send + Sync>,
+        require_preimage_auth: bool,
     ) -> GatewayTest {
         // TODO: Make construction easier
         let server_gens = ServerModuleInitRegistry::from(self.servers.clone());
@@ -149,6 +201,7 @@ impl Fixtures {
                 .expect("Failed to allocate a port range"),
             cli_password,
             FakeLightningTest::new(),
+            lightning_builder,
             decoders,
             clients
                 .filter(|client| {
@@ -161,6 +214,7 @@ impl Fixtures {
                 })
                 .collect(),
             num_route_hints,
+            require_preimage_auth,
         )
         .await
     }
@@ -196,6 +250,61 @@ impl Fixtures {
     pub fn dyn_bitcoin_rpc(&self) -> DynBitcoindRpc {
         self.dyn_bitcoin_rpc.clone()
     }
+
+    /// Mines `n` blocks and waits until the consensus block count of every
+    /// federation with a lightning module started from these fixtures advanced
+    /// by at least `n`
+    ///
+    /// Fails if no such federation was started, since the consensus block
+    /// count is only exposed by the lightning module
+    pub async fn advance_consensus_block_count(&self, n: u64) -> anyhow::Result<()> {
+        let ln_apis = self.ln_apis.lock().expect("poisoned").clone();
+        if ln_apis.is_empty() {
+            bail!("No federation with a lightning module was started from these fixtures");
+        }
+
+        let mut targets = Vec::with_capacity(ln_apis.len());
+        for ln_api in ln_apis {
+            let block_count = fetch_consensus_block_count(&ln_api)
+                .await
+                .context("Federation has no consensus block count yet")?;
+            targets.push((ln_api, block_count + n));
+        }
+
+        self.bitcoin.mine_blocks(n).await;
+
+        for (ln_api, target) in targets {
+            loop {
+                match fetch_consensus_block_count(&ln_api).await {
+                    Some(block_count) if target <= block_count => break,
+                    block_count => {
+                        sleep_in_test(
+                            format!(
+                                "Waiting for consensus block count {block_count:?} to reach {target}"
+                            ),
+                            Duration::from_millis(100),
+                        )
+                        .await;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Peers may briefly disagree while their votes propagate, so errors are
+/// treated the same as a missing block count
+async fn fetch_consensus_block_count(ln_api: &DynModuleApi) -> Option<u64> {
+    ln_api
+        .request_current_consensus::<Option<u64>>(
+            BLOCK_COUNT_ENDPOINT.to_string(),
+            ApiRequestErased::default(),
+        )
+        .await
+        .ok()
+        .flatten()
 }
 
 /// If `FM_TEST_DIR` is set, use it as a base, otherwise use a tempdir
@@ -78,9 +78,11 @@ impl GatewayTest {
         base_port: u16,
         cli_password: Option<String>,
         lightning: FakeLightningTest,
+        lightning_builder: Arc<dyn LightningBuilder + Send + Sync>,
         decoders: ModuleDecoderRegistry,
         registry: ClientModuleInitRegistry,
         num_route_hints: u32,
+        require_preimage_auth: bool,
     ) -> Self {
         let listen: SocketAddr = format!("127.0.0.1:{base_port}").parse().unwrap();
         let address: SafeUrl = format!("http://{listen}").parse().unwrap();
@@ -90,10 +92,8 @@ impl GatewayTest {
 
         // Create federation client builder for the gateway
         let client_builder: GatewayClientBuilder =
-            GatewayClientBuilder::new(path.clone(), registry, 0);
-
-        let lightning_builder: Arc<dyn LightningBuilder + Send + Sync> =
-            Arc::new(FakeLightningBuilder);
+            GatewayClientBuilder::new(path.clone(), registry, 0)
+                .with_require_preimage_auth(require_preimage_auth);
 
         let gateway_db = Database::new(MemDatabase::new(), decoders.clone());
 
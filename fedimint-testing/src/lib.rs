@@ -8,8 +8,10 @@
 #![allow(clippy::must_use_candidate)]
 #![allow(clippy::return_self_not_must_use)]
 
+pub mod api;
 pub mod btc;
 pub mod db;
+pub mod encoding;
 pub mod envs;
 pub mod federation;
 pub mod fixtures;
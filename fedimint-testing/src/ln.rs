@@ -1,3 +1,4 @@
+use std::collections::{BTreeMap, VecDeque};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
@@ -6,7 +7,7 @@ use async_trait::async_trait;
 use bitcoin::hashes::{sha256, Hash};
 use bitcoin::key::KeyPair;
 use bitcoin::secp256k1::{PublicKey, SecretKey};
-use fedimint_core::task::TaskGroup;
+use fedimint_core::task::{sleep, TaskGroup};
 use fedimint_core::util::BoxStream;
 use fedimint_core::{secp256k1, Amount};
 use fedimint_ln_common::PrunedInvoice;
@@ -21,7 +22,7 @@ use ln_gateway::gateway_lnrpc::{
     InterceptHtlcResponse, PayInvoiceRequest, PayInvoiceResponse,
 };
 use ln_gateway::lightning::cln::{HtlcResult, RouteHtlcStream};
-use ln_gateway::lightning::{ChannelInfo, ILnRpcClient, LightningRpcError};
+use ln_gateway::lightning::{ChannelInfo, ILnRpcClient, LightningBuilder, LightningRpcError};
 use rand::rngs::OsRng;
 use tokio::sync::mpsc;
 use tracing::info;
@@ -252,3 +253,243 @@ impl ILnRpcClient for FakeLightningTest {
         unimplemented!("FakeLightningTest does not support listing active channels")
     }
 }
+
+/// Outcome of a single attempt of [`MockLightning`] to pay an invoice
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MockPayOutcome {
+    /// The payment succeeds, revealing the invoice's preimage
+    Success,
+    /// The node cannot be reached, a later attempt may succeed
+    TransientFailure,
+    /// The payment cannot be routed
+    PermanentFailure,
+    /// The payment is stuck for the given duration, then fails
+    Timeout(Duration),
+    /// The payment succeeds after the given duration
+    Delayed(Duration),
+}
+
+#[derive(Debug)]
+struct MockPayment {
+    preimage: [u8; 32],
+    outcomes: VecDeque<MockPayOutcome>,
+    attempts: usize,
+}
+
+/// Lightning node paying its invoices according to a programmed sequence of
+/// [`MockPayOutcome`]s, one per payment attempt
+///
+/// Invoices not created through [`MockLightning::invoice`] are paid like
+/// [`FakeLightningTest`] does, as are attempts beyond the programmed ones.
+#[derive(Debug)]
+pub struct MockLightning {
+    fake: FakeLightningTest,
+    payments: Arc<Mutex<BTreeMap<sha256::Hash, MockPayment>>>,
+}
+
+impl MockLightning {
+    pub fn new() -> Self {
+        Self {
+            fake: FakeLightningTest::new(),
+            payments: Arc::default(),
+        }
+    }
+
+    /// Creates an invoice whose payment attempts have the given `outcomes`,
+    /// in order
+    pub fn invoice(&self, amount: Amount, outcomes: Vec<MockPayOutcome>) -> Bolt11Invoice {
+        let ctx = bitcoin::secp256k1::Secp256k1::new();
+        let preimage: [u8; 32] = rand::random();
+        let payment_hash = sha256::Hash::hash(&preimage);
+
+        self.payments.lock().unwrap().insert(
+            payment_hash,
+            MockPayment {
+                preimage,
+                outcomes: outcomes.into(),
+                attempts: 0,
+            },
+        );
+
+        InvoiceBuilder::new(Currency::Regtest)
+            .description(String::new())
+            .payment_hash(payment_hash)
+            .current_timestamp()
+            .min_final_cltv_expiry_delta(0)
+            .payment_secret(PaymentSecret(rand::random()))
+            .amount_milli_satoshis(amount.msats)
+            .expiry_time(Duration::from_secs(DEFAULT_EXPIRY_TIME))
+            .build_signed(|m| ctx.sign_ecdsa_recoverable(m, &self.fake.gateway_node_sec_key))
+            .expect("Invoice creation failed")
+    }
+
+    /// Number of times a gateway started paying `invoice`, which has to be
+    /// created by [`MockLightning::invoice`]
+    pub fn pay_attempts(&self, invoice: &Bolt11Invoice) -> usize {
+        self.payments.lock().unwrap()[invoice.payment_hash()].attempts
+    }
+
+    /// Builder handing the gateway nodes that share this node's programmed
+    /// payments
+    pub fn builder(&self) -> Arc<dyn LightningBuilder + Send + Sync> {
+        Arc::new(MockLightningBuilder {
+            payments: self.payments.clone(),
+        })
+    }
+
+    /// Pays the invoice with `payment_hash` if it was created by this node,
+    /// `None` otherwise
+    async fn pay_programmed(
+        &self,
+        payment_hash: sha256::Hash,
+    ) -> Option<Result<PayInvoiceResponse, LightningRpcError>> {
+        let (preimage, outcome) = {
+            let mut payments = self.payments.lock().unwrap();
+            let payment = payments.get_mut(&payment_hash)?;
+            payment.attempts += 1;
+            (payment.preimage, payment.outcomes.pop_front())
+        };
+
+        let failure_reason = match outcome.unwrap_or(MockPayOutcome::Success) {
+            MockPayOutcome::Success => {
+                return Some(Ok(PayInvoiceResponse {
+                    preimage: preimage.to_vec(),
+                }));
+            }
+            MockPayOutcome::Delayed(duration) => {
+                sleep(duration).await;
+                return Some(Ok(PayInvoiceResponse {
+                    preimage: preimage.to_vec(),
+                }));
+            }
+            MockPayOutcome::TransientFailure => {
+                return Some(Err(LightningRpcError::FailedToConnect))
+            }
+            MockPayOutcome::PermanentFailure => "No route found".to_string(),
+            MockPayOutcome::Timeout(duration) => {
+                sleep(duration).await;
+                "Payment timed out".to_string()
+            }
+        };
+
+        Some(Err(LightningRpcError::FailedPayment { failure_reason }))
+    }
+}
+
+impl Default for MockLightning {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl ILnRpcClient for MockLightning {
+    async fn info(&self) -> Result<GetNodeInfoResponse, LightningRpcError> {
+        self.fake.info().await
+    }
+
+    async fn routehints(
+        &self,
+        num_route_hints: usize,
+    ) -> Result<GetRouteHintsResponse, LightningRpcError> {
+        self.fake.routehints(num_route_hints).await
+    }
+
+    async fn pay(
+        &self,
+        invoice: PayInvoiceRequest,
+    ) -> Result<PayInvoiceResponse, LightningRpcError> {
+        let payment_hash =
+            sha256::Hash::from_slice(&invoice.payment_hash).expect("Invalid payment hash");
+        match self.pay_programmed(payment_hash).await {
+            Some(result) => result,
+            None => self.fake.pay(invoice).await,
+        }
+    }
+
+    fn supports_private_payments(&self) -> bool {
+        self.fake.supports_private_payments()
+    }
+
+    async fn pay_private(
+        &self,
+        invoice: PrunedInvoice,
+        max_delay: u64,
+        max_fee: Amount,
+    ) -> Result<PayInvoiceResponse, LightningRpcError> {
+        match self.pay_programmed(invoice.payment_hash).await {
+            Some(result) => result,
+            None => self.fake.pay_private(invoice, max_delay, max_fee).await,
+        }
+    }
+
+    async fn route_htlcs<'a>(
+        self: Box<Self>,
+        task_group: &mut TaskGroup,
+    ) -> Result<(RouteHtlcStream<'a>, Arc<dyn ILnRpcClient>), LightningRpcError> {
+        let Self { fake, payments } = *self;
+        let (stream, _) = Box::new(fake).route_htlcs(task_group).await?;
+        Ok((
+            stream,
+            Arc::new(Self {
+                fake: FakeLightningTest::new(),
+                payments,
+            }),
+        ))
+    }
+
+    async fn complete_htlc(
+        &self,
+        htlc: InterceptHtlcResponse,
+    ) -> Result<EmptyResponse, LightningRpcError> {
+        self.fake.complete_htlc(htlc).await
+    }
+
+    async fn create_invoice(
+        &self,
+        create_invoice_request: CreateInvoiceRequest,
+    ) -> Result<CreateInvoiceResponse, LightningRpcError> {
+        self.fake.create_invoice(create_invoice_request).await
+    }
+
+    async fn get_funding_address(&self) -> Result<GetFundingAddressResponse, LightningRpcError> {
+        self.fake.get_funding_address().await
+    }
+
+    async fn open_channel(
+        &self,
+        pubkey: bitcoin::secp256k1::PublicKey,
+        host: String,
+        channel_size_sats: u64,
+        push_amount_sats: u64,
+    ) -> Result<EmptyResponse, LightningRpcError> {
+        self.fake
+            .open_channel(pubkey, host, channel_size_sats, push_amount_sats)
+            .await
+    }
+
+    async fn close_channels_with_peer(
+        &self,
+        pubkey: bitcoin::secp256k1::PublicKey,
+    ) -> Result<CloseChannelsWithPeerResponse, LightningRpcError> {
+        self.fake.close_channels_with_peer(pubkey).await
+    }
+
+    async fn list_active_channels(&self) -> Result<Vec<ChannelInfo>, LightningRpcError> {
+        self.fake.list_active_channels().await
+    }
+}
+
+struct MockLightningBuilder {
+    payments: Arc<Mutex<BTreeMap<sha256::Hash, MockPayment>>>,
+}
+
+#[async_trait]
+impl LightningBuilder for MockLightningBuilder {
+    async fn build(&self) -> Box<dyn ILnRpcClient> {
+        Box::new(MockLightning {
+            fake: FakeLightningTest::new(),
+            payments: self.payments.clone(),
+        })
+    }
+}
@@ -37,3 +37,33 @@ pub fn default_esplora_server(network: Network) -> BitcoinRpcConfig {
         url,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::Network;
+    use fedimint_core::encoding::{Decodable, Encodable};
+    use fedimint_core::module::registry::ModuleDecoderRegistry;
+    use fedimint_core::util::SafeUrl;
+
+    use crate::default_esplora_server;
+
+    #[test]
+    fn default_esplora_servers_survive_encoding_roundtrip() {
+        for network in [
+            Network::Bitcoin,
+            Network::Testnet,
+            Network::Regtest,
+            Network::Signet,
+        ] {
+            let url = default_esplora_server(network).url;
+            assert!(url.as_str().ends_with('/'));
+
+            let bytes = url.consensus_encode_to_vec();
+            assert_eq!(bytes, url.as_str().consensus_encode_to_vec());
+
+            let decoded = SafeUrl::consensus_decode_vec(bytes, &ModuleDecoderRegistry::default())
+                .expect("Decoding failed");
+            assert_eq!(decoded.as_str(), url.as_str());
+        }
+    }
+}
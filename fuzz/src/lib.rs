@@ -12,10 +12,7 @@ use fedimint_meta_common::MetaCommonInit;
 use fedimint_mint_common::MintCommonInit;
 use fedimint_wallet_common::WalletCommonInit;
 
-pub fn all_standard_modules() -> fedimint_core::module::registry::ModuleRegistry<
-    fedimint_core::core::Decoder,
-    fedimint_core::module::registry::DecodingMode,
-> {
+pub fn all_standard_modules() -> ModuleDecoderRegistry {
     ModuleDecoderRegistry::new([
         (0, WalletCommonInit::KIND, WalletCommonInit::decoder()),
         (1, MintCommonInit::KIND, MintCommonInit::decoder()),
@@ -8,6 +8,8 @@
 //! clients to request routing of payments through the Lightning Network.
 //! The API also has endpoints for managing the gateway.
 
+use std::time::Duration;
+
 use fedimint_core::fedimint_build_code_version_env;
 use fedimint_core::task::TaskGroup;
 use fedimint_core::util::handle_version_hash_command;
@@ -15,6 +17,10 @@ use fedimint_logging::TracingSetup;
 use ln_gateway::Gateway;
 use tracing::info;
 
+/// How long to wait on shutdown for outgoing payments that weren't sent yet to
+/// cancel their contracts
+const UNSENT_PAYMENTS_CANCEL_TIMEOUT: Duration = Duration::from_secs(30);
+
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
     handle_version_hash_command(fedimint_build_code_version_env!());
@@ -24,6 +30,9 @@ async fn main() -> Result<(), anyhow::Error> {
     let gatewayd = Gateway::new_with_default_modules().await?;
     let shutdown_receiver = gatewayd.clone().run(&tg).await?;
     shutdown_receiver.await;
+    gatewayd
+        .cancel_unpaid_outgoing_contracts(UNSENT_PAYMENTS_CANCEL_TIMEOUT)
+        .await;
     gatewayd.leave_all_federations().await;
     info!("Gatewayd exiting...");
     Ok(())
@@ -25,6 +25,7 @@ pub struct GatewayClientBuilder {
     work_dir: PathBuf,
     registry: ClientModuleInitRegistry,
     primary_module: ModuleInstanceId,
+    require_preimage_auth: bool,
 }
 
 impl GatewayClientBuilder {
@@ -37,8 +38,18 @@ impl GatewayClientBuilder {
             work_dir,
             registry,
             primary_module,
+            require_preimage_auth: true,
         }
     }
+
+    /// Whether outgoing payments must be authenticated with the
+    /// `preimage_auth` of the first payment of the same invoice before the
+    /// gateway releases the preimage. Enabled by default, only disable it
+    /// if the gateway is not exposed to untrusted clients.
+    pub fn with_require_preimage_auth(mut self, require_preimage_auth: bool) -> Self {
+        self.require_preimage_auth = require_preimage_auth;
+        self
+    }
 }
 
 impl GatewayClientBuilder {
@@ -61,6 +72,7 @@ impl GatewayClientBuilder {
             timelock_delta,
             mint_channel_id,
             gateway: gateway.clone(),
+            require_preimage_auth: self.require_preimage_auth,
         });
         registry.attach(GatewayClientInitV2 { gateway });
 
@@ -36,6 +36,7 @@ use std::net::SocketAddr;
 use std::ops::ControlFlow;
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -73,7 +74,7 @@ use fedimint_core::util::{SafeUrl, Spanned};
 use fedimint_core::{
     fedimint_build_code_version_env, push_db_pair_items, Amount, BitcoinAmountOrAll, BitcoinHash,
 };
-use fedimint_ln_client::pay::PayInvoicePayload;
+use fedimint_ln_client::pay::{PayInvoicePayload, PaymentData};
 use fedimint_ln_common::config::{GatewayFee, LightningClientConfig};
 use fedimint_ln_common::contracts::Preimage;
 use fedimint_ln_common::route_hints::RouteHint;
@@ -102,7 +103,7 @@ use rpc::{
     CloseChannelsWithPeerPayload, FederationInfo, GatewayFedConfig, GatewayInfo, LeaveFedPayload,
     OpenChannelPayload, SetConfigurationPayload, V1_API_ENDPOINT,
 };
-use state_machine::pay::OutgoingPaymentError;
+use state_machine::pay::{OutgoingPaymentError, SwapCandidate};
 use state_machine::GatewayClientModule;
 use strum::IntoEnumIterator;
 use thiserror::Error;
@@ -341,6 +342,10 @@ pub struct Gateway {
 
     // The socket the gateway listens on.
     listen: SocketAddr,
+
+    // Set once the gateway started shutting down, after which outgoing payments that
+    // weren't sent yet cancel their contract instead.
+    shutting_down: Arc<AtomicBool>,
 }
 
 impl std::fmt::Debug for Gateway {
@@ -470,6 +475,7 @@ impl Gateway {
             client_joining_lock: Arc::new(Mutex::new(ClientsJoinLock)),
             versioned_api: gateway_parameters.versioned_api,
             listen: gateway_parameters.listen,
+            shutting_down: Arc::new(AtomicBool::new(false)),
         })
     }
 
@@ -986,6 +992,7 @@ impl Gateway {
                     GatewayExtPayStates::Fail {
                         error,
                         error_message,
+                        ..
                     } => {
                         error!("{error_message} while paying invoice: {contract_id}");
                         return Err(GatewayError::OutgoingPaymentError(Box::new(error)));
@@ -1653,6 +1660,82 @@ impl Gateway {
         }
     }
 
+    /// Returns the connected federations an invoice can be paid into via a
+    /// direct swap, i.e. those whose short channel id is the last hop of one of
+    /// the invoice's route hints through our node, in route hint order. Each
+    /// candidate carries the gateway's ecash balance in that federation, which
+    /// is what funds the swap.
+    pub async fn direct_swap_candidates(&self, payment_data: &PaymentData) -> Vec<SwapCandidate> {
+        let Ok(lightning_context) = self.get_lightning_context().await else {
+            return vec![];
+        };
+
+        let federation_ids = {
+            let scid_to_feds = self.scid_to_federation.read().await;
+            payment_data
+                .route_hints()
+                .iter()
+                .filter_map(|route_hint| route_hint.0.last())
+                .filter(|hop| hop.src_node_id == lightning_context.lightning_public_key)
+                .filter_map(|hop| scid_to_feds.get(&hop.short_channel_id).copied())
+                .fold(vec![], |mut federation_ids, federation_id| {
+                    if !federation_ids.contains(&federation_id) {
+                        federation_ids.push(federation_id);
+                    }
+                    federation_ids
+                })
+        };
+
+        let mut candidates = vec![];
+        for federation_id in federation_ids {
+            let Some(client) = self.clients.read().await.get(&federation_id).cloned() else {
+                continue;
+            };
+            candidates.push(SwapCandidate {
+                federation_id,
+                available_liquidity: client.value().get_balance().await,
+            });
+        }
+        candidates
+    }
+
+    /// Returns true once [`Gateway::cancel_unpaid_outgoing_contracts`] was
+    /// called
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::SeqCst)
+    }
+
+    /// Stops sending outgoing payments and waits up to `timeout` for the
+    /// contracts of payments that weren't sent yet to be canceled, so their
+    /// users are refunded right away instead of after the timelock expired.
+    ///
+    /// Payments that were already sent over lightning or via a direct swap are
+    /// left to complete, as canceling them could cost the gateway the funds.
+    pub async fn cancel_unpaid_outgoing_contracts(&self, timeout: Duration) {
+        self.shutting_down.store(true, Ordering::SeqCst);
+
+        let clients = self
+            .clients
+            .read()
+            .await
+            .values()
+            .map(|client| client.value().clone())
+            .collect::<Vec<_>>();
+        let unsent_payments = futures::future::join_all(clients.iter().map(|client| async move {
+            client
+                .get_first_module::<GatewayClientModule>()
+                .await_unsent_payments()
+                .await;
+        }));
+
+        if fedimint_core::runtime::timeout(timeout, unsent_payments)
+            .await
+            .is_err()
+        {
+            warn!("Timed out waiting for unsent outgoing payments to be canceled");
+        }
+    }
+
     /// Iterates through all of the federations the gateway is registered with
     /// and requests to remove the registration record.
     pub async fn leave_all_federations(&self) {
@@ -18,6 +18,7 @@ use fedimint_client::sm::util::MapStateTransitions;
 use fedimint_client::sm::{Context, DynState, ModuleNotifier, State};
 use fedimint_client::transaction::{ClientOutput, TransactionBuilder};
 use fedimint_client::{sm_enum_variant_translation, AddStateMachinesError, DynGlobalClientContext};
+use fedimint_core::config::FederationId;
 use fedimint_core::core::{Decoder, IntoDynInstance, ModuleInstanceId, OperationId};
 use fedimint_core::db::{AutocommitError, DatabaseTransaction, DatabaseVersion};
 use fedimint_core::encoding::{Decodable, Encodable};
@@ -49,8 +50,8 @@ use tracing::{debug, info, warn};
 
 use self::complete::GatewayCompleteStateMachine;
 use self::pay::{
-    GatewayPayCommon, GatewayPayInvoice, GatewayPayStateMachine, GatewayPayStates,
-    OutgoingPaymentError,
+    validate_payment_data, GatewayPayCommon, GatewayPayInvoice, GatewayPayStateMachine,
+    GatewayPayStates, OutgoingContractError, OutgoingPaymentError, RefundFailureKind,
 };
 use crate::gateway_lnrpc::InterceptHtlcRequest;
 use crate::state_machine::complete::{
@@ -76,6 +77,7 @@ pub enum GatewayExtPayStates {
     Fail {
         error: OutgoingPaymentError,
         error_message: String,
+        refund_failure: RefundFailureKind,
     },
     OfferDoesNotExist {
         contract_id: ContractId,
@@ -112,6 +114,7 @@ pub struct GatewayClientInit {
     pub timelock_delta: u64,
     pub mint_channel_id: u64,
     pub gateway: Arc<Gateway>,
+    pub require_preimage_auth: bool,
 }
 
 impl ModuleInit for GatewayClientInit {
@@ -149,6 +152,8 @@ impl ClientModuleInit for GatewayClientInit {
             mint_channel_id: self.mint_channel_id,
             client_ctx: args.context(),
             gateway: self.gateway.clone(),
+            require_preimage_auth: self.require_preimage_auth,
+            federation_id: *args.federation_id(),
         })
     }
 }
@@ -161,6 +166,9 @@ pub struct GatewayClientContext {
     pub ln_decoder: Decoder,
     notifier: ModuleNotifier<GatewayClientStateMachines>,
     gateway: Arc<Gateway>,
+    require_preimage_auth: bool,
+    /// Federation the state machines of this client belong to
+    federation_id: FederationId,
 }
 
 impl Context for GatewayClientContext {}
@@ -189,6 +197,8 @@ pub struct GatewayClientModule {
     module_api: DynModuleApi,
     client_ctx: ClientContext<Self>,
     gateway: Arc<Gateway>,
+    require_preimage_auth: bool,
+    federation_id: FederationId,
 }
 
 impl ClientModule for GatewayClientModule {
@@ -206,6 +216,8 @@ impl ClientModule for GatewayClientModule {
             ln_decoder: self.decoder(),
             notifier: self.notifier.clone(),
             gateway: self.gateway.clone(),
+            require_preimage_auth: self.require_preimage_auth,
+            federation_id: self.federation_id,
         }
     }
 
@@ -513,6 +525,16 @@ impl GatewayClientModule {
         }))
     }
 
+    /// Checks the invoice of an outgoing payment for problems that would make
+    /// the payment fail regardless of the contract funding it, so callers can
+    /// reject it before a contract is funded
+    pub fn pre_validate_invoice(
+        &self,
+        payment_data: &PaymentData,
+    ) -> Result<(), OutgoingContractError> {
+        validate_payment_data(payment_data).map(|_| ())
+    }
+
     /// Pay lightning invoice on behalf of federation user
     pub async fn gateway_pay_bolt11_invoice(
         &self,
@@ -581,6 +603,57 @@ impl GatewayClientModule {
             })
     }
 
+    /// Lists the contract id and current state of every outgoing payment the
+    /// gateway has not finished processing yet
+    pub async fn list_active_payments(&self) -> Vec<(ContractId, String)> {
+        self.client_ctx
+            .get_own_active_states()
+            .await
+            .into_iter()
+            .filter_map(|(state, _)| match state {
+                GatewayClientStateMachines::Pay(pay) => pay
+                    .state
+                    .contract_id()
+                    .map(|contract_id| (contract_id, pay.state.to_string())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Waits until every outgoing payment that wasn't sent yet or is canceling
+    /// its contract reached a final state
+    pub async fn await_unsent_payments(&self) {
+        let operation_ids = self
+            .client_ctx
+            .get_own_active_states()
+            .await
+            .into_iter()
+            .filter_map(|(state, _)| match state {
+                GatewayClientStateMachines::Pay(GatewayPayStateMachine {
+                    common,
+                    state: GatewayPayStates::PayInvoice(_) | GatewayPayStates::CancelContract(_),
+                }) => Some(common.operation_id),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        futures::future::join_all(operation_ids.into_iter().map(|operation_id| async move {
+            let Ok(updates) = self.gateway_subscribe_ln_pay(operation_id).await else {
+                return;
+            };
+            let mut updates = std::pin::pin!(updates.into_stream());
+            while let Some(state) = updates.next().await {
+                if !matches!(
+                    state,
+                    GatewayExtPayStates::Created | GatewayExtPayStates::Preimage { .. }
+                ) {
+                    return;
+                }
+            }
+        }))
+        .await;
+    }
+
     pub async fn gateway_subscribe_ln_pay(
         &self,
         operation_id: OperationId,
@@ -623,7 +696,7 @@ impl GatewayClientModule {
                                     }
                                     Err(e) => {
                                         warn!(?operation_id, "Got failure {e:?} while awaiting for transaction {txid} to be accepted for");
-                                        yield GatewayExtPayStates::Fail { error, error_message: format!("Refund transaction {txid} was not accepted by the federation. OperationId: {} Error: {e:?}", operation_id.fmt_short()) };
+                                        yield GatewayExtPayStates::Fail { error, error_message: format!("Refund transaction {txid} was not accepted by the federation. OperationId: {} Error: {e:?}", operation_id.fmt_short()), refund_failure: RefundFailureKind::SubmissionRejected };
                                     }
                                 }
                             }
@@ -633,7 +706,11 @@ impl GatewayClientModule {
                             }
                             GatewayPayStates::Failed{ error, error_message } => {
                                 warn!("Yielding Fail state for {} due to {error:?} {error_message:?}", operation_id.fmt_short());
-                                yield GatewayExtPayStates::Fail{ error, error_message };
+                                yield GatewayExtPayStates::Fail{ error, error_message, refund_failure: RefundFailureKind::Unknown };
+                            },
+                            GatewayPayStates::RefundFailed{ error, error_message, refund_failure } => {
+                                warn!("Yielding Fail state for {} due to {error:?} {error_message:?} {refund_failure:?}", operation_id.fmt_short());
+                                yield GatewayExtPayStates::Fail{ error, error_message, refund_failure };
                             },
                             GatewayPayStates::PayInvoice(_) => {
                                 debug!("Got initial state PayInvoice while awaiting for output of {}", operation_id.fmt_short());
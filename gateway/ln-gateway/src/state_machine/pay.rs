@@ -16,7 +16,8 @@ use fedimint_ln_client::pay::{PayInvoicePayload, PaymentData};
 use fedimint_ln_common::config::FeeToAmount;
 use fedimint_ln_common::contracts::outgoing::OutgoingContractAccount;
 use fedimint_ln_common::contracts::{ContractId, FundedContract, IdentifiableContract, Preimage};
-use fedimint_ln_common::{LightningInput, LightningOutput};
+use fedimint_ln_common::{outgoing_contract_max_delay, LightningInput, LightningOutput};
+use fedimint_mint_client::InsufficientBalanceError;
 use futures::future;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
@@ -28,7 +29,7 @@ use crate::db::{FederationIdKey, PreimageAuthentication};
 use crate::gateway_lnrpc::{PayInvoiceRequest, PayInvoiceResponse};
 use crate::lightning::LightningRpcError;
 use crate::state_machine::GatewayClientModule;
-use crate::{GatewayState, RoutingFees};
+use crate::RoutingFees;
 
 #[cfg_attr(doc, aquamarine::aquamarine)]
 /// State machine that executes the Lightning payment on behalf of
@@ -47,7 +48,7 @@ use crate::{GatewayState, RoutingFees};
 ///    WaitForSwapPreimage -- wait for preimge failed --> Canceled
 ///    ClaimOutgoingContract -- claim tx submission --> Preimage
 ///    CancelContract -- cancel tx submission successful --> Canceled
-///    CancelContract -- cancel tx submission unsuccessful --> Failed
+///    CancelContract -- cancel tx submission unsuccessful --> RefundFailed
 /// ```
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Decodable, Encodable, Serialize, Deserialize)]
 pub enum GatewayPayStates {
@@ -62,10 +63,89 @@ pub enum GatewayPayStates {
     },
     WaitForSwapPreimage(Box<GatewayPayWaitForSwapPreimage>),
     ClaimOutgoingContract(Box<GatewayPayClaimOutgoingContract>),
+    /// Superseded by [`GatewayPayStates::RefundFailed`], only kept to decode
+    /// states persisted by older gateways
     Failed {
         error: OutgoingPaymentError,
         error_message: String,
     },
+    RefundFailed {
+        error: OutgoingPaymentError,
+        error_message: String,
+        refund_failure: RefundFailureKind,
+    },
+}
+
+impl GatewayPayStates {
+    /// Id of the outgoing contract being paid, if the state still knows it
+    pub fn contract_id(&self) -> Option<ContractId> {
+        match self {
+            GatewayPayStates::PayInvoice(pay_invoice) => {
+                Some(pay_invoice.pay_invoice_payload.contract_id)
+            }
+            GatewayPayStates::CancelContract(cancel) => {
+                Some(cancel.contract.contract.contract_id())
+            }
+            GatewayPayStates::Preimage(..) => None,
+            GatewayPayStates::OfferDoesNotExist(contract_id)
+            | GatewayPayStates::Canceled { contract_id, .. } => Some(*contract_id),
+            GatewayPayStates::WaitForSwapPreimage(wait) => {
+                Some(wait.contract.contract.contract_id())
+            }
+            GatewayPayStates::ClaimOutgoingContract(claim) => {
+                Some(claim.contract.contract.contract_id())
+            }
+            GatewayPayStates::Failed { error, .. }
+            | GatewayPayStates::RefundFailed { error, .. } => Some(error.contract_id),
+        }
+    }
+}
+
+impl GatewayPayStates {
+    /// Name of the state, used as the `state` field of the state machine logs
+    pub fn name(&self) -> &'static str {
+        match self {
+            GatewayPayStates::PayInvoice(_) => "PayInvoice",
+            GatewayPayStates::CancelContract(_) => "CancelContract",
+            GatewayPayStates::Preimage(..) => "Preimage",
+            GatewayPayStates::OfferDoesNotExist(_) => "OfferDoesNotExist",
+            GatewayPayStates::Canceled { .. } => "Canceled",
+            GatewayPayStates::WaitForSwapPreimage(_) => "WaitForSwapPreimage",
+            GatewayPayStates::ClaimOutgoingContract(_) => "ClaimOutgoingContract",
+            GatewayPayStates::Failed { .. } => "Failed",
+            GatewayPayStates::RefundFailed { .. } => "RefundFailed",
+        }
+    }
+}
+
+impl Display for GatewayPayStates {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Why the gateway could not refund the user by canceling the outgoing
+/// contract
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Decodable, Encodable, Serialize, Deserialize)]
+pub enum RefundFailureKind {
+    /// The gateway's balance does not cover the fees of the refund transaction
+    InsufficientGatewayFunds,
+    /// The refund transaction could not be built or the federation rejected it
+    SubmissionRejected,
+    /// Recorded by an older gateway that did not keep track of the reason, see
+    /// [`GatewayPayStates::Failed`]
+    Unknown,
+}
+
+impl RefundFailureKind {
+    /// Classifies an error returned while funding the refund transaction
+    pub fn from_fund_output_error(error: &anyhow::Error) -> Self {
+        if error.chain().any(|e| e.is::<InsufficientBalanceError>()) {
+            RefundFailureKind::InsufficientGatewayFunds
+        } else {
+            RefundFailureKind::SubmissionRejected
+        }
+    }
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Decodable, Encodable, Serialize, Deserialize)]
@@ -87,24 +167,34 @@ impl State for GatewayPayStateMachine {
         context: &Self::ModuleContext,
         global_context: &DynGlobalClientContext,
     ) -> Vec<fedimint_client::sm::StateTransition<Self>> {
+        let state = self.state.name();
         match &self.state {
-            GatewayPayStates::PayInvoice(gateway_pay_invoice) => {
-                gateway_pay_invoice.transitions(global_context.clone(), context, &self.common)
-            }
+            GatewayPayStates::PayInvoice(gateway_pay_invoice) => gateway_pay_invoice.transitions(
+                global_context.clone(),
+                context,
+                &self.common,
+                state,
+            ),
             GatewayPayStates::WaitForSwapPreimage(gateway_pay_wait_for_swap_preimage) => {
-                gateway_pay_wait_for_swap_preimage.transitions(context.clone(), self.common.clone())
+                gateway_pay_wait_for_swap_preimage.transitions(
+                    context.clone(),
+                    self.common.clone(),
+                    state,
+                )
             }
             GatewayPayStates::ClaimOutgoingContract(gateway_pay_claim_outgoing_contract) => {
                 gateway_pay_claim_outgoing_contract.transitions(
                     global_context.clone(),
                     context.clone(),
                     self.common.clone(),
+                    state,
                 )
             }
             GatewayPayStates::CancelContract(gateway_pay_cancel) => gateway_pay_cancel.transitions(
                 global_context.clone(),
                 context.clone(),
                 self.common.clone(),
+                state,
             ),
             _ => {
                 vec![]
@@ -137,6 +227,8 @@ pub enum OutgoingContractError {
     MissingContractData,
     #[error("The invoice is expired. Expiry happened at timestamp: {0}")]
     InvoiceExpired(u64),
+    #[error("The invoice amount plus the gateway fee overflows")]
+    AmountOverflow,
 }
 
 #[derive(
@@ -155,6 +247,8 @@ pub enum OutgoingPaymentErrorType {
     InvoiceAlreadyPaid,
     #[error("No federation configuration")]
     InvalidFederationConfiguration,
+    #[error("The gateway is shutting down")]
+    GatewayShuttingDown,
 }
 
 #[derive(
@@ -183,6 +277,7 @@ impl GatewayPayInvoice {
         global_context: DynGlobalClientContext,
         context: &GatewayClientContext,
         common: &GatewayPayCommon,
+        state: &'static str,
     ) -> Vec<StateTransition<GatewayPayStateMachine>> {
         let payload = self.pay_invoice_payload.clone();
         vec![StateTransition::new(
@@ -191,6 +286,7 @@ impl GatewayPayInvoice {
                 payload,
                 context.clone(),
                 common.clone(),
+                state,
             ),
             |_dbtx, result, _old_state| Box::pin(futures::future::ready(result)),
         )]
@@ -201,6 +297,7 @@ impl GatewayPayInvoice {
         pay_invoice_payload: PayInvoicePayload,
         context: GatewayClientContext,
         common: GatewayPayCommon,
+        state: &'static str,
     ) -> GatewayPayStateMachine {
         match Self::await_get_payment_parameters(
             global_context,
@@ -208,6 +305,7 @@ impl GatewayPayInvoice {
             pay_invoice_payload.contract_id,
             pay_invoice_payload.payment_data.clone(),
             pay_invoice_payload.federation_id,
+            state,
         )
         .await
         {
@@ -218,11 +316,17 @@ impl GatewayPayInvoice {
                     payment_parameters.clone(),
                     common.clone(),
                     pay_invoice_payload.clone(),
+                    state,
                 )
                 .await
             }
             Err(e) => {
-                warn!("Failed to get payment parameters: {e:?}");
+                warn!(
+                    contract_id = %e.contract_id,
+                    federation_id = %pay_invoice_payload.federation_id,
+                    state,
+                    "Failed to get payment parameters: {e:?}"
+                );
                 match e.contract.clone() {
                     Some(contract) => GatewayPayStateMachine {
                         common,
@@ -245,10 +349,28 @@ impl GatewayPayInvoice {
         payment_parameters: PaymentParameters,
         common: GatewayPayCommon,
         payload: PayInvoicePayload,
+        state: &'static str,
     ) -> GatewayPayStateMachine {
-        debug!("Buying preimage contract {contract:?}");
+        let contract_id = contract.contract.contract_id();
+        let amount_msat = contract.amount.msats;
+        let federation_id = payload.federation_id;
+        debug!(
+            %contract_id,
+            amount_msat,
+            %federation_id,
+            state,
+            "Buying preimage"
+        );
         // Verify that this client is authorized to receive the preimage.
-        if let Err(err) = Self::verify_preimage_authentication(
+        if !context.require_preimage_auth {
+            warn!(
+                %contract_id,
+                amount_msat,
+                %federation_id,
+                state,
+                "Preimage authentication is disabled, skipping it"
+            );
+        } else if let Err(err) = Self::verify_preimage_authentication(
             &context,
             payload.payment_data.payment_hash(),
             payload.preimage_auth,
@@ -256,7 +378,13 @@ impl GatewayPayInvoice {
         )
         .await
         {
-            warn!("Preimage authentication failed: {err} for contract {contract:?}");
+            warn!(
+                %contract_id,
+                amount_msat,
+                %federation_id,
+                state,
+                "Preimage authentication failed: {err}"
+            );
             return GatewayPayStateMachine {
                 common,
                 state: GatewayPayStates::CancelContract(Box::new(GatewayPayCancelContract {
@@ -266,6 +394,30 @@ impl GatewayPayInvoice {
             };
         }
 
+        // This is the last point at which the contract can be canceled safely, so
+        // when shutting down refund the user now instead of making them wait for
+        // the timelock to expire
+        if context.gateway.is_shutting_down() {
+            info!(
+                %contract_id,
+                amount_msat,
+                %federation_id,
+                state,
+                "Gateway is shutting down, canceling outgoing contract"
+            );
+            return GatewayPayStateMachine {
+                common,
+                state: GatewayPayStates::CancelContract(Box::new(GatewayPayCancelContract {
+                    contract: contract.clone(),
+                    error: OutgoingPaymentError {
+                        contract_id,
+                        contract: Some(contract),
+                        error_type: OutgoingPaymentErrorType::GatewayShuttingDown,
+                    },
+                })),
+            };
+        }
+
         if let Some(client) =
             Self::check_swap_to_federation(context.clone(), payment_parameters.payment_data.clone())
                 .await
@@ -277,6 +429,8 @@ impl GatewayPayInvoice {
                         payment_parameters.payment_data.clone(),
                         contract.clone(),
                         common.clone(),
+                        federation_id,
+                        state,
                     )
                 })
                 .await
@@ -286,6 +440,7 @@ impl GatewayPayInvoice {
                 payment_parameters,
                 contract.clone(),
                 common.clone(),
+                state,
             )
             .await
         }
@@ -297,8 +452,14 @@ impl GatewayPayInvoice {
         contract_id: ContractId,
         payment_data: PaymentData,
         federation_id: FederationId,
+        state: &'static str,
     ) -> Result<(OutgoingContractAccount, PaymentParameters), OutgoingPaymentError> {
-        debug!("Await payment parameters for outgoing contract {contract_id:?}");
+        debug!(
+            %contract_id,
+            %federation_id,
+            state,
+            "Await payment parameters for outgoing contract"
+        );
         let account = global_context
             .module_api()
             .wait_contract(contract_id)
@@ -327,7 +488,14 @@ impl GatewayPayInvoice {
                     },
                 })?;
 
-            debug!("Consensus block count: {consensus_block_count:?} for outgoing contract {contract_id:?}");
+            debug!(
+                %contract_id,
+                amount_msat = outgoing_contract_account.amount.msats,
+                %federation_id,
+                state,
+                ?consensus_block_count,
+                "Fetched consensus block count for outgoing contract"
+            );
             if consensus_block_count.is_none() {
                 return Err(OutgoingPaymentError {
                     contract_id,
@@ -358,18 +526,36 @@ impl GatewayPayInvoice {
                 routing_fees,
             )
             .map_err(|e| {
-                warn!("Invalid outgoing contract: {e:?}");
+                warn!(
+                    %contract_id,
+                    amount_msat = outgoing_contract_account.amount.msats,
+                    %federation_id,
+                    state,
+                    "Invalid outgoing contract: {e:?}"
+                );
                 OutgoingPaymentError {
                     contract_id,
                     contract: Some(outgoing_contract_account.clone()),
                     error_type: OutgoingPaymentErrorType::InvalidOutgoingContract { error: e },
                 }
             })?;
-            debug!("Got payment parameters: {payment_parameters:?} for contract {contract_id:?}");
+            debug!(
+                %contract_id,
+                amount_msat = outgoing_contract_account.amount.msats,
+                %federation_id,
+                state,
+                ?payment_parameters,
+                "Got payment parameters"
+            );
             return Ok((outgoing_contract_account, payment_parameters));
         }
 
-        error!("Contract {contract_id:?} is not an outgoing contract");
+        error!(
+            %contract_id,
+            %federation_id,
+            state,
+            "Contract is not an outgoing contract"
+        );
         Err(OutgoingPaymentError {
             contract_id,
             contract: None,
@@ -382,21 +568,33 @@ impl GatewayPayInvoice {
         buy_preimage: PaymentParameters,
         contract: OutgoingContractAccount,
         common: GatewayPayCommon,
+        state: &'static str,
     ) -> GatewayPayStateMachine {
-        debug!("Buying preimage over lightning for contract {contract:?}");
+        let contract_id = contract.contract.contract_id();
+        let amount_msat = contract.amount.msats;
+        let federation_id = context.federation_id;
+        debug!(
+            %contract_id,
+            amount_msat,
+            %federation_id,
+            state,
+            "Buying preimage over lightning"
+        );
         let payment_data = buy_preimage.payment_data.clone();
 
         let max_delay = buy_preimage.max_delay;
-        let max_fee = buy_preimage.max_send_amount
-            - buy_preimage
-                .payment_data
-                .amount()
-                .expect("We already checked that an amount was supplied");
+        let max_fee = buy_preimage.max_fee;
 
         let lightning_context = match context.gateway.get_lightning_context().await {
             Ok(lightning_context) => lightning_context,
             Err(error) => {
-                return Self::gateway_pay_cancel_contract(error, contract, common);
+                return Self::gateway_pay_cancel_contract(
+                    error,
+                    contract,
+                    common,
+                    federation_id,
+                    state,
+                );
             }
         };
 
@@ -422,7 +620,13 @@ impl GatewayPayInvoice {
 
         match payment_result {
             Ok(PayInvoiceResponse { preimage, .. }) => {
-                debug!("Preimage received for contract {contract:?}");
+                debug!(
+                    %contract_id,
+                    amount_msat,
+                    %federation_id,
+                    state,
+                    "Preimage received"
+                );
                 let slice: [u8; 32] = preimage.try_into().expect("Failed to parse preimage");
                 GatewayPayStateMachine {
                     common,
@@ -434,7 +638,9 @@ impl GatewayPayInvoice {
                     )),
                 }
             }
-            Err(error) => Self::gateway_pay_cancel_contract(error, contract, common),
+            Err(error) => {
+                Self::gateway_pay_cancel_contract(error, contract, common, federation_id, state)
+            }
         }
     }
 
@@ -442,10 +648,19 @@ impl GatewayPayInvoice {
         error: LightningRpcError,
         contract: OutgoingContractAccount,
         common: GatewayPayCommon,
+        federation_id: FederationId,
+        state: &'static str,
     ) -> GatewayPayStateMachine {
-        warn!("Failed to buy preimage with {error} for contract {contract:?}");
+        let contract_id = contract.contract.contract_id();
+        warn!(
+            %contract_id,
+            amount_msat = contract.amount.msats,
+            %federation_id,
+            state,
+            "Failed to buy preimage: {error}"
+        );
         let outgoing_error = OutgoingPaymentError {
-            contract_id: contract.contract.contract_id(),
+            contract_id,
             contract: Some(contract.clone()),
             error_type: OutgoingPaymentErrorType::LightningPayError {
                 lightning_error: error,
@@ -465,8 +680,20 @@ impl GatewayPayInvoice {
         payment_data: PaymentData,
         contract: OutgoingContractAccount,
         common: GatewayPayCommon,
+        federation_id: FederationId,
+        state: &'static str,
     ) -> GatewayPayStateMachine {
-        debug!("Buying preimage via direct swap for contract {contract:?}");
+        let contract_id = contract.contract.contract_id();
+        let amount_msat = contract.amount.msats;
+        let swap_federation_id = client.federation_id();
+        debug!(
+            %contract_id,
+            amount_msat,
+            %federation_id,
+            %swap_federation_id,
+            state,
+            "Buying preimage via direct swap"
+        );
         match payment_data.try_into() {
             Ok(swap_params) => match client
                 .get_first_module::<GatewayClientModule>()
@@ -474,22 +701,36 @@ impl GatewayPayInvoice {
                 .await
             {
                 Ok(operation_id) => {
-                    debug!("Direct swap initiated for contract {contract:?}");
+                    debug!(
+                        %contract_id,
+                        amount_msat,
+                        %federation_id,
+                        %swap_federation_id,
+                        state,
+                        "Direct swap initiated"
+                    );
                     GatewayPayStateMachine {
                         common,
                         state: GatewayPayStates::WaitForSwapPreimage(Box::new(
                             GatewayPayWaitForSwapPreimage {
                                 contract,
-                                federation_id: client.federation_id(),
+                                federation_id: swap_federation_id,
                                 operation_id,
                             },
                         )),
                     }
                 }
                 Err(e) => {
-                    info!("Failed to initiate direct swap: {e:?} for contract {contract:?}");
+                    info!(
+                        %contract_id,
+                        amount_msat,
+                        %federation_id,
+                        %swap_federation_id,
+                        state,
+                        "Failed to initiate direct swap: {e:?}"
+                    );
                     let outgoing_payment_error = OutgoingPaymentError {
-                        contract_id: contract.contract.contract_id(),
+                        contract_id,
                         contract: Some(contract.clone()),
                         error_type: OutgoingPaymentErrorType::SwapFailed {
                             swap_error: format!("Failed to initiate direct swap: {e}"),
@@ -507,9 +748,16 @@ impl GatewayPayInvoice {
                 }
             },
             Err(e) => {
-                info!("Failed to initiate direct swap: {e:?} for contract {contract:?}");
+                info!(
+                    %contract_id,
+                    amount_msat,
+                    %federation_id,
+                    %swap_federation_id,
+                    state,
+                    "Failed to initiate direct swap: {e:?}"
+                );
                 let outgoing_payment_error = OutgoingPaymentError {
-                    contract_id: contract.contract.contract_id(),
+                    contract_id,
                     contract: Some(contract.clone()),
                     error_type: OutgoingPaymentErrorType::SwapFailed {
                         swap_error: format!("Failed to initiate direct swap: {e}"),
@@ -584,40 +832,42 @@ impl GatewayPayInvoice {
             return Err(OutgoingContractError::NotOurKey);
         }
 
-        let payment_amount = payment_data
-            .amount()
-            .ok_or(OutgoingContractError::InvoiceMissingAmount)?;
+        let payment_amount = payment_amount(payment_data)?;
 
         let gateway_fee = routing_fees.to_amount(&payment_amount);
-        let necessary_contract_amount = payment_amount + gateway_fee;
+        let necessary_contract_amount = payment_amount
+            .checked_add(gateway_fee)
+            .ok_or(OutgoingContractError::AmountOverflow)?;
         if account.amount < necessary_contract_amount {
             return Err(OutgoingContractError::Underfunded(
                 necessary_contract_amount,
                 account.amount,
             ));
         }
+        let max_fee = account.amount.checked_sub(payment_amount).ok_or(
+            OutgoingContractError::Underfunded(necessary_contract_amount, account.amount),
+        )?;
 
-        let max_delay = u64::from(account.contract.timelock)
-            .checked_sub(consensus_block_count.saturating_sub(1))
-            .and_then(|delta| delta.checked_sub(timelock_delta));
+        let max_delay = outgoing_contract_max_delay(
+            u64::from(account.contract.timelock),
+            consensus_block_count,
+            timelock_delta,
+        );
         if max_delay.is_none() {
             return Err(OutgoingContractError::TimeoutTooClose);
         }
 
-        if payment_data.is_expired() {
-            return Err(OutgoingContractError::InvoiceExpired(
-                payment_data.expiry_timestamp(),
-            ));
-        }
+        check_not_expired(payment_data)?;
 
         Ok(PaymentParameters {
             max_delay: max_delay.unwrap(),
             max_send_amount: account.amount,
+            max_fee,
             payment_data: payment_data.clone(),
         })
     }
 
-    // Checks if the invoice route hint last hop has source node id matching this
+    // Checks if an invoice route hint last hop has source node id matching this
     // gateways node pubkey and if the short channel id matches one assigned by
     // this gateway to a connected federation. In this case, the gateway can
     // avoid paying the invoice over the lightning network and instead perform a
@@ -626,34 +876,50 @@ impl GatewayPayInvoice {
         context: GatewayClientContext,
         payment_data: PaymentData,
     ) -> Option<Spanned<ClientHandleArc>> {
-        let rhints = payment_data.route_hints();
-        match rhints.first().and_then(|rh| rh.0.last()) {
-            None => None,
-            Some(hop) => match context.gateway.state.read().await.clone() {
-                GatewayState::Running { lightning_context } => {
-                    if hop.src_node_id != lightning_context.lightning_public_key {
-                        return None;
-                    }
+        let amount = payment_data.amount()?;
+        let candidates = context.gateway.direct_swap_candidates(&payment_data).await;
+        let federation_id = select_swap_candidate(&candidates, amount)?;
+        debug!(
+            %federation_id,
+            ?candidates,
+            "Selected federation for direct swap"
+        );
 
-                    let scid_to_feds = context.gateway.scid_to_federation.read().await;
-                    match scid_to_feds.get(&hop.short_channel_id).copied() {
-                        None => None,
-                        Some(federation_id) => {
-                            let clients = context.gateway.clients.read().await;
-                            clients.get(&federation_id).cloned()
-                        }
-                    }
-                }
-                _ => None,
-            },
-        }
+        context
+            .gateway
+            .clients
+            .read()
+            .await
+            .get(&federation_id)
+            .cloned()
     }
 }
 
+/// A connected federation an outgoing payment can be swapped into directly
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SwapCandidate {
+    pub federation_id: FederationId,
+    /// The gateway's ecash balance in the federation
+    pub available_liquidity: Amount,
+}
+
+/// Picks the first candidate with enough liquidity to fund a swap of `amount`.
+/// If none has enough, the first candidate is picked anyway so the payment
+/// fails in the swap like it would when paying over lightning.
+fn select_swap_candidate(candidates: &[SwapCandidate], amount: Amount) -> Option<FederationId> {
+    candidates
+        .iter()
+        .find(|candidate| amount <= candidate.available_liquidity)
+        .or_else(|| candidates.first())
+        .map(|candidate| candidate.federation_id)
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Decodable, Encodable, Serialize, Deserialize)]
 struct PaymentParameters {
     max_delay: u64,
     max_send_amount: Amount,
+    /// Part of the contract amount left for routing fees
+    max_fee: Amount,
     payment_data: PaymentData,
 }
 
@@ -669,6 +935,7 @@ impl GatewayPayClaimOutgoingContract {
         global_context: DynGlobalClientContext,
         context: GatewayClientContext,
         common: GatewayPayCommon,
+        state: &'static str,
     ) -> Vec<StateTransition<GatewayPayStateMachine>> {
         let contract = self.contract.clone();
         let preimage = self.preimage.clone();
@@ -682,6 +949,7 @@ impl GatewayPayClaimOutgoingContract {
                     common.clone(),
                     contract.clone(),
                     preimage.clone(),
+                    state,
                 ))
             },
         )]
@@ -694,8 +962,18 @@ impl GatewayPayClaimOutgoingContract {
         common: GatewayPayCommon,
         contract: OutgoingContractAccount,
         preimage: Preimage,
+        state: &'static str,
     ) -> GatewayPayStateMachine {
-        debug!("Claiming outgoing contract {contract:?}");
+        let contract_id = contract.contract.contract_id();
+        let amount_msat = contract.amount.msats;
+        let federation_id = context.federation_id;
+        debug!(
+            %contract_id,
+            amount_msat,
+            %federation_id,
+            state,
+            "Claiming outgoing contract"
+        );
         let claim_input = contract.claim(preimage.clone());
         let client_input = ClientInput::<LightningInput, GatewayClientStateMachines> {
             input: claim_input,
@@ -705,7 +983,14 @@ impl GatewayPayClaimOutgoingContract {
         };
 
         let out_points = global_context.claim_input(dbtx, client_input).await.1;
-        debug!("Claimed outgoing contract {contract:?} with out points {out_points:?}");
+        debug!(
+            %contract_id,
+            amount_msat,
+            %federation_id,
+            state,
+            ?out_points,
+            "Claimed outgoing contract"
+        );
         GatewayPayStateMachine {
             common,
             state: GatewayPayStates::Preimage(out_points, preimage),
@@ -725,17 +1010,31 @@ impl GatewayPayWaitForSwapPreimage {
         &self,
         context: GatewayClientContext,
         common: GatewayPayCommon,
+        state: &'static str,
     ) -> Vec<StateTransition<GatewayPayStateMachine>> {
-        let federation_id = self.federation_id;
+        let federation_id = context.federation_id;
+        let swap_federation_id = self.federation_id;
         let operation_id = self.operation_id;
         let contract = self.contract.clone();
         vec![StateTransition::new(
-            Self::await_preimage(context, federation_id, operation_id, contract.clone()),
-            move |_dbtx, result, _old_state| {
+            Self::await_preimage(
+                context,
+                swap_federation_id,
+                operation_id,
+                contract.clone(),
+                state,
+            ),
+            move |_dbtx, result, old_state| {
                 let common = common.clone();
                 let contract = contract.clone();
-                Box::pin(async {
-                    Self::transition_claim_outgoing_contract(common, result, contract)
+                Box::pin(async move {
+                    Self::transition_claim_outgoing_contract(
+                        common,
+                        result,
+                        contract,
+                        federation_id,
+                        old_state.state.name(),
+                    )
                 })
             },
         )]
@@ -743,20 +1042,31 @@ impl GatewayPayWaitForSwapPreimage {
 
     async fn await_preimage(
         context: GatewayClientContext,
-        federation_id: FederationId,
+        swap_federation_id: FederationId,
         operation_id: OperationId,
         contract: OutgoingContractAccount,
+        state: &'static str,
     ) -> Result<Preimage, OutgoingPaymentError> {
-        debug!("Waiting preimage for contract {contract:?}");
+        let contract_id = contract.contract.contract_id();
+        let amount_msat = contract.amount.msats;
+        let federation_id = context.federation_id;
+        debug!(
+            %contract_id,
+            amount_msat,
+            %federation_id,
+            %swap_federation_id,
+            state,
+            "Waiting preimage"
+        );
         let client = context
             .gateway
             .clients
             .read()
             .await
-            .get(&federation_id)
+            .get(&swap_federation_id)
             .cloned()
             .ok_or(OutgoingPaymentError {
-                contract_id: contract.contract.contract_id(),
+                contract_id,
                 contract: Some(contract.clone()),
                 error_type: OutgoingPaymentErrorType::SwapFailed {
                     swap_error: "Federation client not found".to_string(),
@@ -770,9 +1080,12 @@ impl GatewayPayWaitForSwapPreimage {
                 .gateway_subscribe_ln_receive(operation_id)
                 .await
                 .map_err(|e| {
-                    let contract_id = contract.contract.contract_id();
                     warn!(
-                        ?contract_id,
+                        %contract_id,
+                        amount_msat,
+                        %federation_id,
+                        %swap_federation_id,
+                        state,
                         "Failed to subscribe to ln receive of direct swap: {e:?}"
                     );
                     OutgoingPaymentError {
@@ -788,21 +1101,49 @@ impl GatewayPayWaitForSwapPreimage {
                 .into_stream();
 
             loop {
-                debug!("Waiting next state of preimage buy for contract {contract:?}");
+                debug!(
+                    %contract_id,
+                    amount_msat,
+                    %federation_id,
+                    %swap_federation_id,
+                    state,
+                    "Waiting next state of preimage buy"
+                );
                 if let Some(state) = stream.next().await {
                     match state {
                         GatewayExtReceiveStates::Funding => {
-                            debug!(?contract, "Funding");
+                            debug!(
+                                %contract_id,
+                                amount_msat,
+                                %federation_id,
+                                %swap_federation_id,
+                                state,
+                                "Funding"
+                            );
                             continue;
                         }
                         GatewayExtReceiveStates::Preimage(preimage) => {
-                            debug!(?contract, "Received preimage");
+                            debug!(
+                                %contract_id,
+                                amount_msat,
+                                %federation_id,
+                                %swap_federation_id,
+                                state,
+                                "Received preimage"
+                            );
                             return Ok(preimage);
                         }
                         other => {
-                            warn!(?contract, "Got state {other:?}");
+                            warn!(
+                                %contract_id,
+                                amount_msat,
+                                %federation_id,
+                                %swap_federation_id,
+                                state,
+                                "Got state {other:?}"
+                            );
                             return Err(OutgoingPaymentError {
-                                contract_id: contract.contract.contract_id(),
+                                contract_id,
                                 contract: Some(contract),
                                 error_type: OutgoingPaymentErrorType::SwapFailed {
                                     swap_error: "Failed to receive preimage".to_string(),
@@ -821,21 +1162,43 @@ impl GatewayPayWaitForSwapPreimage {
         common: GatewayPayCommon,
         result: Result<Preimage, OutgoingPaymentError>,
         contract: OutgoingContractAccount,
+        federation_id: FederationId,
+        state: &'static str,
     ) -> GatewayPayStateMachine {
+        let contract_id = contract.contract.contract_id();
+        let amount_msat = contract.amount.msats;
         match result {
-            Ok(preimage) => GatewayPayStateMachine {
-                common,
-                state: GatewayPayStates::ClaimOutgoingContract(Box::new(
-                    GatewayPayClaimOutgoingContract { contract, preimage },
-                )),
-            },
-            Err(e) => GatewayPayStateMachine {
-                common,
-                state: GatewayPayStates::CancelContract(Box::new(GatewayPayCancelContract {
-                    contract,
-                    error: e,
-                })),
-            },
+            Ok(preimage) => {
+                debug!(
+                    %contract_id,
+                    amount_msat,
+                    %federation_id,
+                    state,
+                    "Claiming outgoing contract with swap preimage"
+                );
+                GatewayPayStateMachine {
+                    common,
+                    state: GatewayPayStates::ClaimOutgoingContract(Box::new(
+                        GatewayPayClaimOutgoingContract { contract, preimage },
+                    )),
+                }
+            }
+            Err(e) => {
+                warn!(
+                    %contract_id,
+                    amount_msat,
+                    %federation_id,
+                    state,
+                    "Direct swap failed, canceling outgoing contract: {e}"
+                );
+                GatewayPayStateMachine {
+                    common,
+                    state: GatewayPayStates::CancelContract(Box::new(GatewayPayCancelContract {
+                        contract,
+                        error: e,
+                    })),
+                }
+            }
         }
     }
 }
@@ -852,6 +1215,7 @@ impl GatewayPayCancelContract {
         global_context: DynGlobalClientContext,
         context: GatewayClientContext,
         common: GatewayPayCommon,
+        state: &'static str,
     ) -> Vec<StateTransition<GatewayPayStateMachine>> {
         let contract = self.contract.clone();
         let error = self.error.clone();
@@ -865,6 +1229,7 @@ impl GatewayPayCancelContract {
                     context.clone(),
                     common.clone(),
                     error.clone(),
+                    state,
                 ))
             },
         )]
@@ -877,16 +1242,23 @@ impl GatewayPayCancelContract {
         context: GatewayClientContext,
         common: GatewayPayCommon,
         error: OutgoingPaymentError,
+        state: &'static str,
     ) -> GatewayPayStateMachine {
-        info!("Canceling outgoing contract {contract:?}");
+        let contract_id = contract.contract.contract_id();
+        let amount_msat = contract.amount.msats;
+        let federation_id = context.federation_id;
+        info!(
+            %contract_id,
+            amount_msat,
+            %federation_id,
+            state,
+            "Canceling outgoing contract"
+        );
         let cancel_signature = context.secp.sign_schnorr(
             &contract.contract.cancellation_message().into(),
             &context.redeem_key,
         );
-        let cancel_output = LightningOutput::new_v0_cancel_outgoing(
-            contract.contract.contract_id(),
-            cancel_signature,
-        );
+        let cancel_output = LightningOutput::new_v0_cancel_outgoing(contract_id, cancel_signature);
         let client_output = ClientOutput::<LightningOutput, GatewayClientStateMachines> {
             output: cancel_output,
             amount: Amount::ZERO,
@@ -895,28 +1267,381 @@ impl GatewayPayCancelContract {
 
         match global_context.fund_output(dbtx, client_output).await {
             Ok((txid, _)) => {
-                info!("Canceled outgoing contract {contract:?} with txid {txid:?}");
+                info!(
+                    %contract_id,
+                    amount_msat,
+                    %federation_id,
+                    state,
+                    %txid,
+                    "Canceled outgoing contract"
+                );
                 GatewayPayStateMachine {
                     common,
                     state: GatewayPayStates::Canceled {
                         txid,
-                        contract_id: contract.contract.contract_id(),
+                        contract_id,
                         error,
                     },
                 }
             }
             Err(e) => {
-                warn!("Failed to cancel outgoing contract {contract:?}: {e:?}");
+                warn!(
+                    %contract_id,
+                    amount_msat,
+                    %federation_id,
+                    state,
+                    "Failed to cancel outgoing contract: {e:?}"
+                );
                 GatewayPayStateMachine {
                     common,
-                    state: GatewayPayStates::Failed {
+                    state: GatewayPayStates::RefundFailed {
                         error,
                         error_message: format!(
                             "Failed to submit refund transaction to federation {e:?}"
                         ),
+                        refund_failure: RefundFailureKind::from_fund_output_error(&e),
                     },
                 }
             }
         }
     }
 }
+
+/// Checks the parts of an outgoing payment that do not depend on the funded
+/// contract, i.e. that the invoice has an amount and is not expired. Returns
+/// the amount to be paid.
+pub(crate) fn validate_payment_data(
+    payment_data: &PaymentData,
+) -> Result<Amount, OutgoingContractError> {
+    let payment_amount = payment_amount(payment_data)?;
+    check_not_expired(payment_data)?;
+    Ok(payment_amount)
+}
+
+fn payment_amount(payment_data: &PaymentData) -> Result<Amount, OutgoingContractError> {
+    payment_data
+        .amount()
+        .ok_or(OutgoingContractError::InvoiceMissingAmount)
+}
+
+fn check_not_expired(payment_data: &PaymentData) -> Result<(), OutgoingContractError> {
+    if payment_data.is_expired() {
+        return Err(OutgoingContractError::InvoiceExpired(
+            payment_data.expiry_timestamp(),
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+    use bitcoin_hashes::{sha256, Hash};
+    use fedimint_core::config::FederationId;
+    use fedimint_core::core::OperationId;
+    use fedimint_core::time::duration_since_epoch;
+    use fedimint_core::{secp256k1, Amount};
+    use fedimint_ln_client::pay::{PayInvoicePayload, PaymentData};
+    use fedimint_ln_common::contracts::outgoing::{OutgoingContract, OutgoingContractAccount};
+    use fedimint_ln_common::contracts::{IdentifiableContract, Preimage};
+    use fedimint_ln_common::PrunedInvoice;
+    use fedimint_mint_client::InsufficientBalanceError;
+    use lightning_invoice::RoutingFees;
+
+    use super::{
+        select_swap_candidate, validate_payment_data, GatewayPayCommon, GatewayPayInvoice,
+        GatewayPayStates, GatewayPayWaitForSwapPreimage, OutgoingContractError,
+        OutgoingPaymentError, OutgoingPaymentErrorType, RefundFailureKind, SwapCandidate,
+    };
+    use crate::lightning::LightningRpcError;
+
+    fn pruned_invoice(expiry_timestamp: u64) -> PaymentData {
+        let secp = secp256k1::Secp256k1::new();
+        PaymentData::PrunedInvoice(PrunedInvoice {
+            amount: Amount::from_msats(1000),
+            destination: secp256k1::PublicKey::from_secret_key(
+                &secp,
+                &secp256k1::SecretKey::from_slice(&[1; 32]).expect("Valid secret key"),
+            ),
+            destination_features: vec![],
+            payment_hash: sha256::Hash::hash(&[0]),
+            payment_secret: [0; 32],
+            route_hints: vec![],
+            min_final_cltv_delta: 18,
+            expiry_timestamp,
+        })
+    }
+
+    #[test]
+    fn swap_prefers_federation_with_sufficient_liquidity() {
+        let first = FederationId::dummy();
+        let second = FederationId(sha256::Hash::hash(&[1]));
+        let candidates = [
+            SwapCandidate {
+                federation_id: first,
+                available_liquidity: Amount::from_sats(5),
+            },
+            SwapCandidate {
+                federation_id: second,
+                available_liquidity: Amount::from_sats(50),
+            },
+        ];
+
+        assert_eq!(
+            select_swap_candidate(&candidates, Amount::from_sats(5)),
+            Some(first)
+        );
+        assert_eq!(
+            select_swap_candidate(&candidates, Amount::from_sats(10)),
+            Some(second)
+        );
+        assert_eq!(
+            select_swap_candidate(&candidates, Amount::from_sats(100)),
+            Some(first)
+        );
+        assert_eq!(select_swap_candidate(&[], Amount::from_sats(1)), None);
+    }
+
+    #[test]
+    fn pre_validation_rejects_expired_invoice() {
+        let now = duration_since_epoch().as_secs();
+
+        assert_eq!(
+            validate_payment_data(&pruned_invoice(now - 60)),
+            Err(OutgoingContractError::InvoiceExpired(now - 60))
+        );
+        assert_eq!(
+            validate_payment_data(&pruned_invoice(now + 3600)),
+            Ok(Amount::from_msats(1000))
+        );
+    }
+
+    #[test]
+    fn base_fee_is_required_on_top_of_proportional_fee() {
+        let secp = secp256k1::Secp256k1::new();
+        let redeem_key = bitcoin::key::KeyPair::from_secret_key(
+            &secp,
+            &secp256k1::SecretKey::from_slice(&[1; 32]).expect("Valid secret key"),
+        );
+        let payment_data = pruned_invoice(duration_since_epoch().as_secs() + 3600);
+
+        // 1000 msat payment plus the 1% proportional fee, but no base fee
+        let account = OutgoingContractAccount {
+            amount: Amount::from_msats(1010),
+            contract: OutgoingContract {
+                hash: sha256::Hash::hash(&[0]),
+                gateway_key: redeem_key.public_key(),
+                timelock: 100,
+                user_key: redeem_key.public_key(),
+                cancelled: false,
+            },
+        };
+        let validate = |base_msat| {
+            GatewayPayInvoice::validate_outgoing_account(
+                &account,
+                redeem_key,
+                10,
+                50,
+                &payment_data,
+                RoutingFees {
+                    base_msat,
+                    proportional_millionths: 10_000,
+                },
+            )
+        };
+
+        assert_eq!(validate(0).unwrap().max_fee, Amount::from_msats(10));
+        assert_eq!(
+            validate(5).unwrap_err(),
+            OutgoingContractError::Underfunded(Amount::from_msats(1015), Amount::from_msats(1010))
+        );
+    }
+
+    #[test]
+    fn overflowing_payment_amount_is_rejected() {
+        let secp = secp256k1::Secp256k1::new();
+        let redeem_key = bitcoin::key::KeyPair::from_secret_key(
+            &secp,
+            &secp256k1::SecretKey::from_slice(&[1; 32]).expect("Valid secret key"),
+        );
+        let PaymentData::PrunedInvoice(mut invoice) =
+            pruned_invoice(duration_since_epoch().as_secs() + 3600)
+        else {
+            unreachable!("Helper returns a pruned invoice");
+        };
+        invoice.amount = Amount::from_msats(u64::MAX);
+        let account = OutgoingContractAccount {
+            amount: Amount::from_msats(u64::MAX),
+            contract: OutgoingContract {
+                hash: sha256::Hash::hash(&[0]),
+                gateway_key: redeem_key.public_key(),
+                timelock: 100,
+                user_key: redeem_key.public_key(),
+                cancelled: false,
+            },
+        };
+
+        assert_eq!(
+            GatewayPayInvoice::validate_outgoing_account(
+                &account,
+                redeem_key,
+                10,
+                50,
+                &PaymentData::PrunedInvoice(invoice),
+                RoutingFees {
+                    base_msat: 0,
+                    proportional_millionths: 10_000,
+                },
+            )
+            .unwrap_err(),
+            OutgoingContractError::AmountOverflow
+        );
+    }
+
+    #[test]
+    fn refund_failure_kind_detects_insufficient_funds() {
+        let error = anyhow::Error::new(InsufficientBalanceError {
+            requested_amount: Amount::from_sats(10),
+            total_amount: Amount::ZERO,
+        })
+        .context("Failed to fund refund transaction");
+        assert_eq!(
+            RefundFailureKind::from_fund_output_error(&error),
+            RefundFailureKind::InsufficientGatewayFunds
+        );
+
+        assert_eq!(
+            RefundFailureKind::from_fund_output_error(&anyhow!("Transaction is invalid")),
+            RefundFailureKind::SubmissionRejected
+        );
+    }
+
+    fn outgoing_contract_account() -> OutgoingContractAccount {
+        let secp = secp256k1::Secp256k1::new();
+        let key = secp256k1::PublicKey::from_secret_key(
+            &secp,
+            &secp256k1::SecretKey::from_slice(&[1; 32]).expect("Valid secret key"),
+        );
+        OutgoingContractAccount {
+            amount: Amount::from_msats(1000),
+            contract: OutgoingContract {
+                hash: sha256::Hash::hash(&[0]),
+                gateway_key: key,
+                timelock: 100,
+                user_key: key,
+                cancelled: false,
+            },
+        }
+    }
+
+    fn pay_invoice_state(contract: &OutgoingContractAccount) -> GatewayPayStates {
+        GatewayPayStates::PayInvoice(GatewayPayInvoice {
+            pay_invoice_payload: PayInvoicePayload {
+                federation_id: FederationId::dummy(),
+                contract_id: contract.contract.contract_id(),
+                payment_data: pruned_invoice(u64::MAX),
+                preimage_auth: sha256::Hash::hash(&[1]),
+            },
+        })
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn cancel_path_logs_contract_id() {
+        let contract = outgoing_contract_account();
+        let contract_id = contract.contract.contract_id();
+        let federation_id = FederationId::dummy();
+
+        let state_machine = GatewayPayInvoice::gateway_pay_cancel_contract(
+            LightningRpcError::FailedPayment {
+                failure_reason: "no route".to_string(),
+            },
+            contract.clone(),
+            GatewayPayCommon {
+                operation_id: OperationId([0; 32]),
+            },
+            federation_id,
+            pay_invoice_state(&contract).name(),
+        );
+
+        assert!(matches!(
+            state_machine.state,
+            GatewayPayStates::CancelContract(_)
+        ));
+        assert!(logs_contain(&format!("contract_id={contract_id}")));
+        assert!(logs_contain("amount_msat=1000"));
+        assert!(logs_contain(&format!("federation_id={federation_id}")));
+        assert!(logs_contain("state=\"PayInvoice\""));
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn swap_preimage_path_logs_contract_id() {
+        let contract = outgoing_contract_account();
+        let contract_id = contract.contract.contract_id();
+        let federation_id = FederationId::dummy();
+        let state =
+            GatewayPayStates::WaitForSwapPreimage(Box::new(GatewayPayWaitForSwapPreimage {
+                contract: contract.clone(),
+                federation_id,
+                operation_id: OperationId([0; 32]),
+            }));
+
+        let state_machine = GatewayPayWaitForSwapPreimage::transition_claim_outgoing_contract(
+            GatewayPayCommon {
+                operation_id: OperationId([0; 32]),
+            },
+            Ok(Preimage([0; 32])),
+            contract,
+            federation_id,
+            state.name(),
+        );
+
+        assert!(matches!(
+            state_machine.state,
+            GatewayPayStates::ClaimOutgoingContract(_)
+        ));
+        assert!(logs_contain(
+            "Claiming outgoing contract with swap preimage"
+        ));
+        assert!(logs_contain(&format!("contract_id={contract_id}")));
+        assert!(logs_contain(&format!("federation_id={federation_id}")));
+        assert!(logs_contain("state=\"WaitForSwapPreimage\""));
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn failed_swap_path_logs_contract_id() {
+        let contract = outgoing_contract_account();
+        let contract_id = contract.contract.contract_id();
+        let federation_id = FederationId::dummy();
+
+        let state_machine = GatewayPayWaitForSwapPreimage::transition_claim_outgoing_contract(
+            GatewayPayCommon {
+                operation_id: OperationId([0; 32]),
+            },
+            Err(OutgoingPaymentError {
+                error_type: OutgoingPaymentErrorType::SwapFailed {
+                    swap_error: "Failed to receive preimage".to_string(),
+                },
+                contract_id,
+                contract: Some(contract.clone()),
+            }),
+            contract,
+            federation_id,
+            "WaitForSwapPreimage",
+        );
+
+        assert!(matches!(
+            state_machine.state,
+            GatewayPayStates::CancelContract(_)
+        ));
+        assert!(logs_contain(
+            "Direct swap failed, canceling outgoing contract"
+        ));
+        assert!(logs_contain(&format!("contract_id={contract_id}")));
+        assert!(logs_contain(&format!("federation_id={federation_id}")));
+        assert!(logs_contain("state=\"WaitForSwapPreimage\""));
+    }
+}
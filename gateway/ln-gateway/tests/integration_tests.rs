@@ -12,6 +12,7 @@ use fedimint_client::transaction::{ClientInput, ClientOutput, TransactionBuilder
 use fedimint_client::ClientHandleArc;
 use fedimint_core::config::FederationId;
 use fedimint_core::core::{IntoDynInstance, OperationId};
+use fedimint_core::endpoint_constants::SUBMIT_TRANSACTION_ENDPOINT;
 use fedimint_core::secp256k1::PublicKey;
 use fedimint_core::task::sleep_in_test;
 use fedimint_core::util::NextOrPending;
@@ -20,9 +21,9 @@ use fedimint_dummy_client::{DummyClientInit, DummyClientModule};
 use fedimint_dummy_common::config::DummyGenParams;
 use fedimint_dummy_server::DummyInit;
 use fedimint_ln_client::api::LnFederationApi;
-use fedimint_ln_client::pay::{PayInvoicePayload, PaymentData};
+use fedimint_ln_client::pay::{GatewayPayError, PayInvoicePayload, PaymentData};
 use fedimint_ln_client::{
-    LightningClientInit, LightningClientModule, LightningClientStateMachines,
+    GatewayConnection, LightningClientInit, LightningClientModule, LightningClientStateMachines,
     LightningOperationMeta, LightningOperationMetaVariant, LnPayState, LnReceiveState,
     MockGatewayConnection, OutgoingLightningPayment, PayType,
 };
@@ -33,12 +34,13 @@ use fedimint_ln_common::contracts::{EncryptedPreimage, FundedContract, Preimage,
 use fedimint_ln_common::{LightningGateway, LightningInput, LightningOutput, PrunedInvoice};
 use fedimint_ln_server::LightningInit;
 use fedimint_logging::LOG_TEST;
+use fedimint_testing::api::{CallPattern, FaultAction, FaultInjector};
 use fedimint_testing::btc::BitcoinTest;
 use fedimint_testing::db::BYTE_33;
 use fedimint_testing::federation::FederationTest;
 use fedimint_testing::fixtures::Fixtures;
 use fedimint_testing::gateway::{GatewayTest, DEFAULT_GATEWAY_PASSWORD};
-use fedimint_testing::ln::FakeLightningTest;
+use fedimint_testing::ln::{FakeLightningTest, MockLightning, MockPayOutcome};
 use fedimint_unknown_common::config::UnknownGenParams;
 use fedimint_unknown_server::UnknownInit;
 use futures::Future;
@@ -67,19 +69,41 @@ async fn user_pay_invoice(
 }
 
 fn fixtures() -> Fixtures {
+    fixtures_with_gateway_conn(Arc::new(MockGatewayConnection))
+}
+
+fn fixtures_with_gateway_conn(gateway_conn: Arc<dyn GatewayConnection + Send + Sync>) -> Fixtures {
     info!(target: LOG_TEST, "Setting up fixtures");
     let fixtures = Fixtures::new_primary(DummyClientInit, DummyInit, DummyGenParams::default())
         .with_server_only_module(UnknownInit, UnknownGenParams::default());
     let ln_params = LightningGenParams::regtest(fixtures.bitcoin_server());
     fixtures.with_module(
-        LightningClientInit {
-            gateway_conn: Arc::new(MockGatewayConnection),
-        },
+        LightningClientInit { gateway_conn },
         LightningInit,
         ln_params,
     )
 }
 
+/// Gateway connection that never completes a payment, leaving the user's
+/// outgoing contract funded until it expires
+#[derive(Debug)]
+struct UnresponsiveGatewayConnection;
+
+#[async_trait::async_trait]
+impl GatewayConnection for UnresponsiveGatewayConnection {
+    async fn verify_gateway_availability(&self, _gateway: &LightningGateway) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn pay_invoice(
+        &self,
+        _gateway: LightningGateway,
+        _payload: PayInvoicePayload,
+    ) -> Result<String, GatewayPayError> {
+        std::future::pending().await
+    }
+}
+
 async fn single_federation_test<B>(
     f: impl FnOnce(
             GatewayTest,
@@ -244,6 +268,160 @@ async fn test_gateway_client_pay_valid_invoice() -> anyhow::Result<()> {
     .await
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn test_gateway_cancels_unsent_payments_on_shutdown() -> anyhow::Result<()> {
+    let fixtures = fixtures();
+    let lightning = MockLightning::new();
+    let fed = fixtures.new_default_fed().await;
+    let mut gateway = fixtures
+        .new_gateway_with_mock_lightning(0, Some(DEFAULT_GATEWAY_PASSWORD.to_string()), &lightning)
+        .await;
+    gateway.connect_fed(&fed).await;
+    let gateway_id = gateway.gateway.gateway_id();
+    let gateway_client = gateway.select_client(fed.id()).await;
+    let gateway_module = gateway_client.get_first_module::<GatewayClientModule>();
+
+    let sent_client = fed.new_client().await;
+    let dummy_module = sent_client.get_first_module::<DummyClientModule>();
+    let (_, outpoint) = dummy_module.print_money(sats(1000)).await?;
+    dummy_module.receive_money(outpoint).await?;
+
+    let faults = FaultInjector::default();
+    let unsent_client = fed.new_client_with_faults(&faults).await;
+    let dummy_module = unsent_client.get_first_module::<DummyClientModule>();
+    let (_, outpoint) = dummy_module.print_money(sats(1000)).await?;
+    dummy_module.receive_money(outpoint).await?;
+
+    // A payment that is being sent over lightning, so the gateway is about to
+    // hold its preimage
+    let sent_invoice = lightning.invoice(
+        sats(250),
+        vec![MockPayOutcome::Delayed(Duration::from_secs(5))],
+    );
+    let sent_lightning_module = sent_client.get_first_module::<LightningClientModule>();
+    let OutgoingLightningPayment { contract_id, .. } =
+        user_pay_invoice(&sent_lightning_module, sent_invoice.clone(), &gateway_id).await?;
+    let sent_gw_pay_op = gateway_module
+        .gateway_pay_bolt11_invoice(PayInvoicePayload {
+            federation_id: sent_client.federation_id(),
+            contract_id,
+            payment_data: get_payment_data(
+                sent_lightning_module.select_gateway(&gateway_id).await,
+                sent_invoice.clone(),
+            ),
+            preimage_auth: Hash::hash(&[0; 32]),
+        })
+        .await?;
+    let mut sent_gw_pay_sub = gateway_module
+        .gateway_subscribe_ln_pay(sent_gw_pay_op)
+        .await?
+        .into_stream();
+    assert_eq!(sent_gw_pay_sub.ok().await?, GatewayExtPayStates::Created);
+    while lightning.pay_attempts(&sent_invoice) == 0 {
+        sleep_in_test(
+            "waiting for the gateway to send the payment",
+            Duration::from_millis(100),
+        )
+        .await;
+    }
+
+    // A payment stuck in `PayInvoice`, since its contract isn't funded yet
+    faults.set_script(vec![(
+        CallPattern::any().with_method(SUBMIT_TRANSACTION_ENDPOINT),
+        FaultAction::Error("Guardians unreachable".to_string()),
+    )]);
+    let unsent_invoice = lightning.invoice(sats(100), vec![]);
+    let unsent_lightning_module = unsent_client.get_first_module::<LightningClientModule>();
+    let OutgoingLightningPayment {
+        payment_type,
+        contract_id,
+        fee: _,
+    } = user_pay_invoice(
+        &unsent_lightning_module,
+        unsent_invoice.clone(),
+        &gateway_id,
+    )
+    .await?;
+    let PayType::Lightning(unsent_pay_op) = payment_type else {
+        panic!("Expected Lightning payment!");
+    };
+    let unsent_gw_pay_op = gateway_module
+        .gateway_pay_bolt11_invoice(PayInvoicePayload {
+            federation_id: unsent_client.federation_id(),
+            contract_id,
+            payment_data: get_payment_data(
+                unsent_lightning_module.select_gateway(&gateway_id).await,
+                unsent_invoice.clone(),
+            ),
+            preimage_auth: Hash::hash(&[0; 32]),
+        })
+        .await?;
+    let mut unsent_gw_pay_sub = gateway_module
+        .gateway_subscribe_ln_pay(unsent_gw_pay_op)
+        .await?
+        .into_stream();
+    assert_eq!(unsent_gw_pay_sub.ok().await?, GatewayExtPayStates::Created);
+    assert!(gateway_module
+        .list_active_payments()
+        .await
+        .contains(&(contract_id, "PayInvoice".to_string())));
+
+    // Shut down, letting the stuck contract get funded once the gateway stopped
+    // sending payments
+    tokio::join!(
+        gateway
+            .gateway
+            .cancel_unpaid_outgoing_contracts(Duration::from_secs(30)),
+        async {
+            while !gateway.gateway.is_shutting_down() {
+                sleep_in_test("waiting for the shutdown", Duration::from_millis(10)).await;
+            }
+            faults.clear();
+        }
+    );
+
+    // The stuck payment gets canceled and its user refunded
+    assert_matches!(
+        unsent_gw_pay_sub.ok().await?,
+        GatewayExtPayStates::Canceled {
+            error: OutgoingPaymentError {
+                error_type: OutgoingPaymentErrorType::GatewayShuttingDown,
+                ..
+            }
+        }
+    );
+    assert_eq!(lightning.pay_attempts(&unsent_invoice), 0);
+    let mut unsent_pay_sub = unsent_lightning_module
+        .subscribe_ln_pay(unsent_pay_op)
+        .await?
+        .into_stream();
+    assert_eq!(unsent_pay_sub.ok().await?, LnPayState::Created);
+    assert_matches!(unsent_pay_sub.ok().await?, LnPayState::Funded { .. });
+    assert_matches!(
+        unsent_pay_sub.ok().await?,
+        LnPayState::WaitingForRefund { .. }
+    );
+    assert_matches!(unsent_pay_sub.ok().await?, LnPayState::Refunded { .. });
+    assert_eq!(unsent_client.get_balance().await, sats(1000));
+
+    // The payment that was already sent is not canceled, the gateway claims it
+    assert_matches!(
+        sent_gw_pay_sub.ok().await?,
+        GatewayExtPayStates::Preimage { .. }
+    );
+    let GatewayExtPayStates::Success { out_points, .. } = sent_gw_pay_sub.ok().await? else {
+        panic!("Gateway pay state machine was not successful");
+    };
+    let dummy_module = gateway_client.get_first_module::<DummyClientModule>();
+    for outpoint in out_points {
+        dummy_module.receive_money(outpoint).await?;
+    }
+    assert_eq!(sent_client.get_balance().await, sats(1000 - 250));
+    assert_eq!(gateway_client.get_balance().await, sats(250));
+
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn test_gateway_enforces_fees() -> anyhow::Result<()> {
     single_federation_test(
@@ -347,6 +525,117 @@ async fn test_gateway_enforces_fees() -> anyhow::Result<()> {
     .await
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn test_gateway_claims_contract_after_transient_lightning_failure() -> anyhow::Result<()> {
+    let fixtures = fixtures();
+    let lightning = MockLightning::new();
+    let fed = fixtures.new_default_fed().await;
+    let mut gateway = fixtures
+        .new_gateway_with_mock_lightning(0, Some(DEFAULT_GATEWAY_PASSWORD.to_string()), &lightning)
+        .await;
+    gateway.connect_fed(&fed).await;
+    let gateway_id = gateway.gateway.gateway_id();
+    let gateway_client = gateway.select_client(fed.id()).await;
+
+    let invoice = lightning.invoice(
+        sats(250),
+        vec![MockPayOutcome::TransientFailure, MockPayOutcome::Success],
+    );
+
+    // The first attempt fails, so the gateway cancels the contract
+    let user_client = fed.new_client().await;
+    let dummy_module = user_client.get_first_module::<DummyClientModule>();
+    let (_, outpoint) = dummy_module.print_money(sats(1000)).await?;
+    dummy_module.receive_money(outpoint).await?;
+
+    let lightning_module = user_client.get_first_module::<LightningClientModule>();
+    let gateway_module = lightning_module.select_gateway(&gateway_id).await;
+    let OutgoingLightningPayment { contract_id, .. } =
+        user_pay_invoice(&lightning_module, invoice.clone(), &gateway_id).await?;
+    let payload = PayInvoicePayload {
+        federation_id: user_client.federation_id(),
+        contract_id,
+        payment_data: get_payment_data(gateway_module, invoice.clone()),
+        preimage_auth: Hash::hash(&[0; 32]),
+    };
+    let gw_pay_op = gateway_client
+        .get_first_module::<GatewayClientModule>()
+        .gateway_pay_bolt11_invoice(payload)
+        .await?;
+    let mut gw_pay_sub = gateway_client
+        .get_first_module::<GatewayClientModule>()
+        .gateway_subscribe_ln_pay(gw_pay_op)
+        .await?
+        .into_stream();
+    assert_eq!(gw_pay_sub.ok().await?, GatewayExtPayStates::Created);
+    assert_matches!(gw_pay_sub.ok().await?, GatewayExtPayStates::Canceled { .. });
+    assert_eq!(gateway_client.get_balance().await, sats(0));
+
+    // A new payment of the same invoice goes through and the gateway claims it
+    let user_client = fed.new_client().await;
+    let dummy_module = user_client.get_first_module::<DummyClientModule>();
+    let (_, outpoint) = dummy_module.print_money(sats(1000)).await?;
+    dummy_module.receive_money(outpoint).await?;
+
+    gateway_pay_valid_invoice(invoice, &user_client, &gateway_client, &gateway_id).await?;
+    assert_eq!(user_client.get_balance().await, sats(1000 - 250));
+    assert_eq!(gateway_client.get_balance().await, sats(250));
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_gateway_lists_active_payments() -> anyhow::Result<()> {
+    let fixtures = fixtures();
+    let lightning = MockLightning::new();
+    let fed = fixtures.new_default_fed().await;
+    let mut gateway = fixtures
+        .new_gateway_with_mock_lightning(0, Some(DEFAULT_GATEWAY_PASSWORD.to_string()), &lightning)
+        .await;
+    gateway.connect_fed(&fed).await;
+    let gateway_id = gateway.gateway.gateway_id();
+    let gateway_client = gateway.select_client(fed.id()).await;
+    let gateway_module = gateway_client.get_first_module::<GatewayClientModule>();
+
+    let user_client = fed.new_client().await;
+    let dummy_module = user_client.get_first_module::<DummyClientModule>();
+    let (_, outpoint) = dummy_module.print_money(sats(1000)).await?;
+    dummy_module.receive_money(outpoint).await?;
+
+    // Keep the payment in flight long enough to observe it
+    let invoice = lightning.invoice(
+        sats(250),
+        vec![MockPayOutcome::Delayed(Duration::from_secs(2))],
+    );
+    let lightning_module = user_client.get_first_module::<LightningClientModule>();
+    let gateway_info = lightning_module.select_gateway(&gateway_id).await;
+    let OutgoingLightningPayment { contract_id, .. } =
+        user_pay_invoice(&lightning_module, invoice.clone(), &gateway_id).await?;
+    let payload = PayInvoicePayload {
+        federation_id: user_client.federation_id(),
+        contract_id,
+        payment_data: get_payment_data(gateway_info, invoice),
+        preimage_auth: Hash::hash(&[0; 32]),
+    };
+
+    assert!(gateway_module.list_active_payments().await.is_empty());
+    let gw_pay_op = gateway_module.gateway_pay_bolt11_invoice(payload).await?;
+    assert_eq!(
+        gateway_module.list_active_payments().await,
+        vec![(contract_id, "PayInvoice".to_string())]
+    );
+
+    let mut gw_pay_sub = gateway_module
+        .gateway_subscribe_ln_pay(gw_pay_op)
+        .await?
+        .into_stream();
+    assert_eq!(gw_pay_sub.ok().await?, GatewayExtPayStates::Created);
+    assert_matches!(gw_pay_sub.ok().await?, GatewayExtPayStates::Preimage { .. });
+    assert!(gateway_module.list_active_payments().await.is_empty());
+
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn test_gateway_cannot_claim_invalid_preimage() -> anyhow::Result<()> {
     single_federation_test(
@@ -420,6 +709,99 @@ async fn test_gateway_cannot_claim_invalid_preimage() -> anyhow::Result<()> {
     .await
 }
 
+/// Test helper function for having the gateway pay `invoice` on behalf of a
+/// new user client, returning the state after `Created`.
+async fn gateway_pay_with_preimage_auth(
+    gateway: &GatewayTest,
+    fed: &FederationTest,
+    invoice: Bolt11Invoice,
+    preimage_auth: sha256::Hash,
+) -> anyhow::Result<GatewayExtPayStates> {
+    let gateway_id = gateway.gateway.gateway_id();
+    let gateway_client = gateway.select_client(fed.id()).await;
+
+    let user_client = fed.new_client().await;
+    let dummy_module = user_client.get_first_module::<DummyClientModule>();
+    let (_, outpoint) = dummy_module.print_money(sats(1000)).await?;
+    dummy_module.receive_money(outpoint).await?;
+
+    let lightning_module = user_client.get_first_module::<LightningClientModule>();
+    let gateway_module = lightning_module.select_gateway(&gateway_id).await;
+    let OutgoingLightningPayment { contract_id, .. } =
+        user_pay_invoice(&lightning_module, invoice.clone(), &gateway_id).await?;
+    let payload = PayInvoicePayload {
+        federation_id: user_client.federation_id(),
+        contract_id,
+        payment_data: get_payment_data(gateway_module, invoice),
+        preimage_auth,
+    };
+
+    let gw_pay_op = gateway_client
+        .get_first_module::<GatewayClientModule>()
+        .gateway_pay_bolt11_invoice(payload)
+        .await?;
+    let mut gw_pay_sub = gateway_client
+        .get_first_module::<GatewayClientModule>()
+        .gateway_subscribe_ln_pay(gw_pay_op)
+        .await?
+        .into_stream();
+    assert_eq!(gw_pay_sub.ok().await?, GatewayExtPayStates::Created);
+    gw_pay_sub.ok().await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_gateway_enforces_preimage_auth() -> anyhow::Result<()> {
+    let fixtures = fixtures();
+    let other_lightning_client = FakeLightningTest::new();
+    let fed = fixtures.new_default_fed().await;
+    let mut gateway = fixtures
+        .new_gateway(0, Some(DEFAULT_GATEWAY_PASSWORD.to_string()))
+        .await;
+    gateway.connect_fed(&fed).await;
+
+    let invoice = other_lightning_client.invoice(sats(250), None)?;
+    let state =
+        gateway_pay_with_preimage_auth(&gateway, &fed, invoice.clone(), sha256(&[0; 32])).await?;
+    assert_matches!(state, GatewayExtPayStates::Preimage { .. });
+
+    // A second payment of the same invoice with a different `preimage_auth` is
+    // not authorized to receive the preimage
+    let state = gateway_pay_with_preimage_auth(&gateway, &fed, invoice, sha256(&[1; 32])).await?;
+    assert_matches!(
+        state,
+        GatewayExtPayStates::Canceled {
+            error: OutgoingPaymentError {
+                error_type: OutgoingPaymentErrorType::InvoiceAlreadyPaid,
+                ..
+            }
+        }
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_gateway_skips_disabled_preimage_auth() -> anyhow::Result<()> {
+    let fixtures = fixtures();
+    let other_lightning_client = FakeLightningTest::new();
+    let fed = fixtures.new_default_fed().await;
+    let mut gateway = fixtures
+        .new_gateway_without_preimage_auth(0, Some(DEFAULT_GATEWAY_PASSWORD.to_string()))
+        .await;
+    gateway.connect_fed(&fed).await;
+
+    let invoice = other_lightning_client.invoice(sats(250), None)?;
+    let state =
+        gateway_pay_with_preimage_auth(&gateway, &fed, invoice.clone(), sha256(&[0; 32])).await?;
+    assert_matches!(state, GatewayExtPayStates::Preimage { .. });
+
+    // Without preimage authentication the gateway pays the invoice again
+    let state = gateway_pay_with_preimage_auth(&gateway, &fed, invoice, sha256(&[1; 32])).await?;
+    assert_matches!(state, GatewayExtPayStates::Preimage { .. });
+
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn test_gateway_client_pay_unpayable_invoice() -> anyhow::Result<()> {
     single_federation_test(
@@ -811,6 +1193,145 @@ async fn test_gateway_cannot_pay_expired_invoice() -> anyhow::Result<()> {
     .await
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn test_gateway_cancels_payment_with_timelock_too_close() -> anyhow::Result<()> {
+    let fixtures = fixtures();
+    let other_lightning_client = FakeLightningTest::new();
+    let fed = fixtures.new_default_fed().await;
+    let mut gateway = fixtures
+        .new_gateway(0, Some(DEFAULT_GATEWAY_PASSWORD.to_string()))
+        .await;
+    gateway.connect_fed(&fed).await;
+    let user_client = fed.new_client().await;
+    let gateway_id = gateway.gateway.gateway_id();
+    let gateway_client = gateway.select_client(fed.id()).await;
+
+    // Print money for user_client
+    let dummy_module = user_client.get_first_module::<DummyClientModule>();
+    let (_, outpoint) = dummy_module.print_money(sats(1000)).await?;
+    dummy_module.receive_money(outpoint).await?;
+
+    // User client funds the outgoing contract
+    let invoice = other_lightning_client.invoice(sats(250), None)?;
+    let lightning_module = user_client.get_first_module::<LightningClientModule>();
+    let gateway_module = lightning_module.select_gateway(&gateway_id).await;
+    let OutgoingLightningPayment {
+        payment_type,
+        contract_id,
+        fee: _,
+    } = user_pay_invoice(&lightning_module, invoice.clone(), &gateway_id).await?;
+    let PayType::Lightning(pay_op) = payment_type else {
+        panic!("Expected Lightning payment!");
+    };
+    let mut pay_sub = lightning_module
+        .subscribe_ln_pay(pay_op)
+        .await?
+        .into_stream();
+    assert_eq!(pay_sub.ok().await?, LnPayState::Created);
+    let LnPayState::Funded { block_height } = pay_sub.ok().await? else {
+        panic!("Expected funded payment");
+    };
+
+    // Leave less than the gateway's timelock delta of 10 blocks until the contract
+    // expires
+    let ln_api = gateway_client
+        .get_first_module::<GatewayClientModule>()
+        .api
+        .clone();
+    let block_count = ln_api.fetch_consensus_block_count().await?.unwrap_or(0);
+    let blocks_to_mine = u64::from(block_height)
+        .checked_sub(block_count + 5)
+        .expect("Contract timelock is at least 5 blocks in the future");
+    fixtures
+        .advance_consensus_block_count(blocks_to_mine)
+        .await?;
+    let block_count = ln_api.fetch_consensus_block_count().await?.unwrap_or(0);
+    assert!(block_count < u64::from(block_height));
+
+    let payload = PayInvoicePayload {
+        federation_id: user_client.federation_id(),
+        contract_id,
+        payment_data: get_payment_data(gateway_module, invoice),
+        preimage_auth: Hash::hash(&[0; 32]),
+    };
+    let gw_pay_op = gateway_client
+        .get_first_module::<GatewayClientModule>()
+        .gateway_pay_bolt11_invoice(payload)
+        .await?;
+    let mut gw_pay_sub = gateway_client
+        .get_first_module::<GatewayClientModule>()
+        .gateway_subscribe_ln_pay(gw_pay_op)
+        .await?
+        .into_stream();
+    assert_eq!(gw_pay_sub.ok().await?, GatewayExtPayStates::Created);
+    assert_matches!(gw_pay_sub.ok().await?, GatewayExtPayStates::Canceled { .. });
+    assert_eq!(gateway_client.get_balance().await, sats(0));
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_expired_outgoing_contract_is_refunded_to_user() -> anyhow::Result<()> {
+    let fixtures = fixtures_with_gateway_conn(Arc::new(UnresponsiveGatewayConnection));
+    let other_lightning_client = FakeLightningTest::new();
+    let fed = fixtures.new_default_fed().await;
+    let mut gateway = fixtures
+        .new_gateway(0, Some(DEFAULT_GATEWAY_PASSWORD.to_string()))
+        .await;
+    gateway.connect_fed(&fed).await;
+    let user_client = fed.new_client().await;
+    let gateway_id = gateway.gateway.gateway_id();
+
+    // Print money for user_client
+    let dummy_module = user_client.get_first_module::<DummyClientModule>();
+    let (_, outpoint) = dummy_module.print_money(sats(1000)).await?;
+    dummy_module.receive_money(outpoint).await?;
+
+    // User client funds the outgoing contract, but the gateway never pays it
+    let invoice = other_lightning_client.invoice(sats(250), None)?;
+    let lightning_module = user_client.get_first_module::<LightningClientModule>();
+    let OutgoingLightningPayment { payment_type, .. } =
+        user_pay_invoice(&lightning_module, invoice, &gateway_id).await?;
+    let PayType::Lightning(pay_op) = payment_type else {
+        panic!("Expected Lightning payment!");
+    };
+    let mut pay_sub = lightning_module
+        .subscribe_ln_pay(pay_op)
+        .await?
+        .into_stream();
+    assert_eq!(pay_sub.ok().await?, LnPayState::Created);
+    let LnPayState::Funded { block_height } = pay_sub.ok().await? else {
+        panic!("Expected funded payment");
+    };
+
+    // Advance the consensus block count past the contract timelock
+    let block_count = gateway
+        .select_client(fed.id())
+        .await
+        .get_first_module::<GatewayClientModule>()
+        .api
+        .fetch_consensus_block_count()
+        .await?
+        .expect("Consensus block count is available");
+    let blocks_to_mine = u64::from(block_height)
+        .checked_sub(block_count)
+        .expect("Contract timelock is in the future")
+        + 1;
+    fixtures
+        .advance_consensus_block_count(blocks_to_mine)
+        .await?;
+
+    // The expired contract is refunded to the user without a gateway cancel
+    let LnPayState::WaitingForRefund { error_reason } = pay_sub.ok().await? else {
+        panic!("Expected refund of the expired contract");
+    };
+    assert!(error_reason.contains("timed out"), "{error_reason}");
+    assert_matches!(pay_sub.ok().await?, LnPayState::Refunded { .. });
+    assert_eq!(user_client.get_balance().await, sats(1000));
+
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn test_gateway_executes_swaps_between_connected_federations() -> anyhow::Result<()> {
     multi_federation_test(|gateway, rpc, fed1, fed2, _| async move {
@@ -49,6 +49,9 @@ pub struct DummyClientModule {
     notifier: ModuleNotifier<DummyStateMachine>,
     client_ctx: ClientContext<Self>,
     db: Database,
+    /// See [`Self::primary_module_started_before`]
+    #[cfg(feature = "test-hooks")]
+    primary_started_before: std::sync::atomic::AtomicBool,
 }
 
 /// Data needed by the state machine
@@ -86,6 +89,16 @@ impl ClientModule for DummyClientModule {
         true
     }
 
+    #[cfg(feature = "test-hooks")]
+    async fn start(&self) {
+        let primary_started = self
+            .client_ctx
+            .primary_module_instance()
+            .is_some_and(|primary| self.client_ctx.is_module_started(primary));
+        self.primary_started_before
+            .store(primary_started, std::sync::atomic::Ordering::SeqCst);
+    }
+
     async fn create_final_inputs_and_outputs(
         &self,
         dbtx: &mut DatabaseTransaction<'_>,
@@ -204,6 +217,15 @@ impl ClientModule for DummyClientModule {
 }
 
 impl DummyClientModule {
+    /// Whether the primary module was already started when this module was
+    /// started, which is always the case unless this is the primary module.
+    /// Only available with the `test-hooks` feature.
+    #[cfg(feature = "test-hooks")]
+    pub fn primary_module_started_before(&self) -> bool {
+        self.primary_started_before
+            .load(std::sync::atomic::Ordering::SeqCst)
+    }
+
     pub async fn print_using_account(
         &self,
         amount: Amount,
@@ -362,6 +384,10 @@ impl ClientModuleInit for DummyClientInit {
             .expect("no version conflicts")
     }
 
+    fn supports_being_primary(&self) -> bool {
+        true
+    }
+
     async fn init(&self, args: &ClientModuleInitArgs<Self>) -> anyhow::Result<Self::Module> {
         Ok(DummyClientModule {
             cfg: args.cfg().clone(),
@@ -373,6 +399,8 @@ impl ClientModuleInit for DummyClientInit {
             notifier: args.notifier().clone(),
             client_ctx: args.context(),
             db: args.db().clone(),
+            #[cfg(feature = "test-hooks")]
+            primary_started_before: std::sync::atomic::AtomicBool::new(false),
         })
     }
 
@@ -10,7 +10,7 @@ use fedimint_core::{Amount, OutPoint, TransactionId};
 use fedimint_dummy_common::DummyOutputOutcome;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
-use tracing::debug;
+use tracing::{debug, error};
 
 use crate::db::DummyClientFundsKeyV1;
 use crate::{get_funds, DummyClientContext};
@@ -84,9 +84,18 @@ impl State for DummyStateMachine {
     }
 }
 
+/// Credits `amount` to the balance. Panicking would abort the state
+/// transition, so an overflowing credit is logged and dropped instead.
 async fn add_funds(amount: Amount, mut dbtx: DatabaseTransaction<'_>) {
-    let funds = get_funds(&mut dbtx).await + amount;
-    dbtx.insert_entry(&DummyClientFundsKeyV1, &funds).await;
+    let funds = get_funds(&mut dbtx).await;
+    match funds.checked_add(amount) {
+        Some(funds) => {
+            dbtx.insert_entry(&DummyClientFundsKeyV1, &funds).await;
+        }
+        None => {
+            error!(%funds, %amount, "Dummy balance overflow, not crediting funds");
+        }
+    }
 }
 
 // TODO: Boiler-plate, should return OutputOutcome
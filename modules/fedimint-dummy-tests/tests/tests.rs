@@ -1,19 +1,38 @@
+use std::pin::pin;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use anyhow::bail;
+use anyhow::{bail, Context as _};
+use fedimint_api_client::api::{FederationError, IRawFederationApi};
+use fedimint_client::db::{CachedApiVersionSet, CachedApiVersionSetKey};
+use fedimint_client::module::init::{ClientModuleInitRegistry, DynClientModuleInit};
+use fedimint_client::secret::{PlainRootSecretStrategy, RootSecretStrategy};
 use fedimint_client::transaction::{ClientInput, ClientOutput, TransactionBuilder};
-use fedimint_core::config::ClientModuleConfig;
+use fedimint_client::{
+    AdminCreds, Client, ClientHandle, ClientHandleArc, DbCompatibility, SkipReason,
+};
+use fedimint_core::config::{ClientConfig, ClientModuleConfig};
 use fedimint_core::core::{IntoDynInstance, ModuleKind, OperationId};
 use fedimint_core::db::mem_impl::MemDatabase;
-use fedimint_core::module::ModuleConsensusVersion;
-use fedimint_core::secp256k1::Secp256k1;
-use fedimint_core::{sats, Amount, OutPoint};
+use fedimint_core::db::{
+    Database, DatabaseVersion, DatabaseVersionKey, IDatabaseTransactionOpsCoreTyped,
+};
+use fedimint_core::endpoint_constants::{SESSION_COUNT_ENDPOINT, SUBMIT_TRANSACTION_ENDPOINT};
+use fedimint_core::invite_code::InviteCode;
+use fedimint_core::module::{
+    ApiAuth, ApiRequestErased, IDynCommonModuleInit, ModuleConsensusVersion,
+};
+use fedimint_core::secp256k1::{KeyPair, Secp256k1};
+use fedimint_core::task::sleep_in_test;
+use fedimint_core::{sats, Amount, OutPoint, PeerId};
 use fedimint_dummy_client::states::DummyStateMachine;
 use fedimint_dummy_client::{DummyClientInit, DummyClientModule};
 use fedimint_dummy_common::config::{DummyClientConfig, DummyGenParams};
-use fedimint_dummy_common::{broken_fed_key_pair, DummyInput, DummyOutput, KIND};
+use fedimint_dummy_common::{broken_fed_key_pair, fed_key_pair, DummyInput, DummyOutput, KIND};
 use fedimint_dummy_server::DummyInit;
+use fedimint_testing::api::{CallPattern, FaultAction, FaultInjector};
 use fedimint_testing::fixtures::Fixtures;
+use futures::StreamExt;
 
 fn fixtures() -> Fixtures {
     Fixtures::new_primary(DummyClientInit, DummyInit, DummyGenParams::default())
@@ -63,6 +82,604 @@ async fn client_ignores_unknown_module() {
         .await;
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn client_reports_skipped_modules() {
+    let fed = fixtures().new_default_fed().await;
+    let client = fed.new_client().await;
+    assert!(client.skipped_modules().is_empty());
+
+    let mut cfg = client.get_config().clone();
+    let module_id = 2142;
+    let kind = ModuleKind::from_static_str("unknown_module");
+    let extra_mod = ClientModuleConfig::from_typed(
+        module_id,
+        kind.clone(),
+        ModuleConsensusVersion::new(0, 0),
+        DummyClientConfig {
+            tx_fee: Amount::from_sats(1),
+        },
+    )
+    .unwrap();
+    cfg.modules.insert(module_id, extra_mod);
+
+    let client = fed
+        .new_client_with(cfg, MemDatabase::new().into(), None)
+        .await;
+    assert_eq!(
+        client.skipped_modules(),
+        vec![(module_id, kind, SkipReason::NotRegistered)]
+    );
+}
+
+async fn build_client_with_primary_kinds(
+    config: ClientConfig,
+    kinds: Vec<ModuleKind>,
+) -> anyhow::Result<Client> {
+    let mut client_builder = Client::builder(MemDatabase::new().into());
+    client_builder.with_module_inits(ClientModuleInitRegistry::from(vec![
+        DynClientModuleInit::from(DummyClientInit),
+    ]));
+    client_builder.with_primary_module_kinds(kinds);
+    let client_secret =
+        Client::load_or_generate_client_secret(client_builder.db_no_decoders()).await?;
+    client_builder
+        .join(
+            PlainRootSecretStrategy::to_root_secret(&client_secret),
+            config,
+            None,
+        )
+        .await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn primary_module_kinds_fall_back_to_available_kind() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let config = fed.new_client().await.get_config().clone();
+
+    let client =
+        build_client_with_primary_kinds(config, vec![ModuleKind::from_static_str("mint"), KIND])
+            .await?;
+
+    // The dummy module is primary, so its funds count towards the balance
+    let dummy_module = client.get_first_module::<DummyClientModule>();
+    let (_, outpoint) = dummy_module.print_money(sats(1000)).await?;
+    dummy_module.receive_money(outpoint).await?;
+    assert_eq!(client.get_balance().await, sats(1000));
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn primary_module_is_started_first() -> anyhow::Result<()> {
+    // The second dummy module is the primary one, so it has to be started
+    // before the first one despite its higher instance id
+    let fed = fixtures()
+        .with_server_only_module(DummyInit, DummyGenParams::default())
+        .new_fed_builder()
+        .primary_client(1)
+        .build()
+        .await;
+    let client = fed.new_client().await;
+
+    let primary_started_before = |module_instance_id| -> anyhow::Result<bool> {
+        Ok(client
+            .get_module_client_dyn(module_instance_id)?
+            .as_any()
+            .downcast_ref::<DummyClientModule>()
+            .context("Not a dummy module")?
+            .primary_module_started_before())
+    };
+    assert!(primary_started_before(0)?);
+    assert!(!primary_started_before(1)?);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn primary_module_kinds_without_match_fail() {
+    let fed = fixtures().new_default_fed().await;
+    let config = fed.new_client().await.get_config().clone();
+
+    let err = build_client_with_primary_kinds(
+        config,
+        vec![
+            ModuleKind::from_static_str("mint"),
+            ModuleKind::from_static_str("ln"),
+        ],
+    )
+    .await
+    .expect_err("No configured kind is available");
+    assert_eq!(
+        err.to_string(),
+        "No primary module instance id was provided"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn client_without_primary_module_can_read_but_not_spend() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let config = fed.new_client().await.get_config().clone();
+
+    let mut client_builder = Client::builder(MemDatabase::new().into());
+    client_builder.with_module_inits(ClientModuleInitRegistry::from(vec![
+        DynClientModuleInit::from(DummyClientInit),
+    ]));
+    client_builder.with_primary_module_kinds(vec![ModuleKind::from_static_str("mint")]);
+    client_builder.allow_no_primary();
+    let client_secret =
+        Client::load_or_generate_client_secret(client_builder.db_no_decoders()).await?;
+    let client = client_builder
+        .join(
+            PlainRootSecretStrategy::to_root_secret(&client_secret),
+            config,
+            None,
+        )
+        .await?;
+    assert_eq!(client.primary_module_instance(), None);
+
+    // Reads still work
+    let dummy_module = client.get_first_module::<DummyClientModule>();
+    let account = dummy_module.account();
+    assert_eq!(client.get_balance().await, Amount::ZERO);
+
+    let err = dummy_module
+        .send_money(account, sats(1))
+        .await
+        .expect_err("Funding requires a primary module");
+    assert!(format!("{err:#}").contains("Client has no primary module"));
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn db_compatibility_detects_newer_db_version() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let config = fed.new_client().await.get_config().clone();
+    let module_inits =
+        ClientModuleInitRegistry::from(vec![DynClientModuleInit::from(DummyClientInit)]);
+
+    let db: Database = MemDatabase::new().into();
+    assert_eq!(
+        Client::db_compatibility(&db, &module_inits).await,
+        DbCompatibility::Uninitialized
+    );
+
+    let mut client_builder = Client::builder(db.clone());
+    client_builder.with_module_inits(module_inits.clone());
+    client_builder.with_primary_module_kinds(vec![KIND]);
+    let client_secret = Client::load_or_generate_client_secret(&db).await?;
+    let client = client_builder
+        .join(
+            PlainRootSecretStrategy::to_root_secret(&client_secret),
+            config,
+            None,
+        )
+        .await?;
+    assert_eq!(
+        Client::db_compatibility(&db, &module_inits).await,
+        DbCompatibility::Compatible
+    );
+
+    // Simulate a newer client having migrated the dummy module's database
+    let module_instance_id = client
+        .get_first_instance(&KIND)
+        .expect("dummy module exists");
+    let mut dbtx = db.begin_transaction().await;
+    dbtx.insert_entry(
+        &DatabaseVersionKey(module_instance_id),
+        &DatabaseVersion(1000),
+    )
+    .await;
+    dbtx.commit_tx().await;
+
+    assert_eq!(
+        Client::db_compatibility(&db, &module_inits).await,
+        DbCompatibility::NewerThanSupported {
+            module_instance_id,
+            kind: KIND,
+            db_version: DatabaseVersion(1000),
+            supported_version: DummyClientInit.database_version(),
+        }
+    );
+    Ok(())
+}
+
+async fn open_or_join(db: &Database, invite_code: &InviteCode) -> anyhow::Result<ClientHandle> {
+    let mut client_builder = Client::builder(db.clone());
+    client_builder.with_module_inits(ClientModuleInitRegistry::from(vec![
+        DynClientModuleInit::from(DummyClientInit),
+    ]));
+    client_builder.with_primary_module_kinds(vec![KIND]);
+    let client_secret = Client::load_or_generate_client_secret(db).await?;
+    client_builder
+        .open_or_join(
+            PlainRootSecretStrategy::to_root_secret(&client_secret),
+            invite_code,
+        )
+        .await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn open_or_join_joins_fresh_db_and_reopens_it() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let db: Database = MemDatabase::new().into();
+
+    let client = open_or_join(&db, &fed.invite_code()).await?;
+    assert_eq!(client.federation_id(), fed.id());
+    let dummy_module = client.get_first_module::<DummyClientModule>();
+    let (_, outpoint) = dummy_module.print_money(sats(1000)).await?;
+    dummy_module.receive_money(outpoint).await?;
+    client.shutdown().await;
+
+    // Joining an initialized database would fail, so this has to open it
+    let client = open_or_join(&db, &fed.invite_code()).await?;
+    assert_eq!(client.federation_id(), fed.id());
+    assert_eq!(client.get_balance().await, sats(1000));
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn renegotiated_api_versions_enable_skipped_module() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let db: Database = MemDatabase::new().into();
+    open_or_join(&db, &fed.invite_code())
+        .await?
+        .shutdown()
+        .await;
+
+    // Simulate guardians that didn't support the dummy module's api yet when
+    // the versions were last negotiated
+    let mut dbtx = db.begin_transaction().await;
+    let CachedApiVersionSet(mut cached_versions) = dbtx
+        .get_value(&CachedApiVersionSetKey)
+        .await
+        .context("Api versions are cached after joining")?;
+    let (module_instance_id, _) = cached_versions
+        .modules
+        .pop_first()
+        .context("Dummy module has an api version")?;
+    dbtx.insert_entry(
+        &CachedApiVersionSetKey,
+        &CachedApiVersionSet(cached_versions),
+    )
+    .await;
+    dbtx.commit_tx().await;
+
+    let mut client_builder = Client::builder(db.clone());
+    client_builder.with_module_inits(ClientModuleInitRegistry::from(vec![
+        DynClientModuleInit::from(DummyClientInit),
+    ]));
+    client_builder.with_primary_module_kinds(vec![KIND]);
+    client_builder.allow_no_primary();
+    let client = client_builder
+        .open(PlainRootSecretStrategy::to_root_secret(
+            &Client::load_or_generate_client_secret(&db).await?,
+        ))
+        .await?;
+    assert_eq!(
+        client.skipped_modules(),
+        vec![(module_instance_id, KIND, SkipReason::IncompatibleApiVersion)]
+    );
+    assert_eq!(client.primary_module_instance(), None);
+
+    // The background re-negotiation picks up the guardians' versions
+    let mut api_versions = pin!(client.subscribe_api_version_changes());
+    loop {
+        let versions = api_versions
+            .next()
+            .await
+            .context("Api versions stream ended")?;
+        if versions.modules.contains_key(&module_instance_id) {
+            break;
+        }
+    }
+
+    // The module is enabled without restarting the client
+    while !client.has_module(module_instance_id) {
+        sleep_in_test("wait for module to be enabled", Duration::from_millis(100)).await;
+    }
+    assert!(client.skipped_modules().is_empty());
+    assert_eq!(client.primary_module_instance(), Some(module_instance_id));
+    assert_eq!(client.get_first_instance(&KIND), Some(module_instance_id));
+    assert_eq!(
+        client.get_first_module::<DummyClientModule>().id,
+        module_instance_id
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn open_or_join_rejects_db_of_other_federation() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let other_fed = fixtures().new_default_fed().await;
+    let db: Database = MemDatabase::new().into();
+
+    open_or_join(&db, &fed.invite_code())
+        .await?
+        .shutdown()
+        .await;
+
+    let err = open_or_join(&db, &other_fed.invite_code())
+        .await
+        .expect_err("Database of another federation should be rejected");
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "Client database belongs to federation {}, not {}",
+            fed.id(),
+            other_fed.id()
+        )
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rotating_api_secret_requires_accepted_secret() -> anyhow::Result<()> {
+    let fed = fixtures()
+        .new_fed_builder()
+        .api_secrets("old-secret,new-secret".parse()?)
+        .build()
+        .await;
+    let client = fed.new_client().await;
+
+    assert!(client
+        .set_api_secret(Some("wrong-secret".to_owned()))
+        .await
+        .is_err());
+    assert_eq!(
+        Client::get_api_secret_from_db(client.db()).await,
+        Some("old-secret".to_owned())
+    );
+
+    client.set_api_secret(Some("new-secret".to_owned())).await?;
+    assert_eq!(client.current_api_secret(), Some("new-secret".to_owned()));
+    assert_eq!(
+        Client::get_api_secret_from_db(client.db()).await,
+        Some("new-secret".to_owned())
+    );
+
+    // The running client keeps working with the new secret
+    let dummy_module = client.get_first_module::<DummyClientModule>();
+    let (_, outpoint) = dummy_module.print_money(sats(1000)).await?;
+    dummy_module.receive_money(outpoint).await?;
+    assert_eq!(client.get_balance().await, sats(1000));
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn verify_admin_creds_rejects_wrong_password() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client = fed.new_client().await;
+
+    let mut client_builder = Client::builder(client.db().clone());
+    client_builder.set_admin_creds(AdminCreds {
+        peer_id: PeerId::from(0),
+        auth: ApiAuth("wrong-password".to_string()),
+    });
+    let err = client_builder
+        .verify_admin_creds()
+        .await
+        .expect_err("Wrong password should be rejected");
+    assert_eq!(err.to_string(), "Guardian 0 rejected the admin credentials");
+    assert!(err.downcast_ref::<FederationError>().is_some());
+
+    client_builder.set_admin_creds(AdminCreds {
+        peer_id: PeerId::from(0),
+        auth: ApiAuth("pass".to_string()),
+    });
+    client_builder.verify_admin_creds().await?;
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn client_recovers_after_guardian_outage() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let faults = FaultInjector::default();
+    let client = fed.new_client_with_faults(&faults).await;
+
+    faults.set_script(vec![(
+        CallPattern::any(),
+        FaultAction::Error("guardian outage".to_owned()),
+    )]);
+    assert!(client.api().session_count().await.is_err());
+
+    faults.clear();
+    client.api().session_count().await?;
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn pending_tx_submission_counts_attempts() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let faults = FaultInjector::default();
+    let client = fed.new_client_with_faults(&faults).await;
+
+    faults.set_script(vec![(
+        CallPattern::any().with_method(SUBMIT_TRANSACTION_ENDPOINT),
+        FaultAction::Error("submission rejected".to_owned()),
+    )]);
+    let print = tokio::spawn({
+        let client = client.clone();
+        async move {
+            client
+                .get_first_module::<DummyClientModule>()
+                .print_money(sats(1000))
+                .await
+        }
+    });
+
+    // Submissions are retried every 5 seconds
+    let mut last_attempts = 0;
+    for _ in 0..200 {
+        let pending = client.pending_tx_submissions().await;
+        if let Some(submission) = pending.first() {
+            assert_eq!(pending.len(), 1);
+            assert!(submission.attempts >= last_attempts);
+            assert!(submission
+                .last_error
+                .as_ref()
+                .map_or(submission.attempts == 0, |e| e
+                    .contains("submission rejected")));
+            last_attempts = submission.attempts;
+            if last_attempts >= 2 {
+                break;
+            }
+        }
+        sleep_in_test("wait for resubmission", Duration::from_millis(100)).await;
+    }
+    assert!(last_attempts >= 2, "Expected the submission to be retried");
+
+    faults.clear();
+    print.await??;
+    assert!(client.pending_tx_submissions().await.is_empty());
+    assert_eq!(client.get_balance().await, sats(1000));
+
+    Ok(())
+}
+
+/// Submits a transaction spending `amount` from `account_kp` and waits for it
+/// to be accepted or to fail
+async fn submit_dummy_input(
+    client: &ClientHandleArc,
+    account_kp: KeyPair,
+    amount: Amount,
+) -> anyhow::Result<Result<(), String>> {
+    let dummy_module = client.get_first_module::<DummyClientModule>();
+    let input = ClientInput {
+        input: DummyInput {
+            amount,
+            account: account_kp.public_key(),
+        },
+        amount,
+        keys: vec![account_kp],
+        state_machines: Arc::new(|_, _| Vec::<DummyStateMachine>::new()),
+    };
+    let tx = TransactionBuilder::new().with_input(input.into_dyn(dummy_module.id));
+    let operation_id = OperationId::new_random();
+    let (txid, _) = client
+        .finalize_and_submit_transaction(operation_id, KIND.as_str(), |_, _| (), tx)
+        .await?;
+
+    Ok(client
+        .transaction_updates(operation_id)
+        .await
+        .await_tx_accepted(txid)
+        .await)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn tx_submission_gives_up_after_retry_limit() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let faults = FaultInjector::default();
+    let client = fed.new_client_with_faults_and_retry_limit(&faults, 1).await;
+
+    faults.set_script(vec![(
+        CallPattern::any().with_method(SUBMIT_TRANSACTION_ENDPOINT),
+        FaultAction::Error("guardian outage".to_owned()),
+    )]);
+    let start = Instant::now();
+    let error = submit_dummy_input(&client, fed_key_pair(), sats(1000))
+        .await?
+        .expect_err("Submission should have failed");
+
+    // The single retry happens after the retry interval of 5 seconds
+    assert!(start.elapsed() >= Duration::from_secs(5));
+    assert!(error.contains("guardian outage"), "{error}");
+    assert!(client.pending_tx_submissions().await.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rejected_tx_submission_is_not_retried() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let faults = FaultInjector::default();
+    let client = fed
+        .new_client_with_faults_and_retry_limit(&faults, 100)
+        .await;
+
+    // Spending from an account without funds is rejected by the federation
+    let start = Instant::now();
+    let unfunded_kp = KeyPair::new(&Secp256k1::new(), &mut rand::thread_rng());
+    submit_dummy_input(&client, unfunded_kp, sats(1000))
+        .await?
+        .expect_err("Transaction should have been rejected");
+
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert!(client.pending_tx_submissions().await.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn client_request_timeout_skips_slow_guardian() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let faults = FaultInjector::default();
+    let client = fed
+        .new_client_with_faults_and_timeout(&faults, Duration::from_millis(500))
+        .await;
+
+    let slow_peer = PeerId::from(0);
+    faults.set_script(vec![(
+        CallPattern::peer(slow_peer),
+        FaultAction::Delay(Duration::from_secs(3600)),
+    )]);
+
+    let single_peer_request = client.api().request_raw(
+        slow_peer,
+        SESSION_COUNT_ENDPOINT,
+        &[ApiRequestErased::default().to_json()],
+    );
+    let result = fedimint_core::runtime::timeout(Duration::from_secs(60), single_peer_request)
+        .await
+        .expect("Request to the slow guardian should time out on its own");
+    assert!(result.is_err());
+
+    // The remaining guardians still form a quorum
+    client.api().session_count().await?;
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn client_reports_quorum_progress() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let faults = FaultInjector::default();
+    let client = fed.new_client_with_faults(&faults).await;
+
+    // Keep one guardian from answering before the others reached consensus
+    faults.set_script(vec![(
+        CallPattern::peer(PeerId::from(0)),
+        FaultAction::Delay(Duration::from_secs(3600)),
+    )]);
+
+    let mut progress = vec![];
+    let _: u64 = client
+        .api_clone()
+        .await_with_quorum_progress(
+            SESSION_COUNT_ENDPOINT.to_owned(),
+            ApiRequestErased::default(),
+            |confirmed, threshold| progress.push((confirmed, threshold)),
+        )
+        .await?;
+
+    let threshold = progress.last().expect("Guardians responded").1;
+    assert_eq!(
+        progress,
+        (1..=threshold)
+            .map(|confirmed| (confirmed, threshold))
+            .collect::<Vec<_>>()
+    );
+
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn federation_should_abort_if_balance_sheet_is_negative() -> anyhow::Result<()> {
     let fed = fixtures().new_default_fed().await;
@@ -24,6 +24,9 @@ enum Opts {
         #[clap(long, default_value = "false")]
         force_internal: bool,
     },
+    /// Hide a lightning receive whose invoice hasn't been paid yet, a late
+    /// payment is still claimed
+    CancelInvoice { operation_id: OperationId },
     /// Pay a lightning invoice or lnurl via a gateway
     Pay {
         /// Lightning invoice or lnurl
@@ -83,6 +86,10 @@ pub(crate) async fn handle_cli_command(
             })
             .expect("Can't fail")
         }
+        Opts::CancelInvoice { operation_id } => {
+            module.cancel_ln_receive(operation_id).await?;
+            serde_json::Value::Null
+        }
         Opts::Pay {
             payment_info,
             amount,
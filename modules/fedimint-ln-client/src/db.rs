@@ -30,6 +30,7 @@ pub enum DbKeyPrefix {
     PaymentResult = 0x29,
     MetaOverridesDeprecated = 0x30,
     LightningGateway = 0x45,
+    CancelledLnReceive = 0x46,
 }
 
 impl std::fmt::Display for DbKeyPrefix {
@@ -92,6 +93,25 @@ impl_db_lookup!(
     query_prefix = LightningGatewayKeyPrefix
 );
 
+/// Marks a lightning receive operation the user asked to hide, see
+/// [`crate::LightningClientModule::cancel_ln_receive`]
+#[derive(Debug, Clone, Encodable, Decodable, Serialize)]
+pub struct CancelledLnReceiveKey(pub OperationId);
+
+#[derive(Debug, Clone, Encodable, Decodable, Serialize)]
+pub struct CancelledLnReceiveKeyPrefix;
+
+impl_db_record!(
+    key = CancelledLnReceiveKey,
+    value = (),
+    db_prefix = DbKeyPrefix::CancelledLnReceive,
+    notify_on_modify = true,
+);
+impl_db_lookup!(
+    key = CancelledLnReceiveKey,
+    query_prefix = CancelledLnReceiveKeyPrefix
+);
+
 /// Migrates `SubmittedOfferV0` to `SubmittedOffer` and `ConfirmedInvoiceV0` to
 /// `ConfirmedInvoice`
 pub(crate) fn get_v1_migrated_state(
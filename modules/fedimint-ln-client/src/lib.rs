@@ -23,8 +23,9 @@ pub mod incoming;
 pub mod pay;
 pub mod receive;
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::iter::once;
+use std::pin::pin;
 use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
@@ -36,7 +37,8 @@ use bitcoin::hashes::{sha256, Hash, HashEngine, Hmac, HmacEngine};
 use bitcoin::key::KeyPair;
 use bitcoin::Network;
 use db::{
-    DbKeyPrefix, LightningGatewayKey, LightningGatewayKeyPrefix, PaymentResult, PaymentResultKey,
+    CancelledLnReceiveKey, CancelledLnReceiveKeyPrefix, DbKeyPrefix, LightningGatewayKey,
+    LightningGatewayKeyPrefix, PaymentResult, PaymentResultKey,
 };
 use fedimint_api_client::api::DynModuleApi;
 use fedimint_client::db::{migrate_state, ClientMigrationFn};
@@ -72,11 +74,12 @@ use fedimint_ln_common::contracts::{
     PreimageKey,
 };
 use fedimint_ln_common::{
-    ContractOutput, LightningCommonInit, LightningGateway, LightningGatewayAnnouncement,
-    LightningGatewayRegistration, LightningInput, LightningModuleTypes, LightningOutput,
-    LightningOutputV0,
+    compute_safe_timelock, ContractOutput, LightningCommonInit, LightningGateway,
+    LightningGatewayAnnouncement, LightningGatewayRegistration, LightningInput,
+    LightningModuleTypes, LightningOutput, LightningOutputV0,
 };
 use fedimint_logging::LOG_CLIENT_MODULE_LN;
+use futures::future::{select, Either};
 use futures::{Future, StreamExt};
 use incoming::IncomingSmError;
 use lightning_invoice::{
@@ -90,7 +93,7 @@ use secp256k1::{All, PublicKey, Scalar, Secp256k1, Signing, ThirtyTwoByteHash, V
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use strum::IntoEnumIterator;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
 use crate::db::PaymentResultPrefix;
 use crate::incoming::{
@@ -102,18 +105,25 @@ use crate::pay::{
     LightningPayStateMachine,
 };
 use crate::receive::{
-    get_incoming_contract, LightningReceiveError, LightningReceiveStateMachine,
-    LightningReceiveStates, LightningReceiveSubmittedOffer,
+    get_incoming_contract, LightningReceiveConfirmedInvoice, LightningReceiveError,
+    LightningReceiveStateMachine, LightningReceiveStates, LightningReceiveSubmittedOffer,
 };
 
 /// Number of blocks until outgoing lightning contracts times out and user
 /// client can get refund
 const OUTGOING_LN_CONTRACT_TIMELOCK: u64 = 500;
 
+/// Largest min final CLTV expiry delta of an invoice we are willing to lock
+/// funds for on top of [`OUTGOING_LN_CONTRACT_TIMELOCK`], about two weeks
+const MAX_INVOICE_CLTV_EXPIRY_DELTA: u64 = 2016;
+
 // 24 hours. Many wallets default to 1 hour, but it's a bad user experience if
 // invoices expire too quickly
 const DEFAULT_INVOICE_EXPIRY_TIME: Duration = Duration::from_secs(60 * 60 * 24);
 
+/// How old the consensus block count used to compute contract timelocks may be
+const CONSENSUS_BLOCK_COUNT_MAX_AGE: Duration = Duration::from_secs(30);
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize, Encodable, Decodable)]
 #[serde(rename_all = "snake_case")]
 pub enum PayType {
@@ -322,6 +332,16 @@ impl ModuleInit for LightningClientInit {
                         "Lightning Gateways"
                     );
                 }
+                DbKeyPrefix::CancelledLnReceive => {
+                    push_db_pair_items!(
+                        dbtx,
+                        CancelledLnReceiveKeyPrefix,
+                        CancelledLnReceiveKey,
+                        (),
+                        ln_client_items,
+                        "Cancelled Lightning Receives"
+                    );
+                }
             }
         }
 
@@ -426,6 +446,17 @@ impl ClientModule for LightningClientModule {
         }
     }
 
+    fn provides_consensus_block_count(&self) -> bool {
+        true
+    }
+
+    async fn fetch_consensus_block_count(&self) -> anyhow::Result<u64> {
+        self.module_api
+            .fetch_consensus_block_count()
+            .await?
+            .ok_or(format_err!("Cannot get consensus block count"))
+    }
+
     #[cfg(feature = "cli")]
     async fn handle_cli_command(
         &self,
@@ -537,6 +568,11 @@ impl LightningClientModule {
             federation_currency,
             invoice_currency
         );
+        let invoice_cltv_delta = invoice.min_final_cltv_expiry_delta();
+        ensure!(
+            invoice_cltv_delta <= MAX_INVOICE_CLTV_EXPIRY_DELTA,
+            "Invoice min final CLTV expiry delta of {invoice_cltv_delta} blocks exceeds the maximum of {MAX_INVOICE_CLTV_EXPIRY_DELTA}"
+        );
 
         // Do not create the funding transaction if the gateway is not currently
         // available
@@ -545,11 +581,16 @@ impl LightningClientModule {
             .await?;
 
         let consensus_count = self
-            .module_api
-            .fetch_consensus_block_count()
-            .await?
-            .ok_or(format_err!("Cannot get consensus block count"))?;
-        let absolute_timelock = consensus_count + OUTGOING_LN_CONTRACT_TIMELOCK - 1;
+            .client_ctx
+            .consensus_block_count_cached(CONSENSUS_BLOCK_COUNT_MAX_AGE)
+            .await?;
+        let absolute_timelock = compute_safe_timelock(
+            consensus_count,
+            invoice_cltv_delta,
+            OUTGOING_LN_CONTRACT_TIMELOCK,
+        );
+        let absolute_timelock =
+            u32::try_from(absolute_timelock).context("Outgoing contract timelock overflows u32")?;
 
         // Compute amount to lock in the outgoing contract
         let invoice_amount = Amount::from_msats(
@@ -568,7 +609,7 @@ impl LightningClientModule {
         let contract = OutgoingContract {
             hash: payment_hash,
             gateway_key: gateway.gateway_redeem_key,
-            timelock: absolute_timelock as u32,
+            timelock: absolute_timelock,
             user_key: user_sk.public_key(),
             cancelled: false,
         };
@@ -673,18 +714,32 @@ impl LightningClientModule {
         operation_id: OperationId,
     ) -> Result<bool, LightningReceiveError> {
         let mut stream = self.notifier.subscribe(operation_id).await;
-        loop {
-            match stream.next().await {
-                Some(LightningClientStateMachines::Receive(state)) => match state.state {
-                    LightningReceiveStates::Funded(_) => return Ok(false),
-                    LightningReceiveStates::Success(outpoints) => return Ok(outpoints.is_empty()), /* if the outpoints are empty, it was an external receive */
-                    LightningReceiveStates::Canceled(e) => {
-                        return Err(e);
-                    }
-                    _ => {}
-                },
-                Some(_) | None => {}
+        let success = async {
+            loop {
+                match stream.next().await {
+                    Some(LightningClientStateMachines::Receive(state)) => match state.state {
+                        LightningReceiveStates::Funded(_) => return Ok(false),
+                        // If the outpoints are empty, it was an external receive
+                        LightningReceiveStates::Success(outpoints) => {
+                            return Ok(outpoints.is_empty())
+                        }
+                        LightningReceiveStates::Canceled(e) => {
+                            return Err(e);
+                        }
+                        _ => {}
+                    },
+                    Some(_) | None => {}
+                }
             }
+        };
+        let canceled = self
+            .client_ctx
+            .module_db()
+            .wait_key_exists(&CancelledLnReceiveKey(operation_id));
+
+        match select(pin!(success), pin!(canceled)).await {
+            Either::Left((result, _)) => result,
+            Either::Right(_) => Err(LightningReceiveError::Canceled),
         }
     }
 
@@ -1547,6 +1602,90 @@ impl LightningClientModule {
         }))
     }
 
+    /// Hides a receive started with
+    /// [`LightningClientModule::create_bolt11_invoice`] whose invoice hasn't
+    /// been paid yet, making its operation end in [`LnReceiveState::Canceled`]
+    ///
+    /// This only marks the operation in the client database, it doesn't
+    /// cancel anything: the offer can't be withdrawn from the federation, so
+    /// the invoice stays payable and the receive state machine keeps running
+    /// until the invoice expires, claiming the funds if a payment arrives.
+    ///
+    /// Fails if the invoice was already paid. That check isn't atomic with
+    /// marking the operation, so a payment arriving concurrently may still be
+    /// claimed into the balance of a receive reported as canceled.
+    pub async fn cancel_ln_receive(&self, operation_id: OperationId) -> anyhow::Result<()> {
+        let operation = self.client_ctx.get_operation(operation_id).await?;
+        let LightningOperationMetaVariant::Receive { invoice, .. } =
+            operation.meta::<LightningOperationMeta>().variant
+        else {
+            bail!("Operation is not a lightning receive")
+        };
+
+        let contract_id = (*invoice.payment_hash()).into();
+        if get_incoming_contract(self.module_api.clone(), contract_id)
+            .await?
+            .is_some()
+        {
+            bail!("Invoice was already paid, the receive can't be canceled anymore");
+        }
+
+        let mut dbtx = self.client_ctx.module_db().begin_transaction().await;
+        dbtx.insert_entry(&CancelledLnReceiveKey(operation_id), &())
+            .await;
+        if let Err(e) = dbtx.commit_tx_result().await {
+            warn!("Lightning receive was canceled multiple times concurrently: {e}");
+        }
+
+        Ok(())
+    }
+
+    /// Lists receives whose invoice hasn't been paid yet and that weren't
+    /// hidden with [`LightningClientModule::cancel_ln_receive`]
+    ///
+    /// Hiding a receive doesn't free any state: its state machine stays active
+    /// until the invoice expires, and the hidden marker is kept in the
+    /// database.
+    pub async fn list_pending_ln_receives(&self) -> Vec<PendingLnReceive> {
+        let mut dbtx = self.client_ctx.module_db().begin_transaction_nc().await;
+        let canceled = dbtx
+            .find_by_prefix(&CancelledLnReceiveKeyPrefix)
+            .await
+            .map(|(CancelledLnReceiveKey(operation_id), ())| operation_id)
+            .collect::<BTreeSet<_>>()
+            .await;
+
+        self.client_ctx
+            .get_own_active_states()
+            .await
+            .into_iter()
+            .filter(|(state, _)| !canceled.contains(&state.operation_id()))
+            .filter_map(|(state, _)| {
+                let LightningClientStateMachines::Receive(LightningReceiveStateMachine {
+                    operation_id,
+                    state,
+                }) = state
+                else {
+                    return None;
+                };
+
+                match state {
+                    LightningReceiveStates::SubmittedOffer(LightningReceiveSubmittedOffer {
+                        invoice,
+                        ..
+                    })
+                    | LightningReceiveStates::ConfirmedInvoice(
+                        LightningReceiveConfirmedInvoice { invoice, .. },
+                    ) => Some(PendingLnReceive {
+                        operation_id,
+                        invoice,
+                    }),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
     /// Returns a gateway to be used for a lightning operation. If
     /// `force_internal` is true and no `gateway_id` is specified, no
     /// gateway will be selected.
@@ -1797,6 +1936,14 @@ pub async fn create_incoming_contract_output(
     Ok((incoming_output, offer.amount, contract_id))
 }
 
+/// A receive whose invoice wasn't paid yet, see
+/// [`LightningClientModule::list_pending_ln_receives`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PendingLnReceive {
+    pub operation_id: OperationId,
+    pub invoice: Bolt11Invoice,
+}
+
 #[derive(Debug, Encodable, Decodable, Serialize)]
 pub struct OutgoingLightningPayment {
     pub payment_type: PayType,
@@ -1892,11 +2039,18 @@ pub async fn get_invoice(
             let response = async_client.make_request(&lnurl.url).await?;
             match response {
                 lnurl::LnUrlResponse::LnUrlPayResponse(response) => {
+                    check_lnurl_pay_amount(amount, response.min_sendable, response.max_sendable)?;
                     let invoice = async_client
                         .get_invoice(&response, amount.msats, None, lnurl_comment.as_deref())
                         .await?;
                     let invoice = Bolt11Invoice::from_str(invoice.invoice())?;
-                    assert_eq!(invoice.amount_milli_satoshis(), Some(amount.msats));
+                    if invoice.amount_milli_satoshis() != Some(amount.msats) {
+                        return Err(LnurlPayError::InvoiceAmountMismatch {
+                            requested: amount,
+                            invoice: invoice.amount_milli_satoshis().map(Amount::from_msats),
+                        }
+                        .into());
+                    }
                     Ok(invoice)
                 }
                 other => {
@@ -1907,6 +2061,41 @@ pub async fn get_invoice(
     }
 }
 
+#[derive(thiserror::Error, Debug, Clone, Eq, PartialEq)]
+pub enum LnurlPayError {
+    #[error(
+        "Amount {amount} is outside of the range {min} to {max} accepted by the lnurl service"
+    )]
+    AmountOutOfRange {
+        amount: Amount,
+        min: Amount,
+        max: Amount,
+    },
+    #[error("Lnurl service returned an invoice for {invoice:?} instead of {requested}")]
+    InvoiceAmountMismatch {
+        requested: Amount,
+        invoice: Option<Amount>,
+    },
+}
+
+/// Checks that `amount` lies within the bounds (in msat) an lnurl-pay service
+/// advertised before requesting an invoice from it
+fn check_lnurl_pay_amount(
+    amount: Amount,
+    min_sendable_msat: u64,
+    max_sendable_msat: u64,
+) -> Result<(), LnurlPayError> {
+    if amount.msats < min_sendable_msat || max_sendable_msat < amount.msats {
+        return Err(LnurlPayError::AmountOutOfRange {
+            amount,
+            min: Amount::from_msats(min_sendable_msat),
+            max: Amount::from_msats(max_sendable_msat),
+        });
+    }
+
+    Ok(())
+}
+
 #[derive(Debug, Clone)]
 pub struct LightningClientContext {
     pub ln_decoder: Decoder,
@@ -2025,3 +2214,33 @@ impl GatewayConnection for MockGatewayConnection {
         Ok("00000000".to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use fedimint_core::Amount;
+
+    use crate::{check_lnurl_pay_amount, LnurlPayError};
+
+    #[test]
+    fn lnurl_pay_amount_bounds_are_inclusive() {
+        assert_eq!(
+            check_lnurl_pay_amount(Amount::from_msats(1_000), 1_000, 5_000),
+            Ok(())
+        );
+        assert_eq!(
+            check_lnurl_pay_amount(Amount::from_msats(5_000), 1_000, 5_000),
+            Ok(())
+        );
+
+        for msats in [999, 5_001] {
+            assert_eq!(
+                check_lnurl_pay_amount(Amount::from_msats(msats), 1_000, 5_000),
+                Err(LnurlPayError::AmountOutOfRange {
+                    amount: Amount::from_msats(msats),
+                    min: Amount::from_msats(1_000),
+                    max: Amount::from_msats(5_000),
+                })
+            );
+        }
+    }
+}
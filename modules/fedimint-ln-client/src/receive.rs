@@ -116,6 +116,8 @@ pub enum LightningReceiveError {
     ClaimRejected,
     #[error("The decrypted preimage was invalid")]
     InvalidPreimage,
+    #[error("Receive was canceled by the user before the invoice was paid")]
+    Canceled,
 }
 
 impl LightningReceiveSubmittedOffer {
@@ -742,3 +742,75 @@ pub fn create_gateway_remove_message(
     message_preimage.append(&mut challenge.consensus_encode_to_vec());
     Message::from_hashed_data::<sha256::Hash>(message_preimage.as_slice())
 }
+
+/// Computes the absolute block height at which an outgoing contract created
+/// at `consensus_block_count` should time out, so that the gateway has at least
+/// `invoice_cltv_delta` blocks to route the payment and the user is left with
+/// `safety_margin` blocks on top of that.
+///
+/// The gateway checks incoming contracts with [`outgoing_contract_max_delay`]
+/// which uses the same math, so a contract created with a safety margin of at
+/// least the gateway's timelock delta is never rejected as too close.
+pub fn compute_safe_timelock(
+    consensus_block_count: u64,
+    invoice_cltv_delta: u64,
+    safety_margin: u64,
+) -> u64 {
+    consensus_block_count
+        .saturating_sub(1)
+        .saturating_add(invoice_cltv_delta)
+        .saturating_add(safety_margin)
+}
+
+/// Returns the maximum number of blocks a gateway may let the payment of an
+/// outgoing contract with the absolute `timelock` take, keeping
+/// `gateway_timelock_delta` blocks to claim the contract. Returns `None` if the
+/// timeout of the contract is too close.
+pub fn outgoing_contract_max_delay(
+    timelock: u64,
+    consensus_block_count: u64,
+    gateway_timelock_delta: u64,
+) -> Option<u64> {
+    timelock
+        .checked_sub(consensus_block_count.saturating_sub(1))
+        .and_then(|delta| delta.checked_sub(gateway_timelock_delta))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compute_safe_timelock, outgoing_contract_max_delay};
+
+    #[test]
+    fn safe_timelock_leaves_cltv_delta_and_margin() {
+        assert_eq!(compute_safe_timelock(1000, 18, 500), 1517);
+        assert_eq!(compute_safe_timelock(0, 18, 500), 518);
+        assert_eq!(compute_safe_timelock(u64::MAX, 18, 500), u64::MAX);
+    }
+
+    #[test]
+    fn safe_timelock_boundary_with_gateway_delta() {
+        let block_count = 1000;
+        let timelock = compute_safe_timelock(block_count, 18, 10);
+
+        assert_eq!(
+            outgoing_contract_max_delay(timelock, block_count, 27),
+            Some(1)
+        );
+        assert_eq!(
+            outgoing_contract_max_delay(timelock, block_count, 28),
+            Some(0)
+        );
+        // The gateway's delta exceeds what the margin left room for
+        assert_eq!(outgoing_contract_max_delay(timelock, block_count, 29), None);
+
+        // Blocks passing since creation eat into the margin
+        assert_eq!(
+            outgoing_contract_max_delay(timelock, block_count + 1, 28),
+            None
+        );
+        assert_eq!(
+            outgoing_contract_max_delay(timelock, block_count + 1, 27),
+            Some(0)
+        );
+    }
+}
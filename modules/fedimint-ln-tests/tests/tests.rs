@@ -1,28 +1,38 @@
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 
 use assert_matches::assert_matches;
+use axum::routing::get;
+use axum::Json;
+use bitcoin_hashes::{sha256, Hash};
 use fedimint_client::Client;
 use fedimint_core::util::NextOrPending;
 use fedimint_core::{sats, Amount};
 use fedimint_dummy_client::{DummyClientInit, DummyClientModule};
 use fedimint_dummy_common::config::DummyGenParams;
 use fedimint_dummy_server::DummyInit;
+use fedimint_ln_client::receive::LightningReceiveError;
 use fedimint_ln_client::{
-    InternalPayState, LightningClientInit, LightningClientModule, LightningOperationMeta,
-    LnPayState, LnReceiveState, MockGatewayConnection, OutgoingLightningPayment, PayType,
-    RealGatewayConnection,
+    get_invoice, InternalPayState, LightningClientInit, LightningClientModule,
+    LightningOperationMeta, LnPayState, LnReceiveState, LnurlPayError, MockGatewayConnection,
+    OutgoingLightningPayment, PayType, RealGatewayConnection,
 };
 use fedimint_ln_common::config::LightningGenParams;
+use fedimint_ln_common::federation_endpoint_constants::BLOCK_COUNT_ENDPOINT;
 use fedimint_ln_common::ln_operation;
 use fedimint_ln_server::LightningInit;
+use fedimint_testing::api::{CallPattern, FaultAction, FaultInjector};
 use fedimint_testing::federation::FederationTest;
 use fedimint_testing::fixtures::Fixtures;
 use fedimint_testing::gateway::{GatewayTest, DEFAULT_GATEWAY_PASSWORD};
 use fedimint_testing::ln::FakeLightningTest;
-use lightning_invoice::{Bolt11Invoice, Bolt11InvoiceDescription, Description};
+use lightning_invoice::{
+    Bolt11Invoice, Bolt11InvoiceDescription, Currency, Description, InvoiceBuilder, PaymentSecret,
+};
+use lnurl::lnurl::LnUrl;
 use rand::rngs::OsRng;
-use secp256k1::KeyPair;
+use secp256k1::{KeyPair, Secp256k1, SecretKey};
 
 fn fixtures() -> Fixtures {
     let fixtures = Fixtures::new_primary(DummyClientInit, DummyInit, DummyGenParams::default());
@@ -121,6 +131,207 @@ async fn test_can_attach_extra_meta_to_receive_operation() -> anyhow::Result<()>
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn can_only_cancel_unpaid_receive() -> anyhow::Result<()> {
+    let fixtures = fixtures();
+    let fed = fixtures.new_default_fed().await;
+    let (client1, client2) = fed.two_clients().await;
+    let client1_ln_module = client1.get_first_module::<LightningClientModule>();
+    let client2_dummy_module = client2.get_first_module::<DummyClientModule>();
+
+    let (op, outpoint) = client2_dummy_module.print_money(sats(1000)).await?;
+    client2.await_primary_module_output(op, outpoint).await?;
+
+    // An unpaid invoice can be canceled
+    let desc = Description::new("canceled".to_string())?;
+    let (op, _, _) = client1_ln_module
+        .create_bolt11_invoice(
+            sats(250),
+            Bolt11InvoiceDescription::Direct(&desc),
+            None,
+            (),
+            None,
+        )
+        .await?;
+    let mut sub = client1_ln_module
+        .subscribe_ln_receive(op)
+        .await?
+        .into_stream();
+    assert_eq!(sub.ok().await?, LnReceiveState::Created);
+    assert_matches!(sub.ok().await?, LnReceiveState::WaitingForPayment { .. });
+
+    client1_ln_module.cancel_ln_receive(op).await?;
+    assert_eq!(
+        sub.ok().await?,
+        LnReceiveState::Canceled {
+            reason: LightningReceiveError::Canceled
+        }
+    );
+
+    // A paid invoice can't be canceled anymore
+    let desc = Description::new("paid".to_string())?;
+    let (op, invoice, _) = client1_ln_module
+        .create_bolt11_invoice(
+            sats(250),
+            Bolt11InvoiceDescription::Direct(&desc),
+            None,
+            (),
+            None,
+        )
+        .await?;
+    let mut sub = client1_ln_module
+        .subscribe_ln_receive(op)
+        .await?
+        .into_stream();
+    assert_eq!(sub.ok().await?, LnReceiveState::Created);
+    assert_matches!(sub.ok().await?, LnReceiveState::WaitingForPayment { .. });
+
+    pay_invoice(&client2, invoice, None).await?;
+    assert_eq!(sub.ok().await?, LnReceiveState::Funded);
+
+    assert!(client1_ln_module.cancel_ln_receive(op).await.is_err());
+    assert_eq!(sub.ok().await?, LnReceiveState::AwaitingFunds);
+    assert_eq!(sub.ok().await?, LnReceiveState::Claimed);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn lists_and_cancels_abandoned_receives() -> anyhow::Result<()> {
+    let fixtures = fixtures();
+    let fed = fixtures.new_default_fed().await;
+    let (client1, client2) = fed.two_clients().await;
+    let client1_ln_module = client1.get_first_module::<LightningClientModule>();
+    let client2_dummy_module = client2.get_first_module::<DummyClientModule>();
+
+    let (op, outpoint) = client2_dummy_module.print_money(sats(1000)).await?;
+    client2.await_primary_module_output(op, outpoint).await?;
+
+    let mut receives = Vec::new();
+    for i in 0..3 {
+        let desc = Description::new(format!("offer-{i}"))?;
+        let (op, invoice, _) = client1_ln_module
+            .create_bolt11_invoice(
+                sats(100),
+                Bolt11InvoiceDescription::Direct(&desc),
+                None,
+                (),
+                None,
+            )
+            .await?;
+        let mut sub = client1_ln_module
+            .subscribe_ln_receive(op)
+            .await?
+            .into_stream();
+        assert_eq!(sub.ok().await?, LnReceiveState::Created);
+        assert_matches!(sub.ok().await?, LnReceiveState::WaitingForPayment { .. });
+        receives.push((op, invoice, sub));
+    }
+
+    let pending = client1_ln_module.list_pending_ln_receives().await;
+    assert_eq!(pending.len(), 3);
+    for (op, invoice, _) in &receives {
+        assert!(pending
+            .iter()
+            .any(|receive| receive.operation_id == *op && receive.invoice == *invoice));
+    }
+
+    // Fund the first offer, which protects it from being canceled
+    let (paid_op, paid_invoice, mut paid_sub) = receives.remove(0);
+    pay_invoice(&client2, paid_invoice, None).await?;
+    assert_eq!(paid_sub.ok().await?, LnReceiveState::Funded);
+    assert!(client1_ln_module.cancel_ln_receive(paid_op).await.is_err());
+    assert_eq!(paid_sub.ok().await?, LnReceiveState::AwaitingFunds);
+    assert_eq!(paid_sub.ok().await?, LnReceiveState::Claimed);
+
+    let pending = client1_ln_module.list_pending_ln_receives().await;
+    assert_eq!(pending.len(), 2);
+    assert!(pending
+        .iter()
+        .all(|receive| receive.operation_id != paid_op));
+
+    // Hide the abandoned ones
+    for (op, _, mut sub) in receives {
+        client1_ln_module.cancel_ln_receive(op).await?;
+        assert_eq!(
+            sub.ok().await?,
+            LnReceiveState::Canceled {
+                reason: LightningReceiveError::Canceled
+            }
+        );
+    }
+    assert!(client1_ln_module
+        .list_pending_ln_receives()
+        .await
+        .is_empty());
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn claims_payment_of_canceled_receive() -> anyhow::Result<()> {
+    let fixtures = fixtures();
+    let fed = fixtures.new_default_fed().await;
+    let (client1, client2) = fed.two_clients().await;
+    let client1_ln_module = client1.get_first_module::<LightningClientModule>();
+    let client2_dummy_module = client2.get_first_module::<DummyClientModule>();
+
+    let (op, outpoint) = client2_dummy_module.print_money(sats(1000)).await?;
+    client2.await_primary_module_output(op, outpoint).await?;
+
+    let desc = Description::new("canceled".to_string())?;
+    let (op, invoice, _) = client1_ln_module
+        .create_bolt11_invoice(
+            sats(250),
+            Bolt11InvoiceDescription::Direct(&desc),
+            None,
+            (),
+            None,
+        )
+        .await?;
+    let mut sub1 = client1_ln_module
+        .subscribe_ln_receive(op)
+        .await?
+        .into_stream();
+    assert_eq!(sub1.ok().await?, LnReceiveState::Created);
+    assert_matches!(sub1.ok().await?, LnReceiveState::WaitingForPayment { .. });
+
+    client1_ln_module.cancel_ln_receive(op).await?;
+    assert_eq!(
+        sub1.ok().await?,
+        LnReceiveState::Canceled {
+            reason: LightningReceiveError::Canceled
+        }
+    );
+
+    // The offer stays registered with the federation, so paying the invoice
+    // anyway succeeds and the funds are not lost
+    let mut balance_sub = client1.subscribe_balance_changes().await;
+    assert_eq!(balance_sub.ok().await?, sats(0));
+    let OutgoingLightningPayment { payment_type, .. } =
+        pay_invoice(&client2, invoice, None).await?;
+    let PayType::Internal(op_id) = payment_type else {
+        panic!("Expected internal payment!");
+    };
+    let mut sub2 = client2
+        .get_first_module::<LightningClientModule>()
+        .subscribe_internal_pay(op_id)
+        .await?
+        .into_stream();
+    assert_eq!(sub2.ok().await?, InternalPayState::Funding);
+    assert_matches!(sub2.ok().await?, InternalPayState::Preimage { .. });
+
+    // The receiver claims the payment even though the receive was canceled
+    assert_eq!(balance_sub.ok().await?, sats(250));
+    assert_eq!(client1.get_balance().await, sats(250));
+    assert!(client1_ln_module
+        .list_pending_ln_receives()
+        .await
+        .is_empty());
+
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn cannot_pay_same_internal_invoice_twice() -> anyhow::Result<()> {
     let fixtures = fixtures();
@@ -346,6 +557,39 @@ async fn cannot_pay_same_external_invoice_twice() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn rejects_invoice_with_excessive_cltv_delta() -> anyhow::Result<()> {
+    let fixtures = fixtures();
+    let fed = fixtures.new_default_fed().await;
+    let gw = gateway(&fixtures, &fed).await;
+    let client = fed.new_client().await;
+    let dummy_module = client.get_first_module::<DummyClientModule>();
+
+    let (op, outpoint) = dummy_module.print_money(sats(1000)).await?;
+    client.await_primary_module_output(op, outpoint).await?;
+
+    // Locking funds for more than two weeks on top of our own timelock is refused
+    let ctx = Secp256k1::new();
+    let kp = KeyPair::new(&ctx, &mut OsRng);
+    let invoice = InvoiceBuilder::new(Currency::Regtest)
+        .payee_pub_key(kp.public_key())
+        .description("excessive cltv delta".to_string())
+        .payment_hash(sha256::Hash::hash(&[1; 32]))
+        .current_timestamp()
+        .min_final_cltv_expiry_delta(2017)
+        .payment_secret(PaymentSecret([1; 32]))
+        .amount_milli_satoshis(100_000)
+        .build_signed(|m| ctx.sign_ecdsa_recoverable(m, &SecretKey::from_keypair(&kp)))?;
+
+    let error = pay_invoice(&client, invoice, Some(gw.gateway.gateway_id()))
+        .await
+        .expect_err("Invoice should have been rejected");
+    assert!(error.to_string().contains("CLTV expiry delta"), "{error}");
+    assert_eq!(client.get_balance().await, sats(1000));
+
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn makes_internal_payments_within_federation() -> anyhow::Result<()> {
     let fixtures = fixtures();
@@ -678,6 +922,83 @@ async fn rejects_wrong_network_invoice() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Serves an lnurl-pay endpoint on localhost whose callback on
+/// `callback_host` returns `invoice`, returning the endpoint's lnurl
+async fn serve_lnurl_pay(invoice: Bolt11Invoice) -> anyhow::Result<String> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let port = listener.local_addr()?.port();
+
+    let pay_response = serde_json::json!({
+        "tag": "payRequest",
+        "callback": format!("http://127.0.0.1:{port}/callback"),
+        "minSendable": 1_000,
+        "maxSendable": 1_000_000_000,
+        "metadata": "[[\"text/plain\",\"fedimint test\"]]",
+    });
+    let invoice_response = serde_json::json!({
+        "pr": invoice.to_string(),
+        "routes": [],
+    });
+    let router = axum::Router::new()
+        .route("/lnurlp", get(|| async move { Json(pay_response) }))
+        .route("/callback", get(|| async move { Json(invoice_response) }));
+    tokio::spawn(async move { axum::serve(listener, router).await });
+
+    Ok(LnUrl::from_url(format!("http://127.0.0.1:{port}/lnurlp")).encode())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn gets_invoice_from_lnurl() -> anyhow::Result<()> {
+    let invoice = FakeLightningTest::new().invoice(sats(100), None)?;
+    let lnurl = serve_lnurl_pay(invoice.clone()).await?;
+
+    assert_eq!(get_invoice(&lnurl, Some(sats(100)), None).await?, invoice);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rejects_lnurl_amount_out_of_range() -> anyhow::Result<()> {
+    let invoice = FakeLightningTest::new().invoice(sats(100), None)?;
+    let lnurl = serve_lnurl_pay(invoice).await?;
+
+    let error = get_invoice(&lnurl, Some(sats(2_000_000)), None)
+        .await
+        .expect_err("Amount is above the lnurl service's maxSendable");
+    assert_matches!(
+        error.downcast_ref::<LnurlPayError>(),
+        Some(LnurlPayError::AmountOutOfRange { .. })
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn concurrent_block_count_calls_share_one_request() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let block_count_calls = CallPattern::any().with_method(BLOCK_COUNT_ENDPOINT);
+    // Keep the first request in flight while the other calls are made
+    let faults = FaultInjector::new(vec![(
+        block_count_calls.clone(),
+        FaultAction::Delay(Duration::from_millis(200)),
+    )]);
+    let client = fed.new_client_with_faults(&faults).await;
+
+    let block_counts = futures::future::join_all(
+        (0..10).map(|_| client.consensus_block_count_cached(Duration::from_secs(60))),
+    )
+    .await
+    .into_iter()
+    .collect::<anyhow::Result<Vec<_>>>()?;
+    assert!(block_counts.windows(2).all(|pair| pair[0] == pair[1]));
+
+    // A single federation request reaches every guardian at most once
+    let num_peers = client.get_config().global.api_endpoints.len();
+    assert!(faults.call_count(&block_count_calls) <= num_peers);
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod fedimint_migration_tests {
     use std::str::FromStr;
@@ -1386,6 +1707,10 @@ mod fedimint_migration_tests {
                             );
                             info!("Validated LightningGateways");
                         }
+                        fedimint_ln_client::db::DbKeyPrefix::CancelledLnReceive => {
+                            // Only written when a user cancels a receive, which no database
+                            // snapshot does
+                        }
                     }
                 }
 
@@ -0,0 +1,27 @@
+use fedimint_api_client::api::{FederationApiExt, FederationResult, IModuleFederationApi};
+use fedimint_core::module::ApiRequestErased;
+use fedimint_core::task::{MaybeSend, MaybeSync};
+use fedimint_core::{apply, async_trait_maybe_send};
+use fedimint_mint_common::endpoint_constants::NOTES_SPENT_ENDPOINT;
+use fedimint_mint_common::Nonce;
+
+#[apply(async_trait_maybe_send!)]
+pub trait MintFederationApi {
+    /// Returns for every nonce whether the federation has already seen the
+    /// corresponding note being spent
+    async fn check_notes_spent(&self, nonces: Vec<Nonce>) -> FederationResult<Vec<bool>>;
+}
+
+#[apply(async_trait_maybe_send!)]
+impl<T: ?Sized> MintFederationApi for T
+where
+    T: IModuleFederationApi + MaybeSend + MaybeSync + 'static,
+{
+    async fn check_notes_spent(&self, nonces: Vec<Nonce>) -> FederationResult<Vec<bool>> {
+        self.request_current_consensus(
+            NOTES_SPENT_ENDPOINT.to_string(),
+            ApiRequestErased::new(nonces),
+        )
+        .await
+    }
+}
@@ -9,6 +9,8 @@
 #![allow(clippy::must_use_candidate)]
 #![allow(clippy::return_self_not_must_use)]
 
+/// Federation API extensions of the mint module
+pub mod api;
 // Backup and restore logic
 pub mod backup;
 /// Database keys used throughout the mint client module
@@ -35,6 +37,7 @@ use backup::recovery::MintRecovery;
 use base64::Engine as _;
 use bitcoin_hashes::{sha256, sha256t, Hash, HashEngine as BitcoinHashEngine};
 use client_db::{DbKeyPrefix, NoteKeyPrefix};
+use fedimint_api_client::api::DynModuleApi;
 use fedimint_client::module::init::{
     ClientModuleInit, ClientModuleInitArgs, ClientModuleRecoverArgs,
 };
@@ -75,6 +78,7 @@ use tbs::{AggregatePublicKey, Signature};
 use thiserror::Error;
 use tracing::{debug, warn};
 
+use crate::api::MintFederationApi;
 use crate::backup::EcashBackup;
 use crate::client_db::{
     CancelledOOBSpendKey, CancelledOOBSpendKeyPrefix, NextECashNoteIndexKey,
@@ -505,6 +509,12 @@ pub enum MintOperationMetaVariant {
         requested_amount: Amount,
         oob_notes: OOBNotes,
     },
+    /// Reissuance split into several transactions, see
+    /// [`MintClientModule::reissue_notes_batched`]
+    BatchReissuance {
+        /// Id and change output indices of every transaction of the batch
+        transactions: Vec<(TransactionId, Vec<u64>)>,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -574,6 +584,10 @@ impl ClientModuleInit for MintClientInit {
             .expect("no version conflicts")
     }
 
+    fn supports_being_primary(&self) -> bool {
+        true
+    }
+
     async fn init(&self, args: &ClientModuleInitArgs<Self>) -> anyhow::Result<Self::Module> {
         Ok(MintClientModule {
             federation_id: *args.federation_id(),
@@ -582,6 +596,8 @@ impl ClientModuleInit for MintClientInit {
             secp: Secp256k1::new(),
             notifier: args.notifier().clone(),
             client_ctx: args.context(),
+            module_api: args.module_api().clone(),
+            note_split_policy: std::sync::RwLock::new(NoteSplitPolicy::default()),
         })
     }
 
@@ -627,6 +643,36 @@ pub struct MintClientModule {
     secp: Secp256k1<All>,
     notifier: ModuleNotifier<MintClientStateMachines>,
     client_ctx: ClientContext<Self>,
+    module_api: DynModuleApi,
+    note_split_policy: std::sync::RwLock<NoteSplitPolicy>,
+}
+
+/// Controls how the mint client splits amounts it receives, e.g. as change,
+/// into e-cash notes. Holding more notes of every denomination gives more
+/// privacy when spending, holding fewer notes keeps the database small and
+/// spending fast.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub struct NoteSplitPolicy {
+    /// Number of notes of each denomination the client tries to hold
+    pub denomination_sets: u16,
+    /// Maximum number of notes of a single denomination produced by one
+    /// issuance. The largest denomination is exempt, since it has to absorb
+    /// amounts that can't be represented otherwise. Amounts that can't be
+    /// represented within the cap at all exceed it.
+    pub max_notes_per_denomination: u16,
+    /// Once the client holds this many notes, new notes are issued using as
+    /// few notes as possible instead of filling up `denomination_sets`
+    pub target_total_notes: Option<usize>,
+}
+
+impl Default for NoteSplitPolicy {
+    fn default() -> Self {
+        Self {
+            denomination_sets: 2,
+            max_notes_per_denomination: u16::MAX,
+            target_total_notes: None,
+        }
+    }
 }
 
 // TODO: wrap in Arc
@@ -788,10 +834,19 @@ impl ClientModule for MintClientModule {
             .note_spend_abs
             .mul_u64(inputs.len() as u64);
 
-        let missing_output = (input + selected_input_amount) - (output + selected_input_fee);
+        let missing_output = input
+            .checked_add(selected_input_amount)
+            .zip(output.checked_add(selected_input_fee))
+            .and_then(|(total_input, total_output)| total_input.checked_sub(total_output))
+            .context("Selected notes do not cover the outputs and fees")?;
 
         let outputs = self
-            .create_exact_output(dbtx, operation_id, 2, missing_output)
+            .create_exact_output(
+                dbtx,
+                operation_id,
+                self.note_split_policy().denomination_sets,
+                missing_output,
+            )
             .await;
 
         Ok((inputs, outputs))
@@ -856,7 +911,43 @@ pub enum ReissueExternalNotesError {
     AlreadyReissued,
 }
 
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum EcashError {
+    #[error("{spent} of the {total} notes were already spent")]
+    AlreadySpent { spent: usize, total: usize },
+}
+
 impl MintClientModule {
+    /// Asks the federation whether any of the `notes` was spent already, so
+    /// importing double-spent e-cash fails with [`EcashError::AlreadySpent`]
+    /// before an operation is created. If the federation can't answer, e.g.
+    /// because it doesn't support the endpoint yet, the check is skipped.
+    async fn ensure_notes_unspent(&self, notes: &TieredMulti<SpendableNote>) -> anyhow::Result<()> {
+        let nonces = notes
+            .iter_items()
+            .map(|(_, note)| note.nonce())
+            .collect::<Vec<_>>();
+        let total = nonces.len();
+
+        match self.module_api.check_notes_spent(nonces).await {
+            Ok(spent) => {
+                let spent = spent.into_iter().filter(|spent| *spent).count();
+                if spent != 0 {
+                    bail!(EcashError::AlreadySpent { spent, total });
+                }
+            }
+            Err(e) => {
+                warn!(
+                    target: LOG_CLIENT_MODULE_MINT,
+                    err = %e,
+                    "Could not check whether notes were spent already"
+                );
+            }
+        }
+
+        Ok(())
+    }
+
     async fn create_sufficient_input(
         &self,
         dbtx: &mut DatabaseTransaction<'_>,
@@ -901,6 +992,15 @@ impl MintClientModule {
             .await
     }
 
+    /// Returns how many e-cash notes of each denomination are held, e.g. to
+    /// show the denomination breakdown of the balance
+    pub async fn notes_by_denomination(&self) -> BTreeMap<Amount, usize> {
+        self.get_notes_tier_counts(&mut self.client_ctx.module_db().begin_transaction_nc().await)
+            .await
+            .iter()
+            .collect()
+    }
+
     /// Pick [`SpendableNote`]s by given counts, when available
     ///
     /// Return the notes picked, and counts of notes that were not available.
@@ -926,10 +1026,29 @@ impl MintClientModule {
             .await
     }
 
+    /// Returns the policy used to split received amounts into e-cash notes
+    pub fn note_split_policy(&self) -> NoteSplitPolicy {
+        *self
+            .note_split_policy
+            .read()
+            .expect("Note split policy lock poisoned")
+    }
+
+    /// Sets the policy used to split received amounts into e-cash notes. The
+    /// policy isn't persisted and has to be set again after reopening the
+    /// client.
+    pub fn set_note_split_policy(&self, policy: NoteSplitPolicy) {
+        *self
+            .note_split_policy
+            .write()
+            .expect("Note split policy lock poisoned") = policy;
+    }
+
     // TODO: put "notes per denomination" default into cfg
     /// Creates a mint output with exactly the given `amount`, issuing e-cash
     /// notes such that the client holds `notes_per_denomination` notes of each
-    /// e-cash note denomination held.
+    /// e-cash note denomination held. The remaining limits of the
+    /// [`NoteSplitPolicy`] apply as well.
     pub async fn create_exact_output(
         &self,
         dbtx: &mut DatabaseTransaction<'_>,
@@ -941,11 +1060,14 @@ impl MintClientModule {
             return Vec::new();
         }
 
-        let denominations = represent_amount(
+        let denominations = represent_amount_with_policy(
             exact_amount,
             &self.get_notes_tier_counts(dbtx).await,
             &self.cfg.tbs_pks,
-            notes_per_denomination,
+            &NoteSplitPolicy {
+                denomination_sets: notes_per_denomination,
+                ..self.note_split_policy()
+            },
         );
 
         let mut outputs = Vec::new();
@@ -1350,6 +1472,11 @@ impl MintClientModule {
                 .to_byte_array(),
         );
 
+        if self.client_ctx.operation_exists(operation_id).await {
+            bail!(ReissueExternalNotesError::AlreadyReissued);
+        }
+        self.ensure_notes_unspent(&notes).await?;
+
         let amount = notes.total_amount();
         let mint_input = self.create_input_from_notes(operation_id, notes)?;
 
@@ -1391,14 +1518,105 @@ impl MintClientModule {
         Ok(operation_id)
     }
 
+    /// Like [`MintClientModule::reissue_external_notes`], but splits the notes
+    /// into transactions of at most `batch_size` inputs, e.g. to stay within
+    /// the maximum transaction size when reissuing many notes. Every
+    /// transaction has to pay its own fees. All transactions are submitted at
+    /// once as part of a single operation, whose progress can be observed
+    /// using [`MintClientModule::subscribe_reissue_external_notes`].
+    pub async fn reissue_notes_batched<M: Serialize + Send>(
+        &self,
+        oob_notes: OOBNotes,
+        batch_size: usize,
+        extra_meta: M,
+    ) -> anyhow::Result<OperationId> {
+        let notes = oob_notes.notes().clone();
+        let federation_id_prefix = oob_notes.federation_id_prefix();
+
+        ensure!(batch_size > 0, "Batch size has to be at least one note");
+        ensure!(
+            notes.total_amount() > Amount::ZERO,
+            "Reissuing zero-amount e-cash isn't supported"
+        );
+
+        if federation_id_prefix != self.federation_id.to_prefix() {
+            bail!(ReissueExternalNotesError::WrongFederationId);
+        }
+
+        let operation_id = OperationId(
+            notes
+                .consensus_hash::<sha256t::Hash<OOBReissueTag>>()
+                .to_byte_array(),
+        );
+
+        if self.client_ctx.operation_exists(operation_id).await {
+            bail!(ReissueExternalNotesError::AlreadyReissued);
+        }
+        self.ensure_notes_unspent(&notes).await?;
+
+        let amount = notes.total_amount();
+        let mint_inputs = self.create_input_from_notes(operation_id, notes)?;
+
+        // Notes are ordered by ascending denomination, distribute them round-robin
+        // starting with the largest ones so every transaction can pay its own fees
+        let num_batches = mint_inputs.len().div_ceil(batch_size);
+        let mut batches = (0..num_batches).map(|_| vec![]).collect::<Vec<_>>();
+        for (idx, mint_input) in mint_inputs.into_iter().rev().enumerate() {
+            batches[idx % num_batches].push(mint_input);
+        }
+        let txs = batches
+            .into_iter()
+            .map(|batch| {
+                TransactionBuilder::new().with_inputs(self.client_ctx.map_dyn(batch).collect())
+            })
+            .collect();
+
+        let extra_meta = serde_json::to_value(extra_meta)
+            .expect("MintClientModule::reissue_notes_batched extra_meta is serializable");
+        let operation_meta_gen =
+            |transactions: Vec<(TransactionId, Vec<OutPoint>)>| MintOperationMeta {
+                variant: MintOperationMetaVariant::BatchReissuance {
+                    transactions: transactions
+                        .into_iter()
+                        .map(|(txid, out_points)| {
+                            assert!(
+                                out_points.iter().all(|out_point| out_point.txid == txid),
+                                "Change outpoints didn't all have consistent transaction id."
+                            );
+                            let out_point_indices = out_points
+                                .iter()
+                                .map(|out_point| out_point.out_idx)
+                                .collect();
+                            (txid, out_point_indices)
+                        })
+                        .collect(),
+                },
+                amount,
+                extra_meta: extra_meta.clone(),
+            };
+
+        self.client_ctx
+            .finalize_and_submit_transactions(
+                operation_id,
+                MintCommonInit::KIND.as_str(),
+                operation_meta_gen,
+                txs,
+            )
+            .await
+            .context(ReissueExternalNotesError::AlreadyReissued)?;
+
+        Ok(operation_id)
+    }
+
     /// Subscribe to updates on the progress of a reissue operation started with
-    /// [`MintClientModule::reissue_external_notes`].
+    /// [`MintClientModule::reissue_external_notes`] or
+    /// [`MintClientModule::reissue_notes_batched`].
     pub async fn subscribe_reissue_external_notes(
         &self,
         operation_id: OperationId,
     ) -> anyhow::Result<UpdateStreamOrOutcome<ReissueExternalNotesState>> {
         let operation = self.mint_operation(operation_id).await?;
-        let (txid, out_points) = match operation.meta::<MintOperationMeta>().variant {
+        let (txids, out_points) = match operation.meta::<MintOperationMeta>().variant {
             MintOperationMetaVariant::Reissuance {
                 legacy_out_point,
                 txid,
@@ -1416,7 +1634,20 @@ impl MintClientModule {
                     .chain(legacy_out_point)
                     .collect::<Vec<_>>();
 
-                (txid, out_points)
+                (vec![txid], out_points)
+            }
+            MintOperationMetaVariant::BatchReissuance { transactions } => {
+                let txids = transactions.iter().map(|(txid, _)| *txid).collect();
+                let out_points = transactions
+                    .into_iter()
+                    .flat_map(|(txid, out_point_indices)| {
+                        out_point_indices
+                            .into_iter()
+                            .map(move |out_idx| OutPoint { txid, out_idx })
+                    })
+                    .collect::<Vec<_>>();
+
+                (txids, out_points)
             }
             MintOperationMetaVariant::SpendOOB { .. } => bail!("Operation is not a reissuance"),
         };
@@ -1427,20 +1658,18 @@ impl MintClientModule {
             stream! {
                 yield ReissueExternalNotesState::Created;
 
-                match client_ctx
-                    .transaction_updates(operation_id)
-                    .await
-                    .await_tx_accepted(txid)
-                    .await
-                {
-                    Ok(()) => {
-                        yield ReissueExternalNotesState::Issuing;
-                    }
-                    Err(e) => {
+                for txid in txids {
+                    if let Err(e) = client_ctx
+                        .transaction_updates(operation_id)
+                        .await
+                        .await_tx_accepted(txid)
+                        .await
+                    {
                         yield ReissueExternalNotesState::Failed(format!("Transaction not accepted {e:?}"));
                         return;
                     }
                 }
+                yield ReissueExternalNotesState::Issuing;
 
                 for out_point in out_points {
                     if let Err(e) = client_ctx.self_ref().await_output_finalized(operation_id, out_point).await {
@@ -1481,7 +1710,8 @@ impl MintClientModule {
         .await
     }
 
-    /// Same as `spend_notes` but allows different to select notes to be used.
+    /// Same as `spend_notes` but allows different to select notes to be used,
+    /// e.g. one of the [`NoteSelectionStrategy`]s.
     pub async fn spend_notes_with_selector<M: Serialize + Send>(
         &self,
         notes_selector: &impl NotesSelector,
@@ -1754,6 +1984,155 @@ impl<Note: Send> NotesSelector<Note> for SelectNotesWithExactAmount {
     }
 }
 
+/// Which notes to spend for a given amount, selectable per spend by passing it
+/// to [`MintClientModule::spend_notes_with_selector`]
+///
+/// All strategies ignore notes not worth more than the per-note input fee and
+/// return at least the requested amount plus fees.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum NoteSelectionStrategy {
+    /// Spend as few notes as possible. Among selections of that size the one
+    /// with the least change is preferred.
+    MinimizeNotes,
+    /// Spend the smallest notes first and drop the ones that turn out not to
+    /// be needed, keeping the change small and consolidating small notes.
+    MinimizeChange,
+    /// Represent the amount exactly, starting from the largest denominations,
+    /// and fall back to a single bigger note if that is not possible. This is
+    /// what [`SelectNotesWithAtleastAmount`] does.
+    #[default]
+    PreferExactDenominations,
+}
+
+#[apply(async_trait_maybe_send!)]
+impl<Note: Send> NotesSelector<Note> for NoteSelectionStrategy {
+    async fn select_notes(
+        &self,
+        #[cfg(not(target_family = "wasm"))] stream: impl futures::Stream<Item = (Amount, Note)> + Send,
+        #[cfg(target_family = "wasm")] stream: impl futures::Stream<Item = (Amount, Note)>,
+        requested_amount: Amount,
+        fee_per_note_input: Amount,
+    ) -> anyhow::Result<TieredMulti<Note>> {
+        let notes = match self {
+            NoteSelectionStrategy::MinimizeNotes => {
+                select_fewest_notes(stream.collect().await, requested_amount, fee_per_note_input)?
+            }
+            NoteSelectionStrategy::MinimizeChange => select_smallest_notes_first(
+                stream.collect().await,
+                requested_amount,
+                fee_per_note_input,
+            )?,
+            NoteSelectionStrategy::PreferExactDenominations => {
+                select_notes_from_stream(stream, requested_amount, fee_per_note_input).await?
+            }
+        };
+
+        Ok(notes)
+    }
+}
+
+/// Selects the smallest possible number of notes, filling one note at a time
+/// with the smallest note that still allows the remaining slots to cover the
+/// pending amount
+fn select_fewest_notes<Note>(
+    mut notes: Vec<(Amount, Note)>,
+    requested_amount: Amount,
+    fee_per_note_input: Amount,
+) -> Result<TieredMulti<Note>, InsufficientBalanceError> {
+    notes.retain(|(amount, _)| *amount > fee_per_note_input);
+    notes.sort_by(|(a, _), (b, _)| b.cmp(a));
+    let net_amount = |amount: Amount| amount - fee_per_note_input;
+
+    let mut covered = Amount::ZERO;
+    let mut num_notes = 0;
+    for (amount, _) in &notes {
+        if covered >= requested_amount {
+            break;
+        }
+        covered += net_amount(*amount);
+        num_notes += 1;
+    }
+
+    if covered < requested_amount {
+        return Err(InsufficientBalanceError {
+            requested_amount,
+            total_amount: covered,
+        });
+    }
+
+    let mut selected = vec![];
+    let mut pending_amount = requested_amount;
+    for remaining_slots in (0..num_notes).rev() {
+        // The most the other slots can contribute is the value of the largest
+        // `remaining_slots` notes, excluding the candidate itself
+        let largest: Vec<Amount> = notes
+            .iter()
+            .take(remaining_slots + 1)
+            .map(|(amount, _)| net_amount(*amount))
+            .collect();
+        let largest_sum: Amount = largest.iter().copied().sum();
+
+        let idx = (0..notes.len())
+            .rev()
+            .find(|&idx| {
+                let candidate = net_amount(notes[idx].0);
+                let rest = if idx < largest.len() {
+                    largest_sum - candidate
+                } else {
+                    largest_sum - largest[remaining_slots]
+                };
+                candidate + rest >= pending_amount
+            })
+            .expect("The largest remaining notes always cover the pending amount");
+
+        let (amount, note) = notes.remove(idx);
+        pending_amount = pending_amount.saturating_sub(net_amount(amount));
+        selected.push((amount, note));
+    }
+
+    Ok(selected.into_iter().collect())
+}
+
+/// Selects notes starting from the smallest until the requested amount is
+/// covered, then drops every note, largest first, that is not needed anymore
+fn select_smallest_notes_first<Note>(
+    mut notes: Vec<(Amount, Note)>,
+    requested_amount: Amount,
+    fee_per_note_input: Amount,
+) -> Result<TieredMulti<Note>, InsufficientBalanceError> {
+    notes.retain(|(amount, _)| *amount > fee_per_note_input);
+    notes.sort_by(|(a, _), (b, _)| a.cmp(b));
+    let net_amount = |amount: Amount| amount - fee_per_note_input;
+
+    let mut covered = Amount::ZERO;
+    let mut num_notes = 0;
+    for (amount, _) in &notes {
+        if covered >= requested_amount {
+            break;
+        }
+        covered += net_amount(*amount);
+        num_notes += 1;
+    }
+
+    if covered < requested_amount {
+        return Err(InsufficientBalanceError {
+            requested_amount,
+            total_amount: covered,
+        });
+    }
+
+    notes.truncate(num_notes);
+    for idx in (0..notes.len()).rev() {
+        let note_amount = net_amount(notes[idx].0);
+        if covered - note_amount >= requested_amount {
+            covered -= note_amount;
+            notes.remove(idx);
+        }
+    }
+
+    Ok(notes.into_iter().collect())
+}
+
 // We are using a greedy algorithm to select notes. We start with the largest
 // then proceed to the lowest tiers/denominations.
 // But there is a catch: we don't know if there are enough notes in the lowest
@@ -2114,22 +2493,66 @@ pub fn represent_amount<K>(
     current_denominations: &TieredCounts,
     tiers: &Tiered<K>,
     denomination_sets: u16,
+) -> TieredCounts {
+    represent_amount_with_policy(
+        amount,
+        current_denominations,
+        tiers,
+        &NoteSplitPolicy {
+            denomination_sets,
+            ..NoteSplitPolicy::default()
+        },
+    )
+}
+
+/// Like [`represent_amount`], but also respects the per-denomination cap and
+/// the target total note count of the given [`NoteSplitPolicy`]
+pub fn represent_amount_with_policy<K>(
+    amount: Amount,
+    current_denominations: &TieredCounts,
+    tiers: &Tiered<K>,
+    policy: &NoteSplitPolicy,
 ) -> TieredCounts {
     let mut remaining_amount = amount;
     let mut denominations = TieredCounts::default();
 
-    // try to hit the target `denomination_sets`
-    for tier in tiers.tiers() {
+    let denomination_sets = match policy.target_total_notes {
+        Some(target) if target <= current_denominations.count_items() => 0,
+        _ => policy.denomination_sets,
+    };
+
+    // try to hit the target `denomination_sets`, leaving room for the notes the
+    // greedy algorithm below may still add to each tier
+    let mut tiers_iter = tiers.tiers().peekable();
+    while let Some(tier) = tiers_iter.next() {
+        let greedy_notes = tiers_iter
+            .peek()
+            .map_or(0, |next_tier| (next_tier.msats - 1) / tier.msats);
+        let max_notes = u64::from(policy.max_notes_per_denomination).saturating_sub(greedy_notes);
         let notes = current_denominations.get(*tier);
         let missing_notes = u64::from(denomination_sets).saturating_sub(notes as u64);
         let possible_notes = remaining_amount / *tier;
 
-        let add_notes = min(possible_notes, missing_notes);
+        let add_notes = min(min(possible_notes, missing_notes), max_notes);
         denominations.inc(*tier, add_notes as usize);
         remaining_amount -= *tier * add_notes;
     }
 
-    // if there is a remaining amount, add denominations with a greedy algorithm
+    // if there is a remaining amount, add denominations with a greedy algorithm,
+    // spilling over to lower denominations instead of exceeding the cap
+    let max_tier = tiers.tiers().next_back();
+    for tier in tiers.tiers().rev() {
+        let mut res = remaining_amount / *tier;
+        if Some(tier) != max_tier {
+            let room = u64::from(policy.max_notes_per_denomination)
+                .saturating_sub(denominations.get(*tier) as u64);
+            res = min(res, room);
+        }
+        remaining_amount -= *tier * res;
+        denominations.inc(*tier, res as usize);
+    }
+
+    // amounts that can't be represented within the cap exceed it
     for tier in tiers.tiers().rev() {
         let res = remaining_amount / *tier;
         remaining_amount %= *tier;
@@ -2166,8 +2589,10 @@ mod tests {
     use tbs::Signature;
 
     use crate::{
-        represent_amount, select_notes_from_stream, MintOperationMetaVariant, OOBNoteV2, OOBNotes,
-        OOBNotesPart, OOBNotesV2, SpendableNote, SpendableNoteUndecoded,
+        represent_amount, represent_amount_with_policy, select_notes_from_stream,
+        InsufficientBalanceError, MintOperationMetaVariant, NoteSelectionStrategy, NoteSplitPolicy,
+        NotesSelector, OOBNoteV2, OOBNotes, OOBNotesPart, OOBNotesV2, SpendableNote,
+        SpendableNoteUndecoded,
     };
 
     #[test]
@@ -2204,6 +2629,74 @@ mod tests {
         );
     }
 
+    #[test]
+    fn represent_amount_respects_note_split_policy() {
+        let tiers = Tiered::gen_denominations(2, Amount::from_sats(1_000_000));
+        let max_tier = *tiers.max_tier();
+        let amount = Amount::from_sats(987_654);
+
+        let policy = NoteSplitPolicy {
+            denomination_sets: 10,
+            max_notes_per_denomination: 4,
+            target_total_notes: None,
+        };
+        let denominations =
+            represent_amount_with_policy(amount, &TieredCounts::default(), &tiers, &policy);
+        assert_eq!(denominations.total_amount(), amount);
+        assert!(denominations
+            .iter()
+            .all(|(tier, count)| tier == max_tier || count <= 4));
+        let uncapped = represent_amount(amount, &TieredCounts::default(), &tiers, 10);
+        assert!(denominations.count_items() < uncapped.count_items());
+
+        // once the target is reached only the minimal number of notes is issued
+        let held = represent_amount(amount, &TieredCounts::default(), &tiers, 2);
+        let policy = NoteSplitPolicy {
+            target_total_notes: Some(held.count_items()),
+            ..NoteSplitPolicy::default()
+        };
+        assert_eq!(
+            represent_amount_with_policy(amount, &held, &tiers, &policy),
+            represent_amount(amount, &TieredCounts::default(), &tiers, 0)
+        );
+    }
+
+    #[test]
+    fn represent_amount_caps_greedy_notes() {
+        let tiers = [1, 2, 3, 10]
+            .into_iter()
+            .map(|msats| (Amount::from_msats(msats), ()))
+            .collect::<Tiered<()>>();
+        let policy = NoteSplitPolicy {
+            denomination_sets: 0,
+            max_notes_per_denomination: 1,
+            target_total_notes: None,
+        };
+
+        // the greedy algorithm alone would use two notes of 3 msat
+        assert_eq!(
+            represent_amount_with_policy(
+                Amount::from_msats(16),
+                &TieredCounts::default(),
+                &tiers,
+                &policy
+            ),
+            [1, 2, 3, 10]
+                .into_iter()
+                .map(|msats| (Amount::from_msats(msats), 1))
+                .collect::<TieredCounts>()
+        );
+
+        // amounts that can't be represented within the cap still are
+        let denominations = represent_amount_with_policy(
+            Amount::from_msats(19),
+            &TieredCounts::default(),
+            &tiers,
+            &policy,
+        );
+        assert_eq!(denominations.total_amount(), Amount::from_msats(19));
+    }
+
     #[test_log::test(tokio::test)]
     async fn select_notes_avg_test() {
         let max_amount = Amount::from_sats(1_000_000);
@@ -2286,6 +2779,88 @@ mod tests {
         assert_eq!(error.total_amount, Amount::from_sats(10));
     }
 
+    #[test_log::test(tokio::test)]
+    async fn note_selection_strategies_select_documented_notes() {
+        let f = || {
+            reverse_sorted_note_stream(vec![
+                (Amount::from_sats(1), 1),
+                (Amount::from_sats(2), 2),
+                (Amount::from_sats(4), 1),
+                (Amount::from_sats(8), 1),
+            ])
+        };
+        let select = |strategy: NoteSelectionStrategy, fee: Amount| async move {
+            strategy
+                .select_notes(f(), Amount::from_sats(5), fee)
+                .await
+                .unwrap()
+        };
+
+        assert_eq!(
+            select(
+                NoteSelectionStrategy::PreferExactDenominations,
+                Amount::ZERO
+            )
+            .await,
+            notes(vec![(Amount::from_sats(1), 1), (Amount::from_sats(4), 1)])
+        );
+        assert_eq!(
+            select(NoteSelectionStrategy::MinimizeNotes, Amount::ZERO).await,
+            notes(vec![(Amount::from_sats(8), 1)])
+        );
+        assert_eq!(
+            select(NoteSelectionStrategy::MinimizeChange, Amount::ZERO).await,
+            notes(vec![(Amount::from_sats(1), 1), (Amount::from_sats(2), 2)])
+        );
+
+        // With a fee of 1 sat per input the 1 sat note is worthless and every
+        // other note is worth 1 sat less
+        assert_eq!(
+            select(NoteSelectionStrategy::MinimizeNotes, Amount::from_sats(1)).await,
+            notes(vec![(Amount::from_sats(8), 1)])
+        );
+        assert_eq!(
+            select(NoteSelectionStrategy::MinimizeChange, Amount::from_sats(1)).await,
+            notes(vec![(Amount::from_sats(2), 2), (Amount::from_sats(4), 1)])
+        );
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn note_selection_strategies_prefer_smallest_sufficient_notes() {
+        let f = || {
+            reverse_sorted_note_stream(vec![
+                (Amount::from_sats(4), 2),
+                (Amount::from_sats(8), 1),
+                (Amount::from_sats(16), 1),
+            ])
+        };
+
+        // Two notes are needed either way, but 16 + 4 leaves less change than
+        // the two largest notes
+        assert_eq!(
+            NoteSelectionStrategy::MinimizeNotes
+                .select_notes(f(), Amount::from_sats(17), Amount::ZERO)
+                .await
+                .unwrap(),
+            notes(vec![(Amount::from_sats(4), 1), (Amount::from_sats(16), 1)])
+        );
+        assert_eq!(
+            NoteSelectionStrategy::MinimizeChange
+                .select_notes(f(), Amount::from_sats(17), Amount::ZERO)
+                .await
+                .unwrap(),
+            notes(vec![(Amount::from_sats(4), 1), (Amount::from_sats(16), 1)])
+        );
+
+        let error = NoteSelectionStrategy::MinimizeChange
+            .select_notes(f(), Amount::from_sats(33), Amount::ZERO)
+            .await
+            .unwrap_err()
+            .downcast::<InsufficientBalanceError>()
+            .unwrap();
+        assert_eq!(error.total_amount, Amount::from_sats(32));
+    }
+
     fn reverse_sorted_note_stream(
         notes: Vec<(Amount, usize)>,
     ) -> impl futures::Stream<Item = (Amount, String)> {
@@ -1,3 +1,4 @@
 pub const AWAIT_OUTPUT_OUTCOME_ENDPOINT: &str = "await_output_outcome";
 pub const BACKUP_ENDPOINT: &str = "backup";
 pub const RECOVER_ENDPOINT: &str = "recover";
+pub const NOTES_SPENT_ENDPOINT: &str = "notes_spent";
@@ -35,11 +35,13 @@ use fedimint_mint_common::config::{
     MintClientConfig, MintConfig, MintConfigConsensus, MintConfigLocal, MintConfigPrivate,
     MintGenParams,
 };
-use fedimint_mint_common::endpoint_constants::{BACKUP_ENDPOINT, RECOVER_ENDPOINT};
+use fedimint_mint_common::endpoint_constants::{
+    BACKUP_ENDPOINT, NOTES_SPENT_ENDPOINT, RECOVER_ENDPOINT,
+};
 pub use fedimint_mint_common::{BackupRequest, SignedBackupRequest};
 use fedimint_mint_common::{
     MintCommonInit, MintConsensusItem, MintInput, MintInputError, MintModuleTypes, MintOutput,
-    MintOutputError, MintOutputOutcome, DEFAULT_MAX_NOTES_PER_DENOMINATION,
+    MintOutputError, MintOutputOutcome, Nonce, DEFAULT_MAX_NOTES_PER_DENOMINATION,
     MODULE_CONSENSUS_VERSION,
 };
 use fedimint_server::config::distributedgen::{evaluate_polynomial_g2, scalar, PeerHandleOps};
@@ -516,6 +518,14 @@ impl ServerModule for Mint {
                         .handle_recover_request(&mut context.dbtx().into_nc(), id).await)
                 }
             },
+            api_endpoint! {
+                NOTES_SPENT_ENDPOINT,
+                ApiVersion::new(0, 0),
+                async |module: &Mint, context, nonces: Vec<Nonce>| -> Vec<bool> {
+                    Ok(module
+                        .handle_notes_spent_request(&mut context.dbtx().into_nc(), nonces).await)
+                }
+            },
         ]
     }
 }
@@ -558,6 +568,19 @@ impl Mint {
     ) -> Option<ECashUserBackupSnapshot> {
         dbtx.get_value(&EcashBackupKey(id)).await
     }
+
+    /// Returns for every nonce whether the corresponding note was spent already
+    async fn handle_notes_spent_request(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        nonces: Vec<Nonce>,
+    ) -> Vec<bool> {
+        let mut spent = Vec::with_capacity(nonces.len());
+        for nonce in nonces {
+            spent.push(dbtx.get_value(&NonceKey(nonce)).await.is_some());
+        }
+        spent
+    }
 }
 
 fn calculate_mint_issued_ecash_metrics(
@@ -1,23 +1,43 @@
+use std::collections::BTreeMap;
 use std::io::Cursor;
+use std::sync::Arc;
 use std::time::Duration;
 
-use fedimint_client::backup::{ClientBackup, Metadata};
+use fedimint_client::backup::{ClientBackup, Metadata, MAX_CHUNKED_BACKUP_SIZE_BYTES};
+use fedimint_client::transaction::{ClientOutput, TransactionBuilder};
+use fedimint_client::Client;
 use fedimint_core::config::EmptyGenParams;
+use fedimint_core::core::backup::BACKUP_REQUEST_MAX_PAYLOAD_SIZE_BYTES;
+use fedimint_core::core::{IntoDynInstance, OperationId};
+use fedimint_core::db::mem_impl::MemDatabase;
+use fedimint_core::encoding::Encodable;
+use fedimint_core::module::ModuleConsensusVersion;
 use fedimint_core::task::sleep_in_test;
 use fedimint_core::util::NextOrPending;
-use fedimint_core::{sats, Amount};
+use fedimint_core::{sats, secp256k1, Amount};
+use fedimint_dummy_client::states::DummyStateMachine;
 use fedimint_dummy_client::{DummyClientInit, DummyClientModule};
 use fedimint_dummy_common::config::DummyGenParams;
+use fedimint_dummy_common::DummyOutput;
 use fedimint_dummy_server::DummyInit;
+use fedimint_ln_client::LightningClientInit;
+use fedimint_ln_common::config::LightningGenParams;
+use fedimint_ln_server::LightningInit;
 use fedimint_logging::LOG_TEST;
 use fedimint_mint_client::{
-    MintClientInit, MintClientModule, OOBNotes, ReissueExternalNotesState, SpendOOBState,
+    EcashError, MintClientInit, MintClientModule, MintOperationMeta, MintOperationMetaVariant,
+    OOBNotes, ReissueExternalNotesState, SpendOOBState,
 };
 use fedimint_mint_common::config::{FeeConsensus, MintGenParams, MintGenParamsConsensus};
+use fedimint_mint_common::{MintInput, MintInputV0, Nonce, Note};
 use fedimint_mint_server::MintInit;
+use fedimint_testing::encoding::assert_downgrade_decoding;
 use fedimint_testing::fixtures::{Fixtures, TIMEOUT};
 use futures::StreamExt;
+use rand::rngs::OsRng;
 use serde::{Deserialize, Serialize};
+use tbs::Signature;
+use threshold_crypto::G1Affine;
 use tracing::{debug, info};
 
 const EXPECTED_MAXIMUM_FEE: Amount = Amount::from_sats(50);
@@ -41,6 +61,36 @@ fn fixtures() -> Fixtures {
     fixtures.with_module(DummyClientInit, DummyInit, DummyGenParams::default())
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn mint_is_primary_module_candidate() -> anyhow::Result<()> {
+    let fixtures = fixtures();
+    let ln_params = LightningGenParams::regtest(fixtures.bitcoin_server());
+    let fixtures = fixtures.with_module(LightningClientInit::default(), LightningInit, ln_params);
+    let fed = fixtures.new_default_fed().await;
+    let config = fed.new_client().await.get_config().clone();
+    let (mint_instance_id, _) = config
+        .modules
+        .iter()
+        .find(|(_, module_config)| module_config.is_kind(&fedimint_mint_common::KIND))
+        .expect("Federation has a mint module");
+
+    // The dummy module could be primary as well, but has no registered init, the
+    // lightning module has one but can't be primary
+    let mut client_builder = Client::builder(MemDatabase::new().into());
+    client_builder.with_module(MintClientInit);
+    client_builder.with_module(LightningClientInit::default());
+    let candidates = client_builder.primary_module_candidates(&config);
+    assert!(!candidates
+        .iter()
+        .any(|(_, kind)| *kind == fedimint_ln_common::KIND));
+    assert_eq!(
+        candidates,
+        vec![(*mint_instance_id, fedimint_mint_common::KIND)]
+    );
+
+    Ok(())
+}
+
 #[derive(Serialize, Deserialize)]
 struct BackupTestMetadata {
     custom_key: String,
@@ -84,6 +134,133 @@ async fn sends_ecash_out_of_band() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn operation_log_records_fees_paid() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let (client1, client2) = fed.two_clients().await;
+    let client1_dummy_module = client1.get_first_module::<DummyClientModule>();
+    let (op, outpoint) = client1_dummy_module.print_money(sats(1000)).await?;
+    client1.await_primary_module_output(op, outpoint).await?;
+
+    let client1_mint = client1.get_first_module::<MintClientModule>();
+    let client2_mint = client2.get_first_module::<MintClientModule>();
+    let (_, notes) = client1_mint
+        .spend_notes(sats(750), TIMEOUT, false, ())
+        .await?;
+    let num_notes = notes.notes().count_items() as u64;
+
+    let op = client2_mint.reissue_external_notes(notes, ()).await?;
+    let mut sub = client2_mint
+        .subscribe_reissue_external_notes(op)
+        .await?
+        .into_stream();
+    assert_eq!(sub.ok().await?, ReissueExternalNotesState::Created);
+    assert_eq!(sub.ok().await?, ReissueExternalNotesState::Issuing);
+    assert_eq!(sub.ok().await?, ReissueExternalNotesState::Done);
+
+    // Every spent note costs `note_spend_abs`, issuing new ones is free
+    let expected_fee = sats(1) * num_notes;
+    let (_, entry) = client2
+        .operation_log()
+        .list_operations(1, None)
+        .await
+        .into_iter()
+        .next()
+        .expect("Reissuance was logged");
+    assert_eq!(entry.fees_paid(), expected_fee);
+    assert_eq!(client2.get_balance().await, sats(750) - expected_fee);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reissues_ecash_in_batches() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let (client1, client2) = fed.two_clients().await;
+    let client1_dummy_module = client1.get_first_module::<DummyClientModule>();
+    let (op, outpoint) = client1_dummy_module.print_money(sats(1000)).await?;
+    client1.await_primary_module_output(op, outpoint).await?;
+
+    let client1_mint = client1.get_first_module::<MintClientModule>();
+    let client2_mint = client2.get_first_module::<MintClientModule>();
+    let (_, notes) = client1_mint
+        .spend_notes(sats(750), TIMEOUT, false, ())
+        .await?;
+
+    let batch_size = 2;
+    let num_notes = notes.notes().count_items();
+    assert!(
+        num_notes > batch_size,
+        "Notes have to span multiple batches"
+    );
+
+    let op = client2_mint
+        .reissue_notes_batched(notes, batch_size, ())
+        .await?;
+    let meta = client2
+        .operation_log()
+        .get_operation(op)
+        .await
+        .expect("Operation exists")
+        .meta::<MintOperationMeta>();
+    let MintOperationMetaVariant::BatchReissuance { transactions } = meta.variant else {
+        panic!("Operation is not a batch reissuance");
+    };
+    assert_eq!(transactions.len(), num_notes.div_ceil(batch_size));
+
+    let mut sub = client2_mint
+        .subscribe_reissue_external_notes(op)
+        .await?
+        .into_stream();
+    assert_eq!(sub.ok().await?, ReissueExternalNotesState::Created);
+    assert_eq!(sub.ok().await?, ReissueExternalNotesState::Issuing);
+    assert_eq!(sub.ok().await?, ReissueExternalNotesState::Done);
+
+    assert!(client2.get_balance().await >= sats(750) - EXPECTED_MAXIMUM_FEE);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn error_reissuing_spent_ecash() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let (client1, client2) = fed.two_clients().await;
+    let client1_dummy_module = client1.get_first_module::<DummyClientModule>();
+    let (op, outpoint) = client1_dummy_module.print_money(sats(1000)).await?;
+    client1.await_primary_module_output(op, outpoint).await?;
+
+    let client1_mint = client1.get_first_module::<MintClientModule>();
+    let client2_mint = client2.get_first_module::<MintClientModule>();
+    let (_, notes) = client1_mint
+        .spend_notes(sats(750), TIMEOUT, false, ())
+        .await?;
+
+    let op = client2_mint
+        .reissue_external_notes(notes.clone(), ())
+        .await?;
+    let mut sub = client2_mint
+        .subscribe_reissue_external_notes(op)
+        .await?
+        .into_stream();
+    assert_eq!(sub.ok().await?, ReissueExternalNotesState::Created);
+    assert_eq!(sub.ok().await?, ReissueExternalNotesState::Issuing);
+    assert_eq!(sub.ok().await?, ReissueExternalNotesState::Done);
+
+    // Importing the same notes into another client has to fail right away
+    let client3 = fed.new_client().await;
+    let client3_mint = client3.get_first_module::<MintClientModule>();
+    let err = client3_mint
+        .reissue_external_notes(notes.clone(), ())
+        .await
+        .expect_err("Notes were already spent");
+    assert!(matches!(
+        err.downcast_ref::<EcashError>(),
+        Some(EcashError::AlreadySpent { spent, total })
+            if spent == total && *total == notes.notes().count_items()
+    ));
+    assert!(client3.operation_log().get_operation(op).await.is_none());
+    assert_eq!(client3.get_balance().await, Amount::ZERO);
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread")]
 #[ignore] // TODO: flaky https://github.com/fedimint/fedimint/issues/4508
 async fn sends_ecash_oob_highly_parallel() -> anyhow::Result<()> {
@@ -174,6 +351,105 @@ async fn sends_ecash_oob_highly_parallel() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn notes_by_denomination_tracks_held_notes() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client = fed.new_client().await;
+    let client_mint = client.get_first_module::<MintClientModule>();
+    assert!(client_mint.notes_by_denomination().await.is_empty());
+
+    let client_dummy_module = client.get_first_module::<DummyClientModule>();
+    let (op, outpoint) = client_dummy_module.print_money(sats(1000)).await?;
+    client.await_primary_module_output(op, outpoint).await?;
+
+    // The default policy issues two notes of each denomination, the rest is
+    // represented with as few notes as possible
+    let mut expected = [
+        (1, 2),
+        (2, 3),
+        (4, 2),
+        (8, 2),
+        (16, 2),
+        (32, 2),
+        (64, 3),
+        (128, 2),
+        (256, 2),
+        (512, 3),
+        (1024, 2),
+        (2048, 2),
+        (4096, 2),
+        (8192, 2),
+        (16384, 3),
+        (32768, 2),
+        (65536, 3),
+        (131_072, 3),
+        (262_144, 1),
+    ]
+    .into_iter()
+    .map(|(msats, count)| (Amount::from_msats(msats), count))
+    .collect::<BTreeMap<_, _>>();
+    assert_eq!(client_mint.notes_by_denomination().await, expected);
+
+    let (_, oob_notes) = client_mint
+        .spend_notes(sats(750), TIMEOUT, false, ())
+        .await?;
+    for (denomination, count) in oob_notes.notes().summary().iter() {
+        let held = expected
+            .get_mut(&denomination)
+            .expect("Only held notes are spent");
+        *held -= count;
+    }
+    expected.retain(|_, count| *count != 0);
+    assert_eq!(client_mint.notes_by_denomination().await, expected);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn transaction_dry_run_predicts_fee() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client = fed.new_client().await;
+    let client_dummy_module = client.get_first_module::<DummyClientModule>();
+    let (op, outpoint) = client_dummy_module.print_money(sats(1000)).await?;
+    client.await_primary_module_output(op, outpoint).await?;
+
+    let output = ClientOutput {
+        output: DummyOutput {
+            amount: sats(250),
+            account: client_dummy_module.account(),
+        },
+        amount: sats(250),
+        state_machines: Arc::new(|_, _| Vec::<DummyStateMachine>::new()),
+    };
+    let tx_builder = TransactionBuilder::new().with_output(output.into_dyn(client_dummy_module.id));
+
+    let balance_before = client.get_balance().await;
+    let dry_run = client.build_transaction_dry_run(tx_builder.clone()).await?;
+    assert!(!dry_run.funding_inputs.is_empty());
+    assert!(dry_run.fee > Amount::ZERO);
+    // Nothing was spent by the dry run
+    assert_eq!(client.get_balance().await, balance_before);
+
+    let operation_id = OperationId::new_random();
+    let (_, change) = client
+        .finalize_and_submit_transaction(operation_id, "dry-run-test", |_, _| (), tx_builder)
+        .await?;
+    assert_eq!(
+        change.len() as u64,
+        dry_run.change_outputs.end - dry_run.change_outputs.start
+    );
+    client
+        .await_primary_module_outputs(operation_id, change)
+        .await?;
+
+    assert_eq!(
+        balance_before - client.get_balance().await,
+        sats(250) + dry_run.fee
+    );
+
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn backup_encode_decode_roundtrip() -> anyhow::Result<()> {
     // Print notes for client
@@ -225,6 +501,38 @@ async fn ecash_backup_can_recover_metadata() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn ecash_backup_exceeding_request_limit_is_chunked() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client = fed.new_client().await;
+    let client_dummy_module = client.get_first_module::<DummyClientModule>();
+    let (op, outpoint) = client_dummy_module.print_money(sats(1000)).await?;
+    client.await_primary_module_output(op, outpoint).await?;
+
+    // Too large for a single backup request, so it's uploaded in three segments
+    let metadata = Metadata::from_raw(
+        (0..2 * BACKUP_REQUEST_MAX_PAYLOAD_SIZE_BYTES)
+            .map(|i| i as u8)
+            .collect(),
+    );
+
+    client.backup_to_federation(metadata.clone()).await?;
+    let fetched_backup = client
+        .download_backup_from_federation()
+        .await?
+        .expect("could not download backup");
+    assert_eq!(fetched_backup.metadata, metadata);
+
+    // Backups exceeding the chunked backup limit are refused
+    let error = client
+        .backup_to_federation(Metadata::from_raw(vec![0; MAX_CHUNKED_BACKUP_SIZE_BYTES]))
+        .await
+        .expect_err("Backup is too large");
+    assert!(error.to_string().contains("exceeds the maximum"), "{error}");
+
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn sends_ecash_out_of_band_cancel() -> anyhow::Result<()> {
     // Print notes for client1
@@ -307,6 +615,46 @@ async fn error_zero_value_oob_receive() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// A hypothetical future version of [`MintInput`] with an additional variant
+#[derive(Debug, Encodable)]
+enum FutureMintInput {
+    V0(MintInputV0),
+    V1 { amount: Amount, memo: String },
+}
+
+#[test]
+fn future_mint_input_variant_decodes_to_default() {
+    let v0 = ModuleConsensusVersion::new(2, 0);
+    let v1 = ModuleConsensusVersion::new(2, 1);
+
+    let (_, pk) = secp256k1::generate_keypair(&mut OsRng);
+    let input_v0 = MintInputV0 {
+        amount: Amount::from_sats(1),
+        note: Note {
+            nonce: Nonce(pk),
+            signature: Signature(G1Affine::generator()),
+        },
+    };
+    let decoded = assert_downgrade_decoding(
+        &FutureMintInput::V0(input_v0.clone()),
+        v0,
+        v0,
+        |input: &MintInput| input.maybe_v0_ref().is_none(),
+    );
+    assert_eq!(decoded, MintInput::V0(input_v0));
+
+    let decoded = assert_downgrade_decoding(
+        &FutureMintInput::V1 {
+            amount: Amount::from_sats(1),
+            memo: "future".to_owned(),
+        },
+        v1,
+        v0,
+        |input: &MintInput| input.maybe_v0_ref().is_none(),
+    );
+    assert!(matches!(decoded, MintInput::Default { variant: 1, .. }));
+}
+
 #[cfg(test)]
 mod fedimint_migration_tests {
     use std::collections::BTreeMap;
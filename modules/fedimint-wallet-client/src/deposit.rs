@@ -16,9 +16,8 @@ use fedimint_wallet_common::WalletInput;
 use secp256k1::KeyPair;
 use tracing::{debug, instrument, trace, warn};
 
-use crate::api::WalletFederationApi;
 use crate::pegin_monitor::filter_onchain_deposit_outputs;
-use crate::{WalletClientContext, WalletClientStates};
+use crate::{WalletClientContext, WalletClientStates, CONSENSUS_BLOCK_COUNT_MAX_AGE};
 
 const TRANSACTION_STATUS_FETCH_INTERVAL: Duration = Duration::from_secs(1);
 
@@ -194,8 +193,7 @@ async fn await_btc_transaction_confirmed(
         // TODO: make everything subscriptions
         // Wait for confirmation
         let consensus_block_count = match global_context
-            .module_api()
-            .fetch_consensus_block_count()
+            .consensus_block_count_cached(CONSENSUS_BLOCK_COUNT_MAX_AGE)
             .await
         {
             Ok(consensus_block_count) => consensus_block_count,
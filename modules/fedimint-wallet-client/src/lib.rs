@@ -19,7 +19,7 @@ mod withdraw;
 use std::collections::BTreeMap;
 use std::future;
 use std::sync::Arc;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 use anyhow::{anyhow, bail, ensure, Context as AnyhowContext};
 use async_stream::stream;
@@ -73,6 +73,9 @@ use crate::withdraw::{CreatedWithdrawState, WithdrawStateMachine, WithdrawStates
 
 const WALLET_TWEAK_CHILD_ID: ChildId = ChildId(0);
 
+/// How old the consensus block count used to check peg-in confirmations may be
+const CONSENSUS_BLOCK_COUNT_MAX_AGE: Duration = Duration::from_secs(10);
+
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct BitcoinTransactionData {
     /// The bitcoin transaction is saved as soon as we see it so the transaction
@@ -341,7 +344,6 @@ impl ClientModule for WalletClientModule {
             let client_ctx = self.client_ctx.clone();
             let db = self.db.clone();
             let btc_rpc = self.rpc.clone();
-            let module_api = self.module_api.clone();
             let data = self.data.clone();
             let pegin_claimed_sender = self.pegin_claimed_sender.clone();
             let pegin_monitor_wakeup_receiver = self.pegin_monitor_wakeup_receiver.clone();
@@ -349,7 +351,6 @@ impl ClientModule for WalletClientModule {
                 client_ctx,
                 db,
                 btc_rpc,
-                module_api,
                 data,
                 pegin_claimed_sender,
                 pegin_monitor_wakeup_receiver,
@@ -364,6 +365,14 @@ impl ClientModule for WalletClientModule {
     fn output_fee(&self, _output: &<Self::Common as ModuleCommon>::Output) -> Option<Amount> {
         Some(self.cfg().fee_consensus.peg_out_abs)
     }
+
+    fn provides_consensus_block_count(&self) -> bool {
+        true
+    }
+
+    async fn fetch_consensus_block_count(&self) -> anyhow::Result<u64> {
+        Ok(self.module_api.fetch_consensus_block_count().await?)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -456,9 +465,15 @@ impl WalletClientModule {
     ) -> anyhow::Result<ClientOutput<WalletOutput, WalletClientStates>> {
         check_address(&address, self.cfg().network)?;
 
+        // Converting into a federation amount panics above the bitcoin supply
+        let total_amount = amount
+            .checked_add(fees.amount())
+            .filter(|total_amount| *total_amount <= bitcoin::Amount::MAX_MONEY)
+            .context("Withdrawal amount plus fees exceeds the bitcoin supply")?;
+
         let output = WalletOutput::new_v0_peg_out(address, amount, fees);
 
-        let amount = output.maybe_v0_ref().expect("v0 output").amount().into();
+        let amount = total_amount.into();
 
         let sm_gen = move |txid, out_idx| {
             vec![WalletClientStates::Withdraw(WithdrawStateMachine {
@@ -3,7 +3,6 @@ use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 
 use bitcoin::ScriptBuf;
-use fedimint_api_client::api::DynModuleApi;
 use fedimint_bitcoind::DynBitcoindRpc;
 use fedimint_client::module::{ClientContext, ClientDbTxContext};
 use fedimint_client::transaction::ClientInput;
@@ -21,12 +20,13 @@ use secp256k1::KeyPair;
 use tokio::sync::watch;
 use tracing::{debug, instrument, trace, warn};
 
-use crate::api::WalletFederationApi as _;
 use crate::client_db::{
     ClaimedPegInData, ClaimedPegInKey, PegInTweakIndexData, PegInTweakIndexKey,
     PegInTweakIndexPrefix, TweakIdx,
 };
-use crate::{WalletClientModule, WalletClientModuleData, WalletClientStates};
+use crate::{
+    WalletClientModule, WalletClientModuleData, WalletClientStates, CONSENSUS_BLOCK_COUNT_MAX_AGE,
+};
 
 /// A helper struct meant to combined data from all addresses/records
 /// into a single struct with all actionable data.
@@ -88,7 +88,6 @@ pub(crate) async fn run_peg_in_monitor(
     client_ctx: ClientContext<WalletClientModule>,
     db: Database,
     btc_rpc: DynBitcoindRpc,
-    module_api: DynModuleApi,
     data: WalletClientModuleData,
     pegin_claimed_sender: watch::Sender<()>,
     mut wakeup_receiver: watch::Receiver<()>,
@@ -100,15 +99,8 @@ pub(crate) async fn run_peg_in_monitor(
     };
 
     loop {
-        if let Err(err) = check_for_deposits(
-            &db,
-            &data,
-            &btc_rpc,
-            &module_api,
-            &client_ctx,
-            &pegin_claimed_sender,
-        )
-        .await
+        if let Err(err) =
+            check_for_deposits(&db, &data, &btc_rpc, &client_ctx, &pegin_claimed_sender).await
         {
             warn!(%err, "Error checking for deposits");
             continue;
@@ -142,7 +134,6 @@ async fn check_for_deposits(
     db: &Database,
     data: &WalletClientModuleData,
     btc_rpc: &DynBitcoindRpc,
-    module_api: &DynModuleApi,
     client_ctx: &ClientContext<WalletClientModule>,
     pengin_claimed_sender: &watch::Sender<()>,
 ) -> Result<(), anyhow::Error> {
@@ -153,7 +144,6 @@ async fn check_for_deposits(
             data,
             due_key,
             btc_rpc,
-            module_api,
             db,
             client_ctx,
             due_val,
@@ -165,19 +155,17 @@ async fn check_for_deposits(
     Ok(())
 }
 
-#[allow(clippy::too_many_arguments)]
 async fn check_and_claim_idx_pegins(
     data: &WalletClientModuleData,
     due_key: PegInTweakIndexKey,
     btc_rpc: &DynBitcoindRpc,
-    module_api: &DynModuleApi,
     db: &Database,
     client_ctx: &ClientContext<WalletClientModule>,
     due_val: PegInTweakIndexData,
     pengin_claimed_sender: &watch::Sender<()>,
 ) -> Result<(), anyhow::Error> {
     let now = time::now();
-    match check_idx_pegins(data, due_key.0, btc_rpc, module_api, db, client_ctx).await {
+    match check_idx_pegins(data, due_key.0, btc_rpc, db, client_ctx).await {
         Ok(outcomes) => {
             let next_check_time = CheckOutcome::retry_delay_vec(&outcomes, due_val.creation_time)
                 .map(|duration| now + duration);
@@ -315,11 +303,12 @@ async fn check_idx_pegins(
     data: &WalletClientModuleData,
     tweak_idx: TweakIdx,
     btc_rpc: &DynBitcoindRpc,
-    module_rpc: &DynModuleApi,
     db: &Database,
     client_ctx: &ClientContext<WalletClientModule>,
 ) -> Result<Vec<CheckOutcome>, anyhow::Error> {
-    let current_consensus_block_count = module_rpc.fetch_consensus_block_count().await?;
+    let current_consensus_block_count = client_ctx
+        .consensus_block_count_cached(CONSENSUS_BLOCK_COUNT_MAX_AGE)
+        .await?;
     let (script, address, tweak_key, operation_id) = data.derive_peg_in_script(tweak_idx);
     btc_rpc.watch_script_history(&script).await?;
 